@@ -0,0 +1,38 @@
+use std::path::Path;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use ferris_lab::{
+    ferris::Ferris,
+    grid::{END_TILE, START_TILE},
+    rules::Rules,
+    solver::{self, SolveOptions},
+};
+
+fn solve_maps(c: &mut Criterion) {
+    let maps_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/maps");
+    let maps = solver::load_ascii_maps(&maps_dir).expect("failed to load ascii maps");
+    let rules = Rules::default();
+    let options = SolveOptions::default();
+
+    for result in solver::solve_batch(&maps, &rules, &options) {
+        println!(
+            "{}: cost {:?} in {:?}",
+            result.name, result.cost, result.duration
+        );
+    }
+
+    for (name, grid) in maps.iter() {
+        let exits = grid.find_all(END_TILE);
+        let start_pos = match grid.find(START_TILE) {
+            Some(start_pos) if !exits.is_empty() => start_pos,
+            _ => continue,
+        };
+        let start = Ferris::at(start_pos);
+        c.bench_function(&format!("solve {}", name), |b| {
+            b.iter(|| solver::solve_grid(black_box(grid), &rules, &start, &exits, &options))
+        });
+    }
+}
+
+criterion_group!(benches, solve_maps);
+criterion_main!(benches);
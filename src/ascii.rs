@@ -0,0 +1,124 @@
+use bevy::math::UVec2;
+use thiserror::Error;
+
+use crate::grid::{TileGrid, CHECKPOINT_TILE, END_TILE, FLOOR_TILE, START_TILE, WALL_TILE};
+
+// Text representation of a TileGrid, one character per cell, top row first:
+// '#' wall, '.' floor, 'S' start, 'E' end, 'a' 'b' 'c' keys and 'A' 'B' 'C' the matching
+// doors (red, yellow, blue), '+' checkpoint. '@' marks ferris in dumps of a running game
+// and is read back as the start (replacing the 'S' of the map), so a dump can be played on
+// from where ferris was.
+
+#[derive(Debug, Error)]
+pub enum AsciiError {
+    #[error("map is empty")]
+    Empty,
+    #[error("row {row} has {len} cells, expected {expected}")]
+    RaggedRow {
+        row: usize,
+        len: usize,
+        expected: usize,
+    },
+    #[error("unknown character '{c}' at row {row}, column {column}")]
+    UnknownChar { c: char, row: usize, column: usize },
+}
+
+fn char_to_tile(c: char) -> Option<Option<u16>> {
+    let tile = match c {
+        '.' => None,
+        '#' => Some(WALL_TILE),
+        'S' => Some(START_TILE),
+        'E' => Some(END_TILE),
+        '+' => Some(CHECKPOINT_TILE),
+        'A' => Some(2),
+        'B' => Some(3),
+        'C' => Some(4),
+        'a' => Some(5),
+        'b' => Some(6),
+        'c' => Some(7),
+        // hidden (consumed) tiles are floor as far as the map is concerned
+        '_' => Some(FLOOR_TILE),
+        '@' => Some(START_TILE),
+        _ => return None,
+    };
+    Some(tile)
+}
+
+fn tile_to_char(tile: Option<u16>) -> char {
+    match tile {
+        None => '.',
+        Some(FLOOR_TILE) => '_',
+        Some(START_TILE) => 'S',
+        Some(END_TILE) => 'E',
+        Some(CHECKPOINT_TILE) => '+',
+        Some(2) => 'A',
+        Some(3) => 'B',
+        Some(4) => 'C',
+        Some(5) => 'a',
+        Some(6) => 'b',
+        Some(7) => 'c',
+        // every other tile blocks ferris
+        Some(_) => '#',
+    }
+}
+
+// Inverse of tilegrid_from_ascii, one line per row (top row first). If ferris is given
+// his cell is written as '@'.
+pub fn tilegrid_to_ascii(grid: &TileGrid, ferris: Option<UVec2>) -> String {
+    let size = grid.size();
+    let mut ascii = String::with_capacity(((size.x + 1) * size.y) as usize);
+    for y in (0..size.y).rev() {
+        for x in 0..size.x {
+            let pos = UVec2::new(x, y);
+            if ferris == Some(pos) {
+                ascii.push('@');
+            } else {
+                ascii.push(tile_to_char(grid.get(pos)));
+            }
+        }
+        ascii.push('\n');
+    }
+    ascii
+}
+
+pub fn tilegrid_from_ascii(ascii: &str) -> Result<TileGrid, AsciiError> {
+    let rows: Vec<&str> = ascii
+        .lines()
+        .map(|line| line.trim_end())
+        .filter(|line| !line.is_empty())
+        .collect();
+
+    let width = rows.first().ok_or(AsciiError::Empty)?.chars().count();
+    let height = rows.len();
+    let mut grid = TileGrid::new(UVec2::new(width as u32, height as u32));
+    let mut ferris = None;
+
+    for (row, line) in rows.iter().enumerate() {
+        let len = line.chars().count();
+        if len != width {
+            return Err(AsciiError::RaggedRow {
+                row,
+                len,
+                expected: width,
+            });
+        }
+        // the first row is the top of the map, tile positions go bottom-up
+        let y = (height - 1 - row) as u32;
+        for (column, c) in line.chars().enumerate() {
+            let tile = char_to_tile(c).ok_or(AsciiError::UnknownChar { c, row, column })?;
+            let pos = UVec2::new(column as u32, y);
+            if c == '@' {
+                ferris = Some(pos);
+            }
+            grid.set(pos, tile);
+        }
+    }
+    if let Some(ferris) = ferris {
+        for pos in grid.find_all(START_TILE) {
+            if pos != ferris {
+                grid.set(pos, None);
+            }
+        }
+    }
+    Ok(grid)
+}
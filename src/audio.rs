@@ -0,0 +1,89 @@
+use bevy::prelude::*;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SoundCategory {
+    Sfx,
+    Music,
+}
+
+// Central volume settings, every system playing sounds goes through play().
+// Levels are 0.0 - 1.0, the effective volume of a sound is master * category. Note that
+// sounds are not scaled by it (see play), a volume of 0.0 mutes them, anything else
+// plays them at full volume.
+pub struct AudioMixer {
+    pub master: f32,
+    pub sfx: f32,
+    pub music: f32,
+}
+
+impl Default for AudioMixer {
+    fn default() -> Self {
+        AudioMixer {
+            master: 1.0,
+            sfx: 1.0,
+            music: 1.0,
+        }
+    }
+}
+
+impl AudioMixer {
+    pub fn volume(&self, category: SoundCategory) -> f32 {
+        let category = match category {
+            SoundCategory::Sfx => self.sfx,
+            SoundCategory::Music => self.music,
+        };
+        self.master * category
+    }
+
+    // bevy_audio has no volume control yet, so for now this only decides between playing
+    // at full volume and not at all. Returns whether the sound was played.
+    pub fn play(
+        &self,
+        audio: &Audio,
+        source: Handle<AudioSource>,
+        category: SoundCategory,
+    ) -> bool {
+        let audible = self.volume(category) > 0.0;
+        if audible {
+            audio.play(source);
+        }
+        audible
+    }
+}
+
+const VOLUME_STEP: f32 = 0.1;
+
+// - / = master, 9 / 0 sfx, 7 / 8 music
+pub fn mixer_input(keyboard_input: Res<Input<KeyCode>>, mut mixer: ResMut<AudioMixer>) {
+    if !keyboard_input.get_just_pressed().any(|key| {
+        matches!(
+            key,
+            KeyCode::Minus
+                | KeyCode::Equals
+                | KeyCode::Key9
+                | KeyCode::Key0
+                | KeyCode::Key7
+                | KeyCode::Key8
+        )
+    }) {
+        return;
+    }
+    let mixer = &mut *mixer;
+    let adjustments = [
+        (&mut mixer.master, KeyCode::Minus, KeyCode::Equals),
+        (&mut mixer.sfx, KeyCode::Key9, KeyCode::Key0),
+        (&mut mixer.music, KeyCode::Key7, KeyCode::Key8),
+    ];
+    for (level, down, up) in adjustments {
+        if keyboard_input.just_pressed(down) {
+            *level = (*level - VOLUME_STEP).max(0.0);
+        }
+        if keyboard_input.just_pressed(up) {
+            *level = (*level + VOLUME_STEP).min(1.0);
+        }
+    }
+    info!(
+        "volume: master {:.1} sfx {:.1} music {:.1}",
+        mixer.master, mixer.sfx, mixer.music
+    );
+}
@@ -0,0 +1,67 @@
+use bevy::math::{IVec2, UVec2};
+
+use crate::grid::{TileGrid, WALL_TILE};
+
+// Wall variants for maps that are not drawn by hand (ASCII / generated). The tileset has
+// one variant per wall neighborhood ("blob" tileset, 47 tiles) starting at this index,
+// ordered like BLOB_MASKS.
+pub const WALL_AUTOTILE_START: u16 = 32;
+
+// neighbor bits, set if the neighbor is a wall
+pub const N: u8 = 1;
+pub const NE: u8 = 2;
+pub const E: u8 = 4;
+pub const SE: u8 = 8;
+pub const S: u8 = 16;
+pub const SW: u8 = 32;
+pub const W: u8 = 64;
+pub const NW: u8 = 128;
+
+// all masks left after reduce_mask, sorted
+const BLOB_MASKS: [u8; 47] = [
+    0, 1, 4, 5, 7, 16, 17, 20, 21, 23, 28, 29, 31, 64, 65, 68, 69, 71, 80, 81, 84, 85, 87, 92, 93,
+    95, 112, 113, 116, 117, 119, 124, 125, 127, 193, 197, 199, 209, 213, 215, 221, 223, 241, 245,
+    247, 253, 255,
+];
+
+// a diagonal neighbor only changes the look (inner corner) if both walls next to it are
+// there as well
+fn reduce_mask(mut mask: u8) -> u8 {
+    for (diagonal, a, b) in [(NE, N, E), (SE, S, E), (SW, S, W), (NW, N, W)] {
+        if mask & a == 0 || mask & b == 0 {
+            mask &= !diagonal;
+        }
+    }
+    mask
+}
+
+// texture index of the wall variant for a neighborhood mask
+pub fn wall_autotile(mask: u8) -> u16 {
+    let index = BLOB_MASKS
+        .binary_search(&reduce_mask(mask))
+        .expect("reduced masks are always in BLOB_MASKS");
+    WALL_AUTOTILE_START + index as u16
+}
+
+// neighborhood mask of pos. Cells outside of the grid count as walls, so the map border
+// looks closed.
+pub fn wall_mask(grid: &TileGrid, pos: UVec2) -> u8 {
+    let neighbors = [
+        (IVec2::new(0, 1), N),
+        (IVec2::new(1, 1), NE),
+        (IVec2::new(1, 0), E),
+        (IVec2::new(1, -1), SE),
+        (IVec2::new(0, -1), S),
+        (IVec2::new(-1, -1), SW),
+        (IVec2::new(-1, 0), W),
+        (IVec2::new(-1, 1), NW),
+    ];
+    let mut mask = 0;
+    for (offset, bit) in neighbors {
+        let n = pos.as_i32() + offset;
+        if !grid.in_bounds(n) || grid.get(n.as_u32()) == Some(WALL_TILE) {
+            mask |= bit;
+        }
+    }
+    mask
+}
@@ -0,0 +1,65 @@
+use std::{collections::HashMap, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+// best result of a level. Moves and time are tracked separately, so both can come from
+// different runs.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Best {
+    pub moves: u32,
+    pub seconds: f64,
+}
+
+// what a finished run improved, compared to the bests before it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct NewRecords {
+    // no earlier completion of the level
+    pub first: bool,
+    pub moves: bool,
+    pub time: bool,
+}
+
+// Personal bests per level name, stored as JSON.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PersonalBests(pub HashMap<String, Best>);
+
+impl PersonalBests {
+    // a missing file means no bests yet
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(PersonalBests::default());
+        }
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    // Best before this run (None for the first completion) and what the run improved.
+    // The bests are updated accordingly.
+    pub fn record(&mut self, level: &str, run: Best) -> (Option<Best>, NewRecords) {
+        let previous = self.0.get(level).copied();
+        let records = match previous {
+            None => NewRecords {
+                first: true,
+                moves: true,
+                time: true,
+            },
+            Some(best) => NewRecords {
+                first: false,
+                moves: run.moves < best.moves,
+                time: run.seconds < best.seconds,
+            },
+        };
+        let best = self.0.entry(level.to_string()).or_insert(run);
+        if records.moves {
+            best.moves = run.moves;
+        }
+        if records.time {
+            best.seconds = run.seconds;
+        }
+        (previous, records)
+    }
+}
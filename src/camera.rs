@@ -1,4 +1,7 @@
 use bevy::{prelude::*, render::camera::Camera};
+use rand::Rng;
+
+use crate::rng::GameRng;
 
 // A simple camera system for moving and zooming the camera.
 pub fn movement(
@@ -43,3 +46,166 @@ pub fn movement(
         transform.translation += time.delta_seconds() * direction * 500.;
     }
 }
+
+// Screen shake driven by 'trauma' (0.0 - 1.0), which decays over time. The offset is
+// applied on top of wherever the other camera systems put the camera and removed again
+// before they run, so it never feeds back into chasing.
+pub struct CameraShake {
+    pub trauma: f32,
+    // trauma lost per second
+    pub decay: f32,
+    // offset in pixels at full trauma
+    pub max_offset: f32,
+    offset: Vec2,
+}
+
+impl Default for CameraShake {
+    fn default() -> Self {
+        CameraShake {
+            trauma: 0.0,
+            decay: 1.5,
+            max_offset: 8.0,
+            offset: Vec2::ZERO,
+        }
+    }
+}
+
+// raises the trauma of all shaking cameras
+pub struct AddTrauma(pub f32);
+
+pub fn remove_camera_shake(mut query: Query<(&mut Transform, &mut CameraShake)>) {
+    for (mut transform, mut shake) in query.iter_mut() {
+        transform.translation -= shake.offset.extend(0.0);
+        shake.offset = Vec2::ZERO;
+    }
+}
+
+pub fn apply_camera_shake(
+    time: Res<Time>,
+    mut rng: ResMut<GameRng>,
+    mut events: EventReader<AddTrauma>,
+    mut query: Query<(&mut Transform, &mut CameraShake)>,
+) {
+    let added: f32 = events.iter().map(|event| event.0).sum();
+
+    for (mut transform, mut shake) in query.iter_mut() {
+        shake.trauma = (shake.trauma + added - shake.decay * time.delta_seconds()).clamp(0.0, 1.0);
+        // squared trauma feels better than linear
+        let strength = shake.max_offset * shake.trauma * shake.trauma;
+        shake.offset =
+            Vec2::new(rng.0.gen_range(-1.0..=1.0), rng.0.gen_range(-1.0..=1.0)) * strength;
+        transform.translation += shake.offset.extend(0.0);
+    }
+}
+
+// Fill uses the whole window. Letterbox(ratio) keeps the visible area at a fixed
+// width / height ratio and covers the rest of the window with black bars. The camera
+// has no viewport to shrink, so the bars are UI nodes drawn over the centered view.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum AspectMode {
+    Fill,
+    Letterbox(f32),
+}
+
+impl Default for AspectMode {
+    fn default() -> Self {
+        AspectMode::Fill
+    }
+}
+
+// offset and size of the visible area inside a window of the given size
+pub fn letterbox_viewport(window: Vec2, mode: AspectMode) -> (Vec2, Vec2) {
+    match mode {
+        AspectMode::Letterbox(ratio) if window.x > 0.0 && window.y > 0.0 => {
+            let size = if window.x / window.y > ratio {
+                Vec2::new(window.y * ratio, window.y)
+            } else {
+                Vec2::new(window.x, window.x / ratio)
+            };
+            ((window - size) * 0.5, size)
+        }
+        _ => (Vec2::ZERO, window),
+    }
+}
+
+// camera scale for the visible area, so it shows as much of the world vertically as scale
+// shows in the whole window. With bars at the sides that is scale itself, with bars on top
+// and bottom the smaller view is zoomed out to make up for them.
+pub fn framing_scale(scale: f32, window: Vec2, mode: AspectMode) -> f32 {
+    let (_, size) = letterbox_viewport(window, mode);
+    if size.y > 0.0 {
+        scale * window.y / size.y
+    } else {
+        scale
+    }
+}
+
+// one of the two black bars, 0 is left / bottom and 1 is right / top
+pub struct LetterboxBar(usize);
+
+pub fn aspect_mode_input(keyboard_input: Res<Input<KeyCode>>, mut mode: ResMut<AspectMode>) {
+    if keyboard_input.just_pressed(KeyCode::F5) {
+        *mode = match *mode {
+            AspectMode::Fill => AspectMode::Letterbox(16.0 / 9.0),
+            AspectMode::Letterbox(_) => AspectMode::Fill,
+        };
+        info!("aspect mode: {:?}", *mode);
+    }
+}
+
+pub fn spawn_letterbox_bars(mut commands: Commands, mut materials: ResMut<Assets<ColorMaterial>>) {
+    let material = materials.add(Color::BLACK.into());
+    for i in 0..2 {
+        commands
+            .spawn_bundle(NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    ..Default::default()
+                },
+                material: material.clone(),
+                visible: Visible {
+                    is_visible: false,
+                    is_transparent: false,
+                },
+                ..Default::default()
+            })
+            .insert(LetterboxBar(i));
+    }
+}
+
+// resizes the bars whenever the window size or the aspect mode changes
+pub fn update_letterbox_bars(
+    windows: Res<Windows>,
+    mode: Res<AspectMode>,
+    mut last: Local<Option<(Vec2, AspectMode)>>,
+    mut query: Query<(&LetterboxBar, &mut Style, &mut Visible)>,
+) {
+    let window = match windows.get_primary() {
+        Some(window) => Vec2::new(window.width(), window.height()),
+        None => return,
+    };
+    if *last == Some((window, *mode)) {
+        return;
+    }
+    *last = Some((window, *mode));
+
+    let (offset, size) = letterbox_viewport(window, *mode);
+    // bars left and right for wide windows, top and bottom for tall ones
+    let horizontal = offset.x > 0.0;
+    for (bar, mut style, mut visible) in query.iter_mut() {
+        visible.is_visible = offset.x > 0.0 || offset.y > 0.0;
+        let (bar_size, position) = if horizontal {
+            let left = if bar.0 == 0 { 0.0 } else { offset.x + size.x };
+            (Vec2::new(offset.x, window.y), Vec2::new(left, 0.0))
+        } else {
+            let bottom = if bar.0 == 0 { 0.0 } else { offset.y + size.y };
+            (Vec2::new(window.x, offset.y), Vec2::new(0.0, bottom))
+        };
+        style.size = Size::new(Val::Px(bar_size.x), Val::Px(bar_size.y));
+        style.position = Rect {
+            left: Val::Px(position.x),
+            bottom: Val::Px(position.y),
+            ..Default::default()
+        };
+    }
+}
@@ -0,0 +1,104 @@
+use std::collections::VecDeque;
+
+use bevy::math::UVec2;
+use thiserror::Error;
+
+// Developer console: state of the input line plus a parser for the commands. Executing
+// them is up to the game.
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    // put ferris on a cell
+    Goto(UVec2),
+    // give ferris the key with this color id
+    GiveKey(u8),
+    Level(usize),
+    Solve,
+    // reload the current map from disk
+    Reload,
+    // print the current solution as replay code
+    Export,
+    // play a replay code from the level start
+    Import(String),
+    // write the current map as an ASCII map file, which can be passed on the command line
+    Save(String),
+    // replace the map with an ASCII map file, e.g. one written by save
+    Load(String),
+    // change a game option (name, value) that has no key of its own
+    Set(String, String),
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ParseError {
+    #[error("empty command")]
+    Empty,
+    #[error("unknown command '{0}'")]
+    Unknown(String),
+    #[error("usage: {0}")]
+    Usage(&'static str),
+}
+
+fn usage(name: &str) -> Option<&'static str> {
+    let usage = match name {
+        "goto" => "goto <x> <y>",
+        "givekey" => "givekey <0-2>",
+        "level" => "level <n>",
+        "solve" => "solve",
+        "reload" => "reload",
+        "export" => "export",
+        "import" => "import <code>",
+        "save" => "save <path>",
+        "load" => "load <path>",
+        "set" => "set <option> <value>",
+        _ => return None,
+    };
+    Some(usage)
+}
+
+pub fn parse_command(line: &str) -> Result<Command, ParseError> {
+    let mut words = line.split_whitespace();
+    let name = words.next().ok_or(ParseError::Empty)?;
+    let args: Vec<&str> = words.collect();
+    let usage = usage(name).ok_or_else(|| ParseError::Unknown(name.to_string()))?;
+    let bad_usage = |_| ParseError::Usage(usage);
+
+    let command = match (name, args.as_slice()) {
+        ("goto", [x, y]) => Command::Goto(UVec2::new(
+            x.parse().map_err(bad_usage)?,
+            y.parse().map_err(bad_usage)?,
+        )),
+        ("givekey", [color_id]) => match color_id.parse().map_err(bad_usage)? {
+            color_id @ 0..=2 => Command::GiveKey(color_id),
+            _ => return Err(ParseError::Usage(usage)),
+        },
+        ("level", [n]) => Command::Level(n.parse().map_err(bad_usage)?),
+        ("solve", []) => Command::Solve,
+        ("reload", []) => Command::Reload,
+        ("export", []) => Command::Export,
+        ("import", [code]) => Command::Import(code.to_string()),
+        ("save", [path]) => Command::Save(path.to_string()),
+        ("load", [path]) => Command::Load(path.to_string()),
+        ("set", [option, value]) => Command::Set(option.to_string(), value.to_string()),
+        _ => return Err(ParseError::Usage(usage)),
+    };
+    Ok(command)
+}
+
+const MAX_OUTPUT_LINES: usize = 8;
+
+#[derive(Default)]
+pub struct Console {
+    pub open: bool,
+    pub input: String,
+    // most recent last
+    pub output: VecDeque<String>,
+}
+
+impl Console {
+    pub fn print(&mut self, line: impl Into<String>) {
+        self.output.push_back(line.into());
+        while self.output.len() > MAX_OUTPUT_LINES {
+            self.output.pop_front();
+        }
+    }
+}
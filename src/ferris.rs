@@ -0,0 +1,31 @@
+use bevy::math::UVec2;
+
+// the player state: this is both the component and the search state of the solver
+#[derive(Eq, PartialEq, Hash, Clone, Debug, Default)]
+pub struct Ferris {
+    pub pos: UVec2,
+    // number of held keys per color. A fixed array rather than a map from color to count on
+    // purpose: there are NUM_COLORS colors, and the solver clones and hashes this for every
+    // search state, which stays cheap and allocation free this way.
+    pub keys: [u32; 3],
+    // keys picked up so far, including the ones used up since
+    pub collected: [bool; 3],
+}
+
+impl Ferris {
+    // empty handed at pos
+    pub fn at(pos: UVec2) -> Self {
+        Ferris {
+            pos,
+            ..Default::default()
+        }
+    }
+
+    // the key ferris carries with KeyCapacity::Single (the first one otherwise)
+    pub fn held_key(&self) -> Option<u8> {
+        self.keys
+            .iter()
+            .position(|count| *count > 0)
+            .map(|color_id| color_id as u8)
+    }
+}
@@ -0,0 +1,172 @@
+use bevy::math::{IVec2, UVec2};
+
+pub const WALL_TILE: u16 = 0;
+pub const FLOOR_TILE: u16 = 1;
+pub const START_TILE: u16 = 18;
+pub const END_TILE: u16 = 19;
+// walkable, records a checkpoint when ferris steps on it
+pub const CHECKPOINT_TILE: u16 = 20;
+
+// up, down, left, right: the orthogonal steps only. Diagonals are an opt-in of the
+// solver, see SolveOptions::eight_way.
+pub const DIRECTIONS: [IVec2; 4] = [
+    IVec2::new(0, 1),
+    IVec2::new(0, -1),
+    IVec2::new(-1, 0),
+    IVec2::new(1, 0),
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Direction {
+    pub fn delta(self) -> IVec2 {
+        match self {
+            Direction::Up => IVec2::new(0, 1),
+            Direction::Down => IVec2::new(0, -1),
+            Direction::Left => IVec2::new(-1, 0),
+            Direction::Right => IVec2::new(1, 0),
+        }
+    }
+
+    // direction of a single step, None for anything else
+    pub fn from_offset(offset: IVec2) -> Option<Direction> {
+        [
+            Direction::Up,
+            Direction::Down,
+            Direction::Left,
+            Direction::Right,
+        ]
+        .iter()
+        .copied()
+        .find(|direction| direction.delta() == offset)
+    }
+}
+
+// The four orthogonal neighbors of pos (in DIRECTIONS order, no diagonals). Neighbors
+// outside of a map of the given size are None, so callers never have to rely on a failed
+// tile lookup to detect the map border.
+pub fn get_neighboring_pos(pos: UVec2, size: UVec2) -> [Option<UVec2>; 4] {
+    let mut neighbors = [None; 4];
+    for (neighbor, dir) in neighbors.iter_mut().zip(DIRECTIONS.iter()) {
+        let n = pos.as_i32() + *dir;
+        if n.x >= 0 && n.y >= 0 && (n.x as u32) < size.x && (n.y as u32) < size.y {
+            *neighbor = Some(n.as_u32());
+        }
+    }
+    neighbors
+}
+
+// doors and keys come in NUM_COLORS colors (red, yellow, blue), one tile per color
+// starting at these indices
+pub const NUM_COLORS: u8 = 3;
+const FIRST_DOOR_TILE: u16 = 2;
+const FIRST_KEY_TILE: u16 = 5;
+
+fn color_in_range(index: u16, first: u16) -> Option<u8> {
+    index
+        .checked_sub(first)
+        .filter(|color_id| *color_id < NUM_COLORS as u16)
+        .map(|color_id| color_id as u8)
+}
+
+// color of a door tile, None for every other tile
+pub fn door_color_id(index: u16) -> Option<u8> {
+    color_in_range(index, FIRST_DOOR_TILE)
+}
+
+// color of a key tile, None for every other tile
+pub fn key_color_id(index: u16) -> Option<u8> {
+    color_in_range(index, FIRST_KEY_TILE)
+}
+
+// color of a door or key tile
+pub fn color_id(index: u16) -> Option<u8> {
+    door_color_id(index).or_else(|| key_color_id(index))
+}
+
+pub fn door_tile(color_id: u8) -> u16 {
+    FIRST_DOOR_TILE + color_id as u16
+}
+
+pub fn key_tile(color_id: u8) -> u16 {
+    FIRST_KEY_TILE + color_id as u16
+}
+
+// Plain copy of the tile layer, indexed by tile position. None means there is no tile
+// at that position (i.e. empty floor).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TileGrid {
+    size: UVec2,
+    tiles: Vec<Option<u16>>,
+}
+
+impl TileGrid {
+    pub fn new(size: UVec2) -> Self {
+        TileGrid {
+            size,
+            tiles: vec![None; (size.x * size.y) as usize],
+        }
+    }
+
+    pub fn size(&self) -> UVec2 {
+        self.size
+    }
+
+    // true until a map has been copied into the grid
+    pub fn is_empty(&self) -> bool {
+        self.tiles.is_empty()
+    }
+
+    pub fn in_bounds(&self, pos: IVec2) -> bool {
+        pos.x >= 0 && pos.y >= 0 && (pos.x as u32) < self.size.x && (pos.y as u32) < self.size.y
+    }
+
+    fn index(&self, pos: UVec2) -> Option<usize> {
+        if pos.x < self.size.x && pos.y < self.size.y {
+            Some((pos.y * self.size.x + pos.x) as usize)
+        } else {
+            None
+        }
+    }
+
+    // texture index at pos. None for empty cells and positions outside of the grid.
+    pub fn get(&self, pos: UVec2) -> Option<u16> {
+        self.index(pos).and_then(|i| self.tiles[i])
+    }
+
+    // positions outside of the grid are ignored
+    pub fn set(&mut self, pos: UVec2, tile: Option<u16>) {
+        if let Some(i) = self.index(pos) {
+            self.tiles[i] = tile;
+        }
+    }
+
+    // first position holding texture_index
+    pub fn find(&self, texture_index: u16) -> Option<UVec2> {
+        self.iter()
+            .find(|(_, tile)| *tile == texture_index)
+            .map(|(pos, _)| pos)
+    }
+
+    // all positions holding texture_index
+    pub fn find_all(&self, texture_index: u16) -> Vec<UVec2> {
+        self.iter()
+            .filter(|(_, tile)| *tile == texture_index)
+            .map(|(pos, _)| pos)
+            .collect()
+    }
+
+    // all non-empty cells
+    pub fn iter(&self) -> impl Iterator<Item = (UVec2, u16)> + '_ {
+        let width = self.size.x;
+        self.tiles.iter().enumerate().filter_map(move |(i, tile)| {
+            tile.map(|tile| (UVec2::new(i as u32 % width, i as u32 / width), tile))
+        })
+    }
+}
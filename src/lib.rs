@@ -1,3 +1,19 @@
+pub mod ascii;
+pub mod audio;
+pub mod autotile;
+pub mod bests;
 pub mod camera;
+pub mod console;
+pub mod ferris;
+pub mod grid;
+pub mod locks;
+pub mod maze;
+pub mod metrics;
+pub mod replay;
+pub mod rng;
+pub mod rules;
+pub mod solver;
 pub mod spritesheet;
 pub mod texture;
+pub mod toast;
+pub mod zorder;
@@ -0,0 +1,76 @@
+use rand::{seq::SliceRandom, Rng};
+use thiserror::Error;
+
+use crate::{
+    ferris::Ferris,
+    grid::{door_tile, key_tile, TileGrid, END_TILE, FLOOR_TILE, NUM_COLORS, START_TILE},
+    rules::Rules,
+    solver::{solve_grid, SolveOptions},
+};
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum LockError {
+    #[error("map has no start or no exit")]
+    NoStartOrExit,
+    #[error("the exit can not be reached")]
+    Unsolvable,
+    #[error("the path to the exit is too short for {0} key / door pairs")]
+    PathTooShort(usize),
+    #[error("there are only {} key / door colors, not {0}", NUM_COLORS)]
+    TooManyPairs(usize),
+}
+
+// Puts `pairs` key / door pairs with random, distinct colors onto the floor cells of the
+// shortest path from the start to an exit, each key before its door. Ferris walks over
+// every key on the way to its door, so the map stays solvable (checked with the solver
+// anyway). Doors in open areas can be walked around, only doors in corridors really lock
+// anything.
+pub fn place_locks(
+    grid: &mut TileGrid,
+    rules: &Rules,
+    rng: &mut impl Rng,
+    pairs: usize,
+) -> Result<(), LockError> {
+    if pairs == 0 {
+        return Ok(());
+    }
+    if pairs > NUM_COLORS as usize {
+        return Err(LockError::TooManyPairs(pairs));
+    }
+    let start = grid.find(START_TILE).ok_or(LockError::NoStartOrExit)?;
+    let exits = grid.find_all(END_TILE);
+    if exits.is_empty() {
+        return Err(LockError::NoStartOrExit);
+    }
+    let options = SolveOptions::default();
+    let (path, _) = solve_grid(grid, rules, &Ferris::at(start), &exits, &options)
+        .ok_or(LockError::Unsolvable)?;
+    let free: Vec<_> = path
+        .iter()
+        .map(|state| state.pos)
+        .filter(|pos| matches!(grid.get(*pos), None | Some(FLOOR_TILE)))
+        .collect();
+    if free.len() < pairs * 2 {
+        return Err(LockError::PathTooShort(pairs));
+    }
+
+    // split the free cells into one run per pair and put the key into the first half of
+    // its run and the door into the second one, so pairs never overlap
+    let mut placed = grid.clone();
+    let mut colors: Vec<u8> = (0..NUM_COLORS).collect();
+    colors.shuffle(rng);
+    let run = free.len() / pairs;
+    for (i, color_id) in colors.into_iter().take(pairs).enumerate() {
+        let cells = &free[i * run..(i + 1) * run];
+        let half = cells.len() / 2;
+        let key_pos = cells[rng.gen_range(0..half)];
+        let door_pos = cells[rng.gen_range(half..cells.len())];
+        placed.set(key_pos, Some(key_tile(color_id)));
+        placed.set(door_pos, Some(door_tile(color_id)));
+    }
+
+    solve_grid(&placed, rules, &Ferris::at(start), &exits, &options)
+        .ok_or(LockError::Unsolvable)?;
+    *grid = placed;
+    Ok(())
+}
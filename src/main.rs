@@ -1,367 +1,4687 @@
-use std::collections::VecDeque;
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    path::Path,
+};
 
-use bevy::{prelude::*, transform};
+use bevy::{
+    asset::LoadState,
+    ecs::schedule::ShouldRun,
+    input::{keyboard::KeyboardInput, ElementState},
+    prelude::*,
+    render::mesh::VertexAttributeValues,
+    transform,
+};
 use bevy_ecs_tilemap::prelude::*;
 
-use ferris_lab::spritesheet::{self};
-use pathfinding::{
-    directed::astar,
-    num_traits::{Signed, Zero},
+use ferris_lab::{
+    ascii::{tilegrid_from_ascii, tilegrid_to_ascii},
+    audio::{AudioMixer, SoundCategory},
+    autotile::{wall_autotile, wall_mask},
+    bests::{Best, NewRecords, PersonalBests},
+    camera::{framing_scale, AddTrauma, AspectMode, CameraShake},
+    console::{parse_command, Command, Console},
+    ferris::Ferris,
+    grid::{
+        color_id, door_color_id, door_tile, key_color_id, key_tile, Direction, TileGrid,
+        CHECKPOINT_TILE, END_TILE, FLOOR_TILE, NUM_COLORS, START_TILE, WALL_TILE,
+    },
+    locks::place_locks,
+    maze::{generate_maze, parse_maze_size},
+    metrics::MetricsLogger,
+    replay::{decode_replay, encode_replay, Replay},
+    rng::{GameRng, DEFAULT_SEED},
+    rules::{
+        apply_move, is_walkable, key_colors, nearest_walkable, step_to, tile_cost, try_drop_key,
+        ExitCondition, Interaction, KeyCapacity, OrderConstraint, Rules,
+    },
+    solver::{solve_grid, SolveOptions, ORTHOGONAL_COST},
+    spritesheet::{self},
+    zorder,
 };
+use pathfinding::num_traits::{Signed, Zero};
+use rand::Rng;
 
-#[derive(Eq, PartialEq, Hash, Clone, Debug)]
-struct Ferris {
-    pos: UVec2,
-    keys: [bool; 3],
-}
+#[cfg(test)]
+mod testkit;
+#[cfg(test)]
+mod tests;
 
 #[derive(Default)]
 struct TargetTracker {
     count: u32,
 }
 
-struct EndPos(UVec2);
+// all exits of the map, reaching any of them wins
+struct EndPos(Vec<UVec2>);
+
+// solution currently played back by play_solution
+struct Solution {
+    // remaining states, the current state is not included
+    steps: VecDeque<Ferris>,
+    // state ferris should be in before the next step. Anything else means he was moved
+    // manually during playback and the remaining steps are stale.
+    expected: Ferris,
+    // the full solution as it was first solved, for replaying it from start
+    start: Ferris,
+    // the map at start, put back before each replay
+    start_grid: TileGrid,
+    recorded: VecDeque<Ferris>,
+    // number of times playback has been started
+    plays: u32,
+    // cells of the latest solve (updated when playback solves again), see PathHighlight
+    path: Vec<UVec2>,
+    // how often each state was reached during the current play. Playback stops when one
+    // comes up more than MAX_STATE_VISITS times, instead of cycling forever.
+    visits: HashMap<Ferris, u32>,
+}
+
+const MAX_STATE_VISITS: u32 = 3;
+
+fn solution_path(start: &Ferris, steps: &VecDeque<Ferris>) -> Vec<UVec2> {
+    std::iter::once(start)
+        .chain(steps.iter())
+        .map(|state| state.pos)
+        .collect()
+}
+
+// what R solves from: ferris' current state, or the level start (ferris and the map are
+// reset before playback, for demonstrating a level from the beginning)
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum SolveFrom {
+    Current,
+    Start,
+}
+
+impl Default for SolveFrom {
+    fn default() -> Self {
+        SolveFrom::Current
+    }
+}
+
+// ferris at the start tile, as placed by init_ferris
+struct StartState(Ferris);
+
+// if true, pressing R while a solution is playing cancels it instead of solving again
+struct SolveToggles(bool);
+
+impl Default for SolveToggles {
+    fn default() -> Self {
+        SolveToggles(true)
+    }
+}
+
+// whether a solution is walked step by step, or applied at once with ferris jumping
+// straight to its end (for testing levels), toggled with V
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum SolveResult {
+    Walk,
+    Teleport,
+}
+
+impl Default for SolveResult {
+    fn default() -> Self {
+        SolveResult::Walk
+    }
+}
+
+// how often a solution is played before playback stops, None replays it forever (demo mode)
+struct PlaybackLoops(Option<u32>);
+
+impl Default for PlaybackLoops {
+    fn default() -> Self {
+        PlaybackLoops(Some(1))
+    }
+}
+
+// the entity under control, drawn with an outline: a bigger, tinted copy of its sprite
+// (Outline) as a child behind it
+struct Outlined;
+
+struct Outline;
+
+const OUTLINE_SCALE: f32 = 1.25;
+
+// horizontal direction ferris is looking at. The spritesheet only has left / right walk
+// cycles, so vertical moves keep the current facing.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Facing {
+    Left,
+    Right,
+}
+
+impl Facing {
+    // frames of the walk cycle, the first one doubles as idle frame
+    fn frames(self) -> std::ops::Range<u32> {
+        match self {
+            Facing::Left => 0..4,
+            Facing::Right => 4..8,
+        }
+    }
+}
 
-#[derive(Default)]
 struct ChaseCamera {
     x_moving: bool,
     y_moving: bool,
+    // jump straight to a newly spawned target instead of panning over to it
+    snap_on_spawn: bool,
+    // current look-ahead in the direction the target moves, eased towards
+    // Tunables::camera_lead while moving and back to zero when idle
+    lead: Vec2,
+}
+
+impl Default for ChaseCamera {
+    fn default() -> Self {
+        ChaseCamera {
+            x_moving: false,
+            y_moving: false,
+            snap_on_spawn: true,
+            lead: Vec2::ZERO,
+        }
+    }
 }
 
 struct ChaseCameraTarget;
 
-fn startup(mut commands: Commands, asset_server: Res<AssetServer>) {
-    commands
-        .spawn_bundle(OrthographicCameraBundle::new_2d())
-        .insert(ChaseCamera::default());
+// box in screen pixels relative to the view center (y up). A chase camera with a deadzone
+// stays put while the target is inside and moves just enough to keep it inside otherwise,
+// instead of the per axis hysteresis. Toggled with B.
+#[derive(Clone, Copy, Debug)]
+struct Deadzone(Rect<f32>);
 
-    let handle: Handle<LdtkMap> = asset_server.load("labyrinth.ldtk");
+impl Deadzone {
+    fn square(half_size: f32) -> Self {
+        Deadzone(Rect {
+            left: -half_size,
+            right: half_size,
+            top: half_size,
+            bottom: -half_size,
+        })
+    }
 
-    let map_entity = commands.spawn().id();
+    // camera movement keeping a target at offset (in world units) from the camera inside the
+    // box, at the given camera scale
+    fn follow(&self, offset: Vec2, scale: f32) -> Vec2 {
+        let min = Vec2::new(self.0.left, self.0.bottom) * scale;
+        let max = Vec2::new(self.0.right, self.0.top) * scale;
+        offset - offset.max(min).min(max)
+    }
+}
 
-    commands.entity(map_entity).insert_bundle(LdtkMapBundle {
-        ldtk_map: handle,
-        map: Map::new(0u16, map_entity),
-        transform: Transform::from_xyz(0.0, 0.0, 0.0),
-        ..Default::default()
-    });
+// Chase follows the first ChaseCameraTarget, FrameAll keeps all of them in view and zooms
+// out as far as needed (toggled with F6)
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum CameraMode {
+    Chase,
+    FrameAll,
 }
 
-fn update_camera(
-    mut camera_query: Query<(&mut Transform, &mut ChaseCamera), With<bevy::render::camera::Camera>>,
-    target_query: Query<
-        &Transform,
-        (
-            With<ChaseCameraTarget>,
-            Without<bevy::render::camera::Camera>,
-        ),
-    >,
-) {
-    if let Some(target_transform) = target_query.iter().next() {
-        let target_translation = target_transform.translation;
-        for (mut camera_transform, mut follow_camera) in camera_query.iter_mut() {
-            // TODO: zoom out slightly during movement
-            camera_transform.scale = Vec3::new(0.5, 0.5, 1.0);
+impl Default for CameraMode {
+    fn default() -> Self {
+        CameraMode::Chase
+    }
+}
+
+// zooming out further than this leaves targets out of view in FrameAll
+const MAX_FRAME_ALL_SCALE: f32 = 2.0;
+
+// center and scale showing all targets with a margin of a tile in a view of the given size
+// (in pixels at scale 1)
+fn frame_targets(targets: &[Vec2], view_size: Vec2, min_scale: f32) -> Option<(Vec2, f32)> {
+    let first = *targets.first()?;
+    let (min, max) = targets
+        .iter()
+        .fold((first, first), |(min, max), t| (min.min(*t), max.max(*t)));
+    let extent = max - min + Vec2::splat(TILE_SIZE * 2.0);
+    let scale = (extent.x / view_size.x)
+        .max(extent.y / view_size.y)
+        .clamp(min_scale, MAX_FRAME_ALL_SCALE.max(min_scale));
+    Some(((min + max) * 0.5, scale))
+}
+
+// marks the cell ferris started the level on, spawned by init_ferris
+struct StartMarker;
+
+// arrow at the border of the view pointing to the exit while it is offscreen
+struct ExitArrow;
+
+// assets that have to be loaded before the game starts. Until then the loading screen is
+// shown and input is ignored.
+#[derive(Default)]
+struct LoadingState {
+    tracked: Vec<HandleUntyped>,
+    loaded: bool,
+}
+
+// root of the loading screen UI, despawned when loading is done
+struct LoadingScreen;
+
+// fill of the progress bar, its width is the loaded fraction
+struct LoadingBar;
+
+// tile semantics authored as an LDTK entity (with a `Type` field) instead of a tile index.
+// Only the visuals: drawn into the tile layer by place_entity_tiles once the layer exists,
+// the TileGrid has the tile already (see process_loaded_tile_maps and update_tile_grid).
+struct PlacedTile {
+    pos: UVec2,
+    texture_index: u16,
+}
+
+// feel of movement and camera, editable at runtime with `set <field> <value>`
+#[derive(Clone, PartialEq, Debug)]
+struct Tunables {
+    // the camera starts chasing ferris once he is further than this from its center
+    chase_max_distance: f32,
+    // pixels per frame ferris walks between cells
+    step_size: f32,
+    // seconds per animation frame, for frames without a duration in the spritesheet
+    animation_interval: f32,
+    camera_scale: f32,
+    // maximum offset in pixels of the TileWobble animation, capped at MAX_WOBBLE
+    wobble_amplitude: f32,
+    // how far the camera looks ahead of ferris while he is moving
+    camera_lead: f32,
+    // particles in the burst of a picked up key or opened door, 0 turns bursts off
+    burst_size: usize,
+}
 
-            let xoffs = target_translation.x - camera_transform.translation.x;
-            let yoffs = target_translation.y - camera_transform.translation.y;
-            let mut xmove = 0.0;
-            let mut ymove = 0.0;
-            const CHASE_MAX_DISTANCE: f32 = 64.0;
-            // chase with 'hysteresis' per axis:
-            // - start chasing target if it is more than CHASE_MAX_DISTANCE from center
-            // - if the camera is already chasing keep moving until target is in center
-            if xoffs.abs() > CHASE_MAX_DISTANCE || (follow_camera.x_moving && xoffs.abs() >= 1.0) {
-                xmove = xoffs.signum();
+impl Default for Tunables {
+    fn default() -> Self {
+        Tunables {
+            chase_max_distance: 64.0,
+            step_size: 0.5,
+            animation_interval: 0.1,
+            camera_scale: 0.5,
+            wobble_amplitude: 0.5,
+            camera_lead: 32.0,
+            burst_size: 8,
+        }
+    }
+}
+
+impl Tunables {
+    // field by name, for the console
+    fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        let field = match name {
+            "chase_max_distance" => &mut self.chase_max_distance,
+            "step_size" => &mut self.step_size,
+            "animation_interval" => &mut self.animation_interval,
+            "camera_scale" => &mut self.camera_scale,
+            "wobble_amplitude" => &mut self.wobble_amplitude,
+            "camera_lead" => &mut self.camera_lead,
+            "burst_size" => {
+                self.burst_size = value
+                    .parse()
+                    .map_err(|_| "expected a whole number".to_string())?;
+                return Ok(());
             }
-            if yoffs.abs() > CHASE_MAX_DISTANCE || (follow_camera.y_moving && yoffs.abs() >= 1.0) {
-                ymove = yoffs.signum();
+            _ => return Err(format!("unknown option '{}'", name)),
+        };
+        match value.parse::<f32>() {
+            Ok(number) if number >= 0.0 => {
+                *field = number;
+                Ok(())
             }
-            follow_camera.x_moving = xmove.abs() >= 1.0;
-            follow_camera.y_moving = ymove.abs() >= 1.0;
-            camera_transform.translation.x += xmove;
-            camera_transform.translation.y += ymove;
+            _ => Err("expected a number of at least 0".to_string()),
         }
     }
 }
 
-fn main() {
-    env_logger::Builder::from_default_env()
-        .filter_level(log::LevelFilter::Info)
-        .init();
+// what drives the walk cycle: Timed advances frames at the spritesheet durations while
+// ferris moves, PerStep advances exactly one frame per completed grid step for a
+// deliberate, stepped gait (toggled with F8)
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum AnimationSync {
+    Timed,
+    PerStep,
+}
 
-    App::new()
-        .insert_resource(WindowDescriptor {
-            width: 1270.0,
-            height: 720.0,
-            title: String::from("LDTK Example"),
-            ..Default::default()
-        })
-        .add_plugins(DefaultPlugins)
-        .add_plugin(TilemapPlugin)
-        .add_plugin(LdtkPlugin)
-        .add_startup_system(startup.system())
-        .add_system(update_camera.system())
-        // .add_system(ferris_lab::camera::movement.system())
-        .add_system(ferris_lab::texture::set_texture_filters_to_nearest.system())
-        .add_system(init_ferris.system())
-        .add_system(move_ferris.system())
-        .add_system(process_loaded_tile_maps.system())
-        .add_system(character_input.system())
-        .add_system(play_solution.system())
-        .add_system(animate_character_system.system())
-        .add_system(map_position.system())
-        // .add_system(show_solution)
-        // .add_system(dump_tiles.system())
-        .run();
+impl Default for AnimationSync {
+    fn default() -> Self {
+        AnimationSync::Timed
+    }
 }
 
-fn dump_tiles(tile_query: Query<(&Tile, &TilePos)>) {
-    for (tile, pos) in tile_query.iter() {
-        println!("{:?} {:?}", tile, pos);
+// debug slow-motion / fast-forward factor for the simulation (input is not scaled)
+struct TimeScale(f32);
+
+impl Default for TimeScale {
+    fn default() -> Self {
+        TimeScale(1.0)
     }
 }
 
-fn pos_to_translation(pos: &UVec2) -> Vec3 {
-    // Vec3::new(
-    //     (pos.x * 16) as f32 + 8.0,
-    //     ((16 - pos.y) * 16) as f32 * -1.0 + 8.0,
-    //     0.0,
-    // )
-    Vec3::new((pos.x * 16) as f32 + 8.0, (pos.y * 16) as f32 + 8.0, 0.0)
+// speed of solution playback relative to normal walking, changed with PageUp / PageDown
+struct PlaybackSpeed(f32);
+
+impl Default for PlaybackSpeed {
+    fn default() -> Self {
+        PlaybackSpeed(1.0)
+    }
 }
 
-const START_TILE: u16 = 18;
-const END_TILE: u16 = 19;
+const MIN_PLAYBACK_SPEED: f32 = 0.25;
+const MAX_PLAYBACK_SPEED: f32 = 8.0;
 
-const LEVEL_ID: u16 = 0;
-const LAYER_ID: u16 = 1;
+// solution breadcrumb, fades out and is despawned when lifetime is over
+struct Breadcrumb {
+    lifetime: Timer,
+}
 
-fn init_ferris(
-    mut commands: Commands,
-    mut query: Query<(Entity, &mut Ferris), Added<Ferris>>,
-    tile_query: Query<(&Tile, &TilePos)>,
-    asset_server: Res<AssetServer>,
-    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
-    mut map_query: MapQuery,
-) {
-    for (entity, mut ferris) in query.iter_mut() {
-        let mut start_pos = Default::default();
-        let mut end_pos = Default::default();
+// lifetime of newly spawned breadcrumbs in seconds
+struct BreadcrumbLifetime(f32);
 
-        for (tile, pos) in tile_query.iter() {
-            match tile.texture_index {
-                START_TILE => start_pos = *pos,
-                END_TILE => end_pos = *pos,
-                _ => (),
-            }
+impl Default for BreadcrumbLifetime {
+    fn default() -> Self {
+        BreadcrumbLifetime(3.0)
+    }
+}
+
+// afterimages of ferris while he moves fast, e.g. during sped up playback (toggled with
+// F10)
+#[derive(Default)]
+struct MotionTrail(bool);
+
+// fading copy of ferris' sprite left behind by MotionTrail
+struct TrailGhost {
+    lifetime: Timer,
+}
+
+// pixels per second ferris has to move at to leave a trail
+const TRAIL_MIN_SPEED: f32 = 240.0;
+const TRAIL_INTERVAL: f32 = 0.04;
+const TRAIL_LIFETIME: f32 = 0.25;
+const MAX_TRAIL_GHOSTS: usize = 8;
+
+// dust puff kicked up behind ferris when he starts a step, drifts and fades out
+struct DustParticle {
+    velocity: Vec2,
+    lifetime: Timer,
+}
+
+const DUST_PER_STEP: usize = 3;
+// no new puffs while this many are still alive
+const MAX_DUST_PARTICLES: usize = 24;
+const DUST_LIFETIME: f32 = 0.4;
+const DUST_SPEED: f32 = 24.0;
+// key / door bursts are dust particles as well, flying out in all directions
+const MAX_BURST_SIZE: usize = 24;
+const MAX_BURST_PARTICLES: usize = 64;
+const BURST_LIFETIME: f32 = 0.5;
+const BURST_SPEED: f32 = 48.0;
+
+// how the remaining solution path is drawn (toggled with P)
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum PathRender {
+    Crumbs,
+    Line,
+}
+
+impl Default for PathRender {
+    fn default() -> Self {
+        PathRender::Crumbs
+    }
+}
+
+// segment of the solution line, redrawn whenever the solution advances
+struct PathLine;
+
+const PATH_LINE_WIDTH: f32 = 3.0;
+
+// draw lines along the tile boundaries (toggled with G)
+#[derive(Default)]
+struct ShowGrid(bool);
+
+struct GridLine;
+
+// faint tint on the cells of the last solution. Unlike breadcrumbs it stays until the
+// exit is reached or another map is loaded.
+struct PathHighlight;
+
+// diagnostic overlay with the movement cost of every cell (toggled with F3)
+#[derive(Default)]
+struct DebugOverlay(bool);
+
+struct DebugOverlayText;
+
+// text next to the mouse cursor describing the hovered cell, shown with the debug overlay
+struct TileTooltip;
+
+// click-to-move (toggled with M): the cell under the cursor and the path ferris would
+// take to it are previewed, a left click walks it
+#[derive(Default)]
+struct ClickToMove(bool);
+
+// preview sprite of ClickToMove (hovered cell or path)
+struct MovePreview;
+
+// map editing (toggled with E): a left click cycles the tile of the clicked cell. The
+// result can be written to an ASCII map with the save console command.
+#[derive(Default)]
+struct EditMode(bool);
+
+// a picked up key flying from its cell into ferris
+struct KeyPopup {
+    from: Vec3,
+    timer: Timer,
+}
+
+// duration of the key pickup animation in seconds
+struct KeyPopupDuration(f32);
+
+impl Default for KeyPopupDuration {
+    fn default() -> Self {
+        KeyPopupDuration(0.4)
+    }
+}
+
+// Tweened: ferris walks from cell to cell. Instant: he jumps there in the same frame.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum MovementStyle {
+    Tweened,
+    Instant,
+}
+
+impl Default for MovementStyle {
+    fn default() -> Self {
+        MovementStyle::Tweened
+    }
+}
+
+// how much ferris squashes when starting a move and stretches when arriving
+// (fraction of his size, 0.0 disables the effect)
+struct SquashStretch {
+    amplitude: f32,
+}
+
+impl Default for SquashStretch {
+    fn default() -> Self {
+        SquashStretch { amplitude: 0.15 }
+    }
+}
+
+// debug single stepping: while active the simulation systems only run on a step request
+#[derive(Default)]
+struct StepMode {
+    active: bool,
+    step_requested: bool,
+}
+
+// Freezes the simulation (through StepMode) while the window is not focused. Only undone
+// on focus regain if the pause came from the focus loss, a manual pause (comma) stays.
+struct FocusPause {
+    enabled: bool,
+    // StepMode was activated by the focus loss
+    paused: bool,
+}
+
+impl Default for FocusPause {
+    fn default() -> Self {
+        FocusPause {
+            enabled: true,
+            paused: false,
         }
+    }
+}
 
-        info!("ferris added {:?} at {:?}", entity, start_pos);
+// palette used for tinting keys and doors. HighContrast is meant for color blind players.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum ColorScheme {
+    Default,
+    HighContrast,
+}
 
-        let desc: Handle<spritesheet::Spritesheet> = asset_server.load("ferris2.0.json");
-        let texture_handle = asset_server.load("ferris2.0.png");
-        let texture_atlas = TextureAtlas::from_grid(texture_handle, Vec2::new(16.0, 16.0), 10, 1);
-        let texture_atlas_handle = texture_atlases.add(texture_atlas);
+impl Default for ColorScheme {
+    fn default() -> Self {
+        ColorScheme::Default
+    }
+}
 
-        let timer = Timer::from_seconds(0.1, true);
-        // timer.pause();
+// torch light: tiles get darker with their manhattan distance to ferris and are fully
+// dark from this distance on. None disables the dimming.
+#[derive(Default)]
+struct LightRadius(Option<f32>);
 
-        commands
-            .entity(entity)
-            .insert_bundle(SpriteSheetBundle {
-                texture_atlas: texture_atlas_handle,
-                transform: Transform {
-                    scale: Vec3::splat(1.0),
-                    ..Default::default()
-                },
-                ..Default::default()
-            })
-            .insert(desc)
-            //            .insert(solution)
-            .insert(EndPos(end_pos.into()))
-            .insert(timer);
-        ferris.pos = start_pos.into();
-        // commands.entity(entity).insert_bundle
+// debug / accessibility cheat (F4): all tiles at full brightness, ignoring LightRadius
+#[derive(Default)]
+struct RevealAll(bool);
+
+// cosmetic idle animation of the map (toggled with T): each tile sways around its cell,
+// out of step with its neighbors. Only the chunk meshes move, the grid and collisions are
+// not affected.
+#[derive(Default)]
+struct TileWobble(bool);
+
+// a wobbling tile stays closer than half a tile to its cell, so wobble_tiles can tell the
+// cell from where the tile is
+const MAX_WOBBLE: f32 = TILE_SIZE * 0.25;
+
+// offset in pixels of the tile on cell at t seconds, at most amplitude along each axis
+fn wobble_offset(cell: IVec2, t: f32, amplitude: f32) -> Vec2 {
+    let amplitude = amplitude.clamp(0.0, MAX_WOBBLE);
+    // phase from the cell, so neighboring tiles don't move in lockstep
+    let phase = cell.x as f32 * 1.9 + cell.y as f32 * 2.7;
+    Vec2::new((t * 1.3 + phase).sin(), (t * 1.7 + phase * 1.1).sin()) * amplitude
+}
+
+// K pulses every key together with its doors in their color for a few seconds, to show
+// which key opens what. None while not pulsing.
+#[derive(Default)]
+struct PairPulse(Option<Timer>);
+
+// doors next to ferris glow if he holds their key (toggled with O)
+struct DoorGlow(bool);
+
+impl Default for DoorGlow {
+    fn default() -> Self {
+        DoorGlow(true)
     }
 }
 
-fn solve(
-    map_query: &mut MapQuery,
-    start_state: Ferris,
-    end_pos: &UVec2,
-    query: &Query<(&Tile, &TilePos)>,
-) -> VecDeque<Ferris> {
-    let successors = |state: &Ferris| {
-        let neighbor_pos = get_neighboring_pos(state.pos.into());
-        let mut successors = Vec::new();
-
-        for pos in neighbor_pos.iter().take(4).filter_map(|f| f.as_ref()) {
-            let mut new_state = state.clone();
-            new_state.pos = (*pos).into();
-
-            info!("pos: {:?}", pos);
-
-            if let Ok(tile_ent) = map_query.get_tile_entity(*pos, LEVEL_ID, LAYER_ID) {
-                if let Ok((tile, _)) = query.get(tile_ent) {
-                    if tile.texture_index == END_TILE
-                        || ((2..=4).contains(&tile.texture_index)
-                            && new_state.keys[(tile.texture_index - 2) as usize])
-                    {
-                        successors.push((new_state, 1));
-                    } else if (5..=7).contains(&tile.texture_index) {
-                        new_state.keys[(tile.texture_index - 5) as usize] = true;
-                        successors.push((new_state, 1));
+// alpha of decoration tiles at or next to ferris' cell
+struct DecorationFade(f32);
+
+impl Default for DecorationFade {
+    fn default() -> Self {
+        DecorationFade(0.35)
+    }
+}
+
+// multiplied onto the tint of a glowing door, values above 1.0 brighten the texture
+const DOOR_GLOW: f32 = 1.6;
+
+const PAIR_PULSE_DURATION: f32 = 3.0;
+// pulses per second
+const PAIR_PULSE_FREQUENCY: f32 = 1.5;
+
+// the TileGrid was copied from a freshly loaded map
+struct TileGridReady;
+
+// a tile was changed in the editor, see edit_tiles
+struct MapEdited;
+
+// a key color has keys but no doors or (worse) doors but no keys
+#[derive(Debug)]
+struct LevelBalanceWarning {
+    color_id: u8,
+    keys: u32,
+    doors: u32,
+}
+
+// the map breaks an authoring rule, e.g. it has more than one start tile
+#[derive(Debug)]
+struct LevelValidationFailed(String);
+
+// ferris collected the key of color_id at pos
+struct KeyPickedUp {
+    pos: UVec2,
+    color_id: u8,
+}
+
+// ferris opened the door of color_id at pos
+struct DoorOpened {
+    pos: UVec2,
+    color_id: u8,
+}
+
+// ferris put down the key of color_id at pos (KeyCapacity::Single)
+struct KeyDropped {
+    pos: UVec2,
+    color_id: u8,
+}
+
+// ferris reached the exit
+struct GameWon;
+
+// ferris tried to move onto the blocked cell at pos (wall, locked door or closed exit)
+struct WallBump {
+    pos: UVec2,
+}
+
+// what bumping into a wall costs (hardcore modes). Locked doors are never penalized.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum WallBumpPenalty {
+    None,
+    // one of the Lives, the level fails when they are used up
+    Life,
+    // the level fails right away
+    Fail,
+}
+
+impl Default for WallBumpPenalty {
+    fn default() -> Self {
+        WallBumpPenalty::None
+    }
+}
+
+struct Lives(u32);
+
+const START_LIVES: u32 = 3;
+
+impl Default for Lives {
+    fn default() -> Self {
+        Lives(START_LIVES)
+    }
+}
+
+// the level is restarted from the beginning
+struct LevelFailed;
+
+// ferris ran into something lethal and loses a life, see handle_deaths
+struct Died;
+
+// the last life was lost, the level is restarted with fresh lives
+struct GameOver;
+
+// remaining lives in the top right corner, only shown in modes that can cost lives
+struct LivesText;
+
+// held keys below the lives, in the colors of the ColorScheme
+struct KeyInventoryText;
+
+// moves and time of the current attempt at the level, for the results shown on winning
+// and the metrics
+#[derive(Default)]
+struct RunStats {
+    moves: u32,
+    // set with the first frame of the attempt once the assets are loaded
+    started: Option<f64>,
+    // solutions shown (solver or replay). Runs with help set no personal bests.
+    solves: u32,
+}
+
+impl RunStats {
+    fn seconds(&self, now: f64) -> f64 {
+        self.started.map_or(0.0, |started| now - started)
+    }
+}
+
+// personal bests are kept in this file in the working directory
+const BESTS_PATH: &str = "personal_bests.json";
+
+struct BestsPath(std::path::PathBuf);
+
+// results panel shown on winning, until the level is restarted
+struct ResultsPanel;
+
+// Escape asks before quitting: Y saves and exits, N (or Escape again) keeps playing. Escape
+// rather than Q, which already drops a key. The game gets no keys while it is open.
+#[derive(Default)]
+struct QuitDialog {
+    open: bool,
+}
+
+// text of the open quit dialog
+struct QuitDialogText;
+
+// ferris reached the exit: plays the "win" animation and ignores movement input
+struct Celebrating;
+
+// named range of the spritesheet (e.g. "win") played instead of the walk cycle. Tags in
+// Spritesheet::one_shot stay on their last frame, the others loop.
+struct PlayAnimation {
+    name: String,
+    // set once a one-shot animation reached its last frame
+    finished: bool,
+}
+
+impl PlayAnimation {
+    fn new(name: &str) -> Self {
+        PlayAnimation {
+            name: name.to_string(),
+            finished: false,
+        }
+    }
+}
+
+// a one-shot animation (tag name) reached its last frame
+struct AnimationFinished(String);
+
+// the exit pulls ferris in on winning: he shrinks to nothing while spinning (toggled
+// with F9). Anything following the win (like loading the next level) should wait for
+// LevelExited.
+struct PortalExit(bool);
+
+impl Default for PortalExit {
+    fn default() -> Self {
+        PortalExit(true)
+    }
+}
+
+// runs while ferris is celebrating, starts once he has visually arrived at the exit
+struct ExitingAnimation {
+    timer: Timer,
+}
+
+const EXIT_ANIMATION_DURATION: f32 = 0.8;
+// full turns over the whole animation
+const EXIT_SPIN: f32 = 1.5;
+
+// ferris disappeared into the exit, see PortalExit
+struct LevelExited;
+
+// command entered in the console
+struct ConsoleCommand(Command);
+
+// solve as if R was pressed (e.g. from the console)
+struct SolveRequested;
+
+// console text (output lines and input line)
+struct ConsoleText;
+
+// solving was requested but there is no path from the current state
+struct NoPath;
+
+// cost of the optimal solution from ferris' current state (None if there is no path)
+#[derive(Default)]
+struct OptimalHint(Option<u32>);
+
+// limits how often update_optimal_hint runs the solver
+struct HintThrottle {
+    timer: Timer,
+    pending: Option<(Ferris, Vec<UVec2>)>,
+    last: Option<Ferris>,
+}
+
+impl Default for HintThrottle {
+    fn default() -> Self {
+        HintThrottle {
+            timer: Timer::from_seconds(0.25, false),
+            pending: None,
+            last: None,
+        }
+    }
+}
+
+// what happens to key and opened door tiles when ferris consumes them
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum ConsumeMode {
+    // remove the tile entity for good
+    Despawn,
+    // swap in FLOOR_TILE and remember the original index, so it can be restored
+    Hide,
+}
+
+impl Default for ConsumeMode {
+    fn default() -> Self {
+        ConsumeMode::Despawn
+    }
+}
+
+// layers and positions of tiles changed this frame. Their chunks are re-meshed once by
+// flush_chunk_updates, no matter how many of their tiles changed.
+#[derive(Default)]
+struct PendingChunkUpdates(HashSet<(u16, UVec2)>);
+
+// everything needed to go back to the moment ferris stepped onto a checkpoint
+struct Checkpoint {
+    ferris: Ferris,
+    grid: TileGrid,
+}
+
+// rewind target of Backspace
+#[derive(Default)]
+struct LastCheckpoint(Option<Checkpoint>);
+
+// The TileGrid as it was when the level was loaded, taken on TileGridReady. Restarts put
+// the map back to it (no matter how tiles were consumed) and SolveFrom::Start solves on it.
+#[derive(Default)]
+struct LevelStart(TileGrid);
+
+// where the map comes from, chosen at startup: the LDTK project or an ASCII map passed on
+// the command line
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum MapSource {
+    Ldtk,
+    // the TileGrid resource is filled in before startup
+    Generated,
+}
+
+impl Default for MapSource {
+    fn default() -> Self {
+        MapSource::Ldtk
+    }
+}
+
+// LDTK projects of the levels, in the order they are played
+const LEVELS: &[&str] = &["labyrinth.ldtk", "labyrinth2.ldtk"];
+
+// index into LEVELS of the loaded level
+#[derive(Default)]
+struct CurrentLevel(usize);
+
+// replace the map with the level at this index into LEVELS
+struct LoadLevel(usize);
+
+// replace the map with this one, e.g. from the load console command
+struct LoadMap(TileGrid);
+
+fn startup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    map_source: Res<MapSource>,
+    current_level: Res<CurrentLevel>,
+    mut loading: ResMut<LoadingState>,
+) {
+    commands
+        .spawn_bundle(OrthographicCameraBundle::new_2d())
+        .insert(ChaseCamera::default())
+        .insert(CameraShake::default());
+    commands.spawn_bundle(UiCameraBundle::default());
+
+    // the handles are kept, so the assets stay loaded for the systems using them later
+    for path in ["ferris2.0.json", "ferris2.0.png", "labyrinth.png"].iter() {
+        loading.tracked.push(asset_server.load_untyped(*path));
+    }
+
+    if *map_source != MapSource::Ldtk {
+        return;
+    }
+    let handle: Handle<LdtkMap> = asset_server.load(LEVELS[current_level.0]);
+    loading.tracked.push(handle.clone_untyped());
+
+    let map_entity = commands.spawn().id();
+
+    commands.entity(map_entity).insert_bundle(LdtkMapBundle {
+        ldtk_map: handle,
+        map: Map::new(0u16, map_entity),
+        transform: Transform::from_xyz(0.0, 0.0, zorder::Layer::Map.z()),
+        ..Default::default()
+    });
+}
+
+fn spawn_loading_screen(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                flex_direction: FlexDirection::ColumnReverse,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            material: materials.add(Color::BLACK.into()),
+            ..Default::default()
+        })
+        .insert(LoadingScreen)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                text: Text::with_section(
+                    "loading",
+                    TextStyle {
+                        font: asset_server.load("fonts/DejaVuSansMono.ttf"),
+                        font_size: 24.0,
+                        color: Color::WHITE,
+                    },
+                    Default::default(),
+                ),
+                ..Default::default()
+            });
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(200.0), Val::Px(12.0)),
+                        margin: Rect::all(Val::Px(8.0)),
+                        ..Default::default()
+                    },
+                    material: materials.add(Color::rgb(0.2, 0.2, 0.2).into()),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    parent
+                        .spawn_bundle(NodeBundle {
+                            style: Style {
+                                size: Size::new(Val::Percent(0.0), Val::Percent(100.0)),
+                                ..Default::default()
+                            },
+                            material: materials.add(Color::rgb(0.9, 0.5, 0.1).into()),
+                            ..Default::default()
+                        })
+                        .insert(LoadingBar);
+                });
+        });
+}
+
+fn update_loading_screen(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut loading: ResMut<LoadingState>,
+    mut bar_query: Query<&mut Style, With<LoadingBar>>,
+    screen_query: Query<Entity, With<LoadingScreen>>,
+) {
+    if loading.loaded {
+        return;
+    }
+    // failed assets are reported by the asset server, don't wait for them forever
+    let done = loading
+        .tracked
+        .iter()
+        .filter(|handle| {
+            matches!(
+                asset_server.get_load_state(handle.id),
+                LoadState::Loaded | LoadState::Failed
+            )
+        })
+        .count();
+    let progress = done as f32 / loading.tracked.len().max(1) as f32;
+    for mut style in bar_query.iter_mut() {
+        style.size.width = Val::Percent(progress * 100.0);
+    }
+    if done < loading.tracked.len() {
+        return;
+    }
+    info!("assets loaded");
+    loading.loaded = true;
+    for entity in screen_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn update_camera(
+    origin: Res<MapOrigin>,
+    windows: Res<Windows>,
+    grid: Res<TileGrid>,
+    tunables: Res<Tunables>,
+    level_meta: Res<LevelMeta>,
+    camera_mode: Res<CameraMode>,
+    aspect_mode: Res<AspectMode>,
+    time: Res<Time>,
+    mut last_target: Local<Option<Vec3>>,
+    mut camera_query: Query<
+        (&mut Transform, &mut ChaseCamera, Option<&Deadzone>),
+        With<bevy::render::camera::Camera>,
+    >,
+    target_query: Query<
+        (&Transform, ChangeTrackers<Transform>),
+        (
+            With<ChaseCameraTarget>,
+            Without<bevy::render::camera::Camera>,
+        ),
+    >,
+) {
+    let window = windows
+        .get_primary()
+        .map(|window| Vec2::new(window.width(), window.height()));
+    // a new level may come with its own zoom. Letterbox bars take away from the window,
+    // zoom out to keep the same view height.
+    let level_scale = level_meta.zoom.unwrap_or(tunables.camera_scale);
+    let level_scale = window.map_or(level_scale, |window| {
+        framing_scale(level_scale, window, *aspect_mode)
+    });
+    if *camera_mode == CameraMode::FrameAll {
+        let targets: Vec<Vec2> = target_query
+            .iter()
+            .map(|(transform, _)| transform.translation.truncate())
+            .collect();
+        let window = match window {
+            Some(window) => window,
+            None => return,
+        };
+        if let Some((center, scale)) = frame_targets(&targets, window, level_scale) {
+            const FRAME_EASE: f32 = 4.0;
+            let ease = (time.delta_seconds() * FRAME_EASE).min(1.0);
+            for (mut camera_transform, _, _) in camera_query.iter_mut() {
+                let translation = camera_transform.translation.truncate();
+                let translation = translation + (center - translation) * ease;
+                camera_transform.translation.x = translation.x;
+                camera_transform.translation.y = translation.y;
+                let scale = camera_transform.scale.x + (scale - camera_transform.scale.x) * ease;
+                camera_transform.scale = Vec3::new(scale, scale, 1.0);
+            }
+        }
+        return;
+    }
+
+    if let Some((target_transform, target_tracker)) = target_query.iter().next() {
+        // direction the target is moving in (zero when idle)
+        let velocity = last_target
+            .map_or(Vec3::ZERO, |last| target_transform.translation - last)
+            .truncate();
+        *last_target = Some(target_transform.translation);
+        let lead_target = velocity.normalize_or_zero() * tunables.camera_lead;
+        const LEAD_EASE: f32 = 3.0;
+        let ease = (time.delta_seconds() * LEAD_EASE).min(1.0);
+        // ease into a new zoom instead of snapping
+        let target_scale = level_scale;
+        const ZOOM_EASE: f32 = 4.0;
+        let zoom_ease = (time.delta_seconds() * ZOOM_EASE).min(1.0);
+
+        for (mut camera_transform, mut follow_camera, deadzone) in camera_query.iter_mut() {
+            follow_camera.lead += (lead_target - follow_camera.lead) * ease;
+            let target_translation = target_transform.translation + follow_camera.lead.extend(0.0);
+            // TODO: zoom out slightly during movement
+            let scale =
+                camera_transform.scale.x + (target_scale - camera_transform.scale.x) * zoom_ease;
+            camera_transform.scale = Vec3::new(scale, scale, 1.0);
+
+            if follow_camera.snap_on_spawn && target_tracker.is_added() {
+                camera_transform.scale = Vec3::new(target_scale, target_scale, 1.0);
+                camera_transform.translation.x = target_translation.x;
+                camera_transform.translation.y = target_translation.y;
+                follow_camera.x_moving = false;
+                follow_camera.y_moving = false;
+            } else if let Some(deadzone) = deadzone {
+                let offset = (target_translation - camera_transform.translation).truncate();
+                let movement = deadzone.follow(offset, camera_transform.scale.x);
+                camera_transform.translation.x += movement.x;
+                camera_transform.translation.y += movement.y;
+                follow_camera.x_moving = false;
+                follow_camera.y_moving = false;
+            } else {
+                let xoffs = target_translation.x - camera_transform.translation.x;
+                let yoffs = target_translation.y - camera_transform.translation.y;
+                let mut xmove = 0.0;
+                let mut ymove = 0.0;
+                let chase_max_distance = tunables.chase_max_distance;
+                // chase with 'hysteresis' per axis:
+                // - start chasing target if it is more than chase_max_distance from center
+                // - if the camera is already chasing keep moving until target is in center
+                if xoffs.abs() > chase_max_distance
+                    || (follow_camera.x_moving && xoffs.abs() >= 1.0)
+                {
+                    xmove = xoffs.signum();
+                }
+                if yoffs.abs() > chase_max_distance
+                    || (follow_camera.y_moving && yoffs.abs() >= 1.0)
+                {
+                    ymove = yoffs.signum();
+                }
+                follow_camera.x_moving = xmove.abs() >= 1.0;
+                follow_camera.y_moving = ymove.abs() >= 1.0;
+                camera_transform.translation.x += xmove;
+                camera_transform.translation.y += ymove;
+            }
+
+            // there is nothing to chase in an axis where the whole map fits into the
+            // view, keep the map centered in that axis instead
+            if let Some(window) = windows.get_primary() {
+                let view_size = Vec2::new(
+                    window.width() * camera_transform.scale.x,
+                    window.height() * camera_transform.scale.y,
+                );
+                let map_size = grid.size().as_f32() * TILE_SIZE;
+                let map_center = origin.0 + map_size * 0.5;
+                if !grid.is_empty() && map_size.x < view_size.x {
+                    camera_transform.translation.x = map_center.x;
+                    follow_camera.x_moving = false;
+                }
+                if !grid.is_empty() && map_size.y < view_size.y {
+                    camera_transform.translation.y = map_center.y;
+                    follow_camera.y_moving = false;
+                }
+            }
+        }
+    }
+}
+
+// an ASCII map passed on the command line, falls back to the LDTK project if there is none
+// or it can't be read
+fn map_source_from_arg(path: Option<String>) -> (MapSource, TileGrid) {
+    match path {
+        Some(path) => match std::fs::read_to_string(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|ascii| Ok(tilegrid_from_ascii(&ascii)?))
+        {
+            Ok(grid) => {
+                info!("using generated map from {}", path);
+                (MapSource::Generated, grid)
+            }
+            Err(err) => {
+                error!("failed to load map {}: {}", path, err);
+                (MapSource::Ldtk, TileGrid::default())
+            }
+        },
+        None => (MapSource::Ldtk, TileGrid::default()),
+    }
+}
+
+fn main() {
+    env_logger::Builder::from_default_env()
+        .filter_level(log::LevelFilter::Info)
+        .init();
+
+    let (mut map_source, mut grid) = map_source_from_arg(std::env::args().nth(1));
+
+    let seed = match std::env::var("FERRIS_SEED").map(|seed| seed.parse::<u64>()) {
+        Ok(Ok(seed)) => seed,
+        Ok(Err(err)) => {
+            error!("invalid FERRIS_SEED: {}", err);
+            DEFAULT_SEED
+        }
+        Err(_) => DEFAULT_SEED,
+    };
+    info!("rng seed: {}", seed);
+    let mut rng = GameRng::from_seed(seed);
+
+    // a random maze instead of the map, e.g. FERRIS_MAZE=20x12 (in cells)
+    match std::env::var("FERRIS_MAZE").map(|size| parse_maze_size(&size)) {
+        Ok(Some(size)) => {
+            grid = generate_maze(size, &mut rng.0);
+            map_source = MapSource::Generated;
+            info!("generated a {}x{} maze", size.x, size.y);
+        }
+        Ok(None) => error!("invalid FERRIS_MAZE, expected e.g. 20x12"),
+        Err(_) => (),
+    }
+
+    // key / door pairs put into a generated map, none by default
+    match std::env::var("FERRIS_LOCKS").map(|pairs| pairs.parse::<usize>()) {
+        Ok(Ok(pairs)) if map_source == MapSource::Generated => {
+            match place_locks(&mut grid, &Rules::default(), &mut rng.0, pairs) {
+                Ok(()) => info!("placed {} key / door pairs", pairs),
+                Err(err) => error!("failed to place key / door pairs: {}", err),
+            }
+        }
+        Ok(Ok(_)) => warn!("FERRIS_LOCKS only applies to generated maps"),
+        Ok(Err(err)) => error!("invalid FERRIS_LOCKS: {}", err),
+        Err(_) => (),
+    }
+
+    // one JSON line per level attempt, off unless a file is given
+    let metrics_path = std::env::var("FERRIS_METRICS")
+        .ok()
+        .map(std::path::PathBuf::from);
+    if let Some(path) = &metrics_path {
+        info!("writing metrics to {}", path.display());
+    }
+
+    let bests = PersonalBests::load(Path::new(BESTS_PATH)).unwrap_or_else(|err| {
+        error!("failed to load personal bests: {}", err);
+        PersonalBests::default()
+    });
+
+    App::new()
+        .insert_resource(WindowDescriptor {
+            width: 1270.0,
+            height: 720.0,
+            title: String::from("LDTK Example"),
+            ..Default::default()
+        })
+        .add_plugins(DefaultPlugins)
+        .add_plugin(TilemapPlugin)
+        .add_plugin(LdtkPlugin)
+        .add_plugin(spritesheet::SpritesheetPlugin)
+        .init_resource::<LoadingState>()
+        .init_resource::<ConsumeMode>()
+        .init_resource::<LevelStart>()
+        .init_resource::<OptimalHint>()
+        .init_resource::<TimeScale>()
+        .init_resource::<PlaybackSpeed>()
+        .init_resource::<Tunables>()
+        .init_resource::<SolveOptions>()
+        .init_resource::<StepMode>()
+        .init_resource::<FocusPause>()
+        .init_resource::<ColorScheme>()
+        .init_resource::<LightRadius>()
+        .init_resource::<RevealAll>()
+        .init_resource::<AspectMode>()
+        .init_resource::<CameraMode>()
+        .init_resource::<KeysAcrossLevels>()
+        .init_resource::<AnimationSync>()
+        .init_resource::<MotionTrail>()
+        .init_resource::<PairPulse>()
+        .init_resource::<TileWobble>()
+        .init_resource::<WallBumpPenalty>()
+        .init_resource::<Lives>()
+        .init_resource::<DoorGlow>()
+        .init_resource::<DecorationFade>()
+        .init_resource::<BreadcrumbLifetime>()
+        .init_resource::<PathRender>()
+        .init_resource::<PlaybackLoops>()
+        .init_resource::<SolveToggles>()
+        .init_resource::<SolveFrom>()
+        .init_resource::<SolveResult>()
+        .init_resource::<AudioMixer>()
+        .init_resource::<PendingChunkUpdates>()
+        .init_resource::<LastCheckpoint>()
+        .init_resource::<MapOrigin>()
+        .init_resource::<Console>()
+        .init_resource::<KeyPopupDuration>()
+        .init_resource::<LevelMeta>()
+        .init_resource::<MovementStyle>()
+        .init_resource::<ShowGrid>()
+        .init_resource::<DebugOverlay>()
+        .init_resource::<EditMode>()
+        .init_resource::<ClickToMove>()
+        .init_resource::<SquashStretch>()
+        .insert_resource(map_source)
+        .insert_resource(rng)
+        .insert_resource(bests)
+        .init_resource::<RunStats>()
+        .insert_resource(MetricsLogger::new(metrics_path))
+        .insert_resource(BestsPath(BESTS_PATH.into()))
+        .insert_resource(grid)
+        .init_resource::<Rules>()
+        .add_event::<NoPath>()
+        .add_event::<GameWon>()
+        .add_event::<WallBump>()
+        .add_event::<LevelFailed>()
+        .add_event::<Died>()
+        .add_event::<GameOver>()
+        .add_event::<KeyDropped>()
+        .add_event::<ConsoleCommand>()
+        .add_event::<SolveRequested>()
+        .add_event::<AnimationFinished>()
+        .add_event::<LevelExited>()
+        .init_resource::<PortalExit>()
+        .add_event::<AddTrauma>()
+        .add_event::<KeyPickedUp>()
+        .add_event::<DoorOpened>()
+        .add_event::<TileGridReady>()
+        .add_event::<LoadLevel>()
+        .add_event::<LoadMap>()
+        .add_event::<MapEdited>()
+        .init_resource::<CurrentLevel>()
+        .add_event::<LevelBalanceWarning>()
+        .add_event::<LevelValidationFailed>()
+        .add_startup_system(startup.system())
+        .add_startup_system(spawn_generated_map.system())
+        .add_startup_system(spawn_console.system())
+        .add_startup_system(ferris_lab::camera::spawn_letterbox_bars.system())
+        .add_startup_system(spawn_loading_screen.system())
+        .add_startup_system(spawn_exit_arrow.system())
+        .add_startup_system(spawn_lives_hud.system())
+        .add_startup_system(spawn_key_inventory.system())
+        .add_startup_system(spawn_tile_tooltip.system())
+        .add_system(update_loading_screen.system())
+        // the console swallows keyboard input while open, so it has to run before every
+        // other system looking at the keyboard
+        .add_system_to_stage(
+            CoreStage::PreUpdate,
+            console_input
+                .system()
+                .label("console_input")
+                .after(bevy::input::InputSystem),
+        )
+        // swallows the keys the same way, an open console gets Escape first
+        .add_system_to_stage(
+            CoreStage::PreUpdate,
+            quit_dialog.system().after("console_input"),
+        )
+        .add_system(run_console_commands.system())
+        .add_system(apply_settings.system())
+        .add_system(update_console_text.system())
+        .add_system(
+            ferris_lab::camera::remove_camera_shake
+                .system()
+                .before("update_camera"),
+        )
+        .add_system(update_camera.system().label("update_camera"))
+        .add_system(
+            ferris_lab::camera::apply_camera_shake
+                .system()
+                .after("update_camera"),
+        )
+        .add_system(detect_win.system())
+        .add_system(shake_on_win.system())
+        .add_system(track_run_stats.system().label("track_run_stats"))
+        .add_system(
+            record_metrics
+                .system()
+                .after("track_run_stats")
+                .before("hide_results"),
+        )
+        .add_system(
+            show_results
+                .system()
+                .after("track_run_stats")
+                .before("hide_results"),
+        )
+        .add_system(hide_results.system().label("hide_results"))
+        .init_resource::<QuitDialog>()
+        .add_system(shake_on_bump.system())
+        .add_system(apply_wall_bump_penalty.system())
+        .add_system(handle_deaths.system())
+        .add_system(update_lives_hud.system())
+        .add_system(update_key_inventory.system())
+        .add_system(restart_failed_level.system())
+        .add_system(start_celebrating.system())
+        .add_system(portal_exit_input.system())
+        .add_system(start_exit_animation.system())
+        .add_system(animate_exit.system().after("move_ferris"))
+        .add_system(stop_exit_animation.system())
+        .add_system(ferris_lab::audio::mixer_input.system())
+        .add_system(play_sound_effects.system())
+        // .add_system(ferris_lab::camera::movement.system())
+        .add_system(ferris_lab::texture::set_texture_filters_to_nearest.system())
+        .add_system(init_ferris.system())
+        .add_system(step_mode_input.system().label("step_mode_input"))
+        .add_system(
+            pause_on_focus_loss
+                .system()
+                .label("pause_on_focus_loss")
+                .after("step_mode_input"),
+        )
+        .add_system_set(
+            SystemSet::new()
+                .with_run_criteria(run_if_stepping.system())
+                .after("pause_on_focus_loss")
+                .with_system(move_ferris.system().label("move_ferris"))
+                .with_system(play_solution.system().label("play_solution"))
+                .with_system(animate_character_system.system()),
+        )
+        .add_system(switch_level.system())
+        .add_system(load_map.system())
+        .add_system(process_loaded_tile_maps.system())
+        .add_system(place_entity_tiles.system())
+        .add_system(update_tile_grid.system())
+        .add_system(snapshot_level_start.system())
+        .add_system(update_key_colors.system())
+        .add_system(check_key_door_balance.system())
+        .add_system(check_start_tiles.system())
+        .add_system(update_start_and_exits.system())
+        .add_system(
+            character_input
+                .system()
+                .label("character_input")
+                .with_run_criteria(run_if_playing.system())
+                .after("pause_on_focus_loss"),
+        )
+        .add_system(
+            solve_input
+                .system()
+                .with_run_criteria(run_if_playing.system())
+                .after("pause_on_focus_loss"),
+        )
+        .add_system(consume_tiles.system().label("consume_tiles"))
+        .add_system(
+            record_checkpoints
+                .system()
+                .after("character_input")
+                .after("play_solution")
+                .after("consume_tiles"),
+        )
+        .add_system(drop_key_input.system())
+        .add_system(place_dropped_keys.system())
+        .add_system(spawn_key_popups.system())
+        .add_system(animate_key_popups.system())
+        .add_system(rewind_input.system())
+        .add_system(time_scale_input.system())
+        .add_system(playback_speed_input.system())
+        .add_system(movement_style_input.system())
+        .add_system(color_scheme_input.system())
+        .add_system(tint_ferris_by_keys.system())
+        .add_system(add_outlines.system())
+        .add_system(sync_outlines.system())
+        .add_system(remove_outlines.system())
+        .add_system(light_radius_input.system())
+        .add_system(reveal_all_input.system())
+        .add_system(ferris_lab::camera::aspect_mode_input.system())
+        .add_system(camera_mode_input.system())
+        .add_system(deadzone_input.system())
+        .add_system(solve_result_input.system())
+        .add_system(keys_across_levels_input.system())
+        .add_system(animation_sync_input.system())
+        .add_system(ferris_lab::camera::update_letterbox_bars.system())
+        .add_system(pair_pulse_input.system())
+        .add_system(tile_wobble_input.system())
+        .add_system(wobble_tiles.system())
+        .add_system(door_glow_input.system())
+        .add_system(update_tile_colors.system())
+        .add_system(fade_decoration_tiles.system())
+        .add_system(raise_decoration_layer.system())
+        .add_system(update_optimal_hint.system())
+        .add_system(show_no_path.system())
+        .add_system(show_level_meta.system())
+        .add_system(ferris_lab::toast::expire_toasts.system())
+        .add_system(map_position.system())
+        .add_system(show_solution.system())
+        .add_system(fade_breadcrumbs.system())
+        .add_system(spawn_dust.system())
+        .add_system(spawn_bursts.system())
+        .add_system(update_dust.system())
+        .add_system(motion_trail_input.system())
+        .add_system(spawn_trail_ghosts.system().after("move_ferris"))
+        .add_system(fade_trail_ghosts.system())
+        .add_system(cull_offscreen.system())
+        .add_system(update_exit_arrow.system().after("update_camera"))
+        .add_system(path_render_input.system())
+        .add_system(show_solution_line.system())
+        .add_system(show_path_highlight.system())
+        .add_system(show_grid_input.system())
+        .add_system(dump_ascii_input.system())
+        .add_system(show_grid.system())
+        .add_system(debug_overlay_input.system())
+        .add_system(show_debug_overlay.system())
+        .add_system(update_tile_tooltip.system().after("update_camera"))
+        .add_system(edit_mode_input.system())
+        .add_system(edit_tiles.system().after("update_camera"))
+        .add_system(click_to_move_input.system())
+        .add_system(click_to_move.system().after("update_camera"))
+        // .add_system(dump_tiles.system())
+        // after all systems that change tiles (and after their commands were applied)
+        .add_system_to_stage(CoreStage::PostUpdate, flush_chunk_updates.system())
+        .run();
+}
+
+fn dump_tiles(tile_query: Query<(&Tile, &TilePos)>) {
+    for (tile, pos) in tile_query.iter() {
+        println!("{:?} {:?}", tile, pos);
+    }
+}
+
+fn pos_to_translation(origin: &MapOrigin, pos: &UVec2, layer: zorder::Layer) -> Vec3 {
+    // Vec3::new(
+    //     (pos.x * 16) as f32 + 8.0,
+    //     ((16 - pos.y) * 16) as f32 * -1.0 + 8.0,
+    //     0.0,
+    // )
+    (cell_origin(origin, pos) + Vec2::splat(TILE_SIZE * 0.5)).extend(layer.z())
+}
+
+const TILE_SIZE: f32 = 16.0;
+
+// lower left corner of the cell at pos
+fn cell_origin(origin: &MapOrigin, pos: &UVec2) -> Vec2 {
+    origin.0 + pos.as_f32() * TILE_SIZE
+}
+
+// Cell containing a world position, None outside of the map (positions are never
+// clamped to the nearest border cell). Rounds down towards the map origin, so a position
+// exactly on a cell border belongs to the cell above / right of the border, like the
+// lower left corner returned by cell_origin.
+fn translation_to_pos(origin: &MapOrigin, grid: &TileGrid, translation: Vec2) -> Option<UVec2> {
+    let cell = ((translation - origin.0) / TILE_SIZE).floor();
+    let cell = IVec2::new(cell.x as i32, cell.y as i32);
+    if grid.in_bounds(cell) {
+        Some(cell.as_u32())
+    } else {
+        None
+    }
+}
+
+// world position of the lower left corner of the map, i.e. of tile (0, 0)
+#[derive(Default)]
+struct MapOrigin(Vec2);
+
+impl MapOrigin {
+    // translation of a tilemap with rows going top-down (like the LDTK ones), putting its
+    // bottom row at the origin
+    fn top_down_translation(&self, rows: u32) -> Vec2 {
+        Vec2::new(self.0.x, self.0.y + rows as f32 * TILE_SIZE)
+    }
+}
+
+const LEVEL_ID: u16 = 0;
+const LAYER_ID: u16 = 1;
+// optional LDTK layer above the gameplay layer, purely cosmetic (not part of the TileGrid)
+const DECORATION_LAYER_ID: u16 = 2;
+
+fn init_ferris(
+    origin: Res<MapOrigin>,
+    mut commands: Commands,
+    // ferris without EndPos has not been initialized yet
+    mut query: Query<(Entity, &mut Ferris), Without<EndPos>>,
+    grid: Res<TileGrid>,
+    asset_server: Res<AssetServer>,
+    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    tunables: Res<Tunables>,
+    marker_query: Query<Entity, With<StartMarker>>,
+) {
+    // the tiles may arrive some frames after ferris, wait for them instead of guessing
+    if grid.is_empty() {
+        return;
+    }
+    for (entity, mut ferris) in query.iter_mut() {
+        // with several start tiles (see check_start_tiles) the lowest row, then leftmost wins
+        let mut start_pos = match grid.find(START_TILE) {
+            Some(pos) => pos,
+            // no guessing, a map without start can't be played (see check_start_tiles)
+            None => continue,
+        };
+        if !is_walkable(grid.get(start_pos)) {
+            if let Some(pos) = nearest_walkable(&grid, start_pos) {
+                warn!(
+                    "start {:?} is blocked, spawning at {:?} instead",
+                    start_pos, pos
+                );
+                start_pos = pos;
+            }
+        }
+        let exits = grid.find_all(END_TILE);
+        if exits.is_empty() {
+            warn!("map has no end tile");
+        }
+
+        info!("ferris added {:?} at {:?}", entity, start_pos);
+
+        // the marker of the previous level goes with it
+        for marker in marker_query.iter() {
+            commands.entity(marker).despawn();
+        }
+        commands
+            .spawn_bundle(SpriteBundle {
+                material: materials.add(asset_server.load("start_marker.png").into()),
+                transform: Transform::from_translation(pos_to_translation(
+                    &origin,
+                    &start_pos,
+                    zorder::Layer::StartMarker,
+                )),
+                ..Default::default()
+            })
+            .insert(StartMarker);
+
+        let desc: Handle<spritesheet::Spritesheet> = asset_server.load("ferris2.0.json");
+        let texture_handle = asset_server.load("ferris2.0.png");
+        let texture_atlas = TextureAtlas::from_grid(texture_handle, Vec2::new(16.0, 16.0), 10, 1);
+        let texture_atlas_handle = texture_atlases.add(texture_atlas);
+
+        let timer = Timer::from_seconds(tunables.animation_interval, true);
+        // timer.pause();
+
+        commands
+            .entity(entity)
+            .insert_bundle(SpriteSheetBundle {
+                texture_atlas: texture_atlas_handle,
+                transform: Transform {
+                    // start in place, so neither ferris nor the camera has to travel there
+                    translation: pos_to_translation(&origin, &start_pos, zorder::Layer::Dynamic),
+                    scale: Vec3::splat(1.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .insert(desc)
+            //            .insert(solution)
+            .insert(EndPos(exits))
+            .insert(StartState(Ferris {
+                pos: start_pos,
+                // carried over from the last level, see KeysAcrossLevels
+                ..ferris.clone()
+            }))
+            .insert(Facing::Right)
+            .insert(Outlined)
+            .insert(timer);
+        ferris.pos = start_pos;
+        // commands.entity(entity).insert_bundle
+    }
+}
+
+fn solve(
+    grid: &TileGrid,
+    rules: &Rules,
+    start_state: Ferris,
+    exits: &[UVec2],
+    options: &SolveOptions,
+) -> VecDeque<Ferris> {
+    if let Some(res) = solve_grid(grid, rules, &start_state, exits, options) {
+        // info!("len: {}", res.1);
+        // for state in res.0.iter() {
+        //     info!("{:?}", state);
+        // }
+        res.0.iter().cloned().collect()
+    } else {
+        error!("no path found");
+        VecDeque::new()
+    }
+}
+
+// read-only counterpart of the R key: only computes the cost, no Solution / playback
+fn update_optimal_hint(
+    time: Res<Time>,
+    mut throttle: Local<HintThrottle>,
+    mut hint: ResMut<OptimalHint>,
+    query: Query<(&Ferris, &EndPos), Changed<Ferris>>,
+    grid: Res<TileGrid>,
+    rules: Res<Rules>,
+    solve_options: Res<SolveOptions>,
+) {
+    for (ferris, end_pos) in query.iter() {
+        // Changed<Ferris> also fires when nothing relevant changed
+        if throttle.last.as_ref() != Some(ferris) {
+            throttle.pending = Some((ferris.clone(), end_pos.0.clone()));
+        }
+    }
+
+    throttle.timer.tick(time.delta());
+    if !throttle.timer.finished() {
+        return;
+    }
+
+    if let Some((ferris, exits)) = throttle.pending.take() {
+        hint.0 = solve_grid(&grid, &rules, &ferris, &exits, &solve_options)
+            .map(|(_, cost)| (cost / ORTHOGONAL_COST) as u32);
+        throttle.last = Some(ferris);
+        throttle.timer.reset();
+    }
+}
+
+fn show_solution(
+    origin: Res<MapOrigin>,
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    solution_query: Query<&Solution, Added<Solution>>,
+    lifetime: Res<BreadcrumbLifetime>,
+    path_render: Res<PathRender>,
+) {
+    if *path_render != PathRender::Crumbs {
+        return;
+    }
+    for solution in solution_query.iter() {
+        let texture_handle = asset_server.load("bread_crumb.png");
+
+        for state in solution.recorded.iter() {
+            info!("{:?}", state);
+            commands
+                .spawn_bundle(SpriteBundle {
+                    material: materials.add(texture_handle.clone().into()),
+                    transform: Transform::from_translation(pos_to_translation(
+                        &origin,
+                        &state.pos,
+                        zorder::Layer::Breadcrumbs,
+                    )),
+                    ..Default::default()
+                })
+                .insert(Breadcrumb {
+                    lifetime: Timer::from_seconds(lifetime.0, false),
+                });
+        }
+    }
+}
+
+fn fade_breadcrumbs(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut query: Query<(Entity, &mut Breadcrumb, &Handle<ColorMaterial>)>,
+) {
+    for (entity, mut breadcrumb, material) in query.iter_mut() {
+        breadcrumb.lifetime.tick(time.delta());
+        if breadcrumb.lifetime.finished() {
+            commands.entity(entity).despawn();
+        } else if let Some(material) = materials.get_mut(material) {
+            // every crumb has its own material, so this only fades this one
+            material.color.set_a(1.0 - breadcrumb.lifetime.percent());
+        }
+    }
+}
+
+fn spawn_dust(
+    origin: Res<MapOrigin>,
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut rng: ResMut<GameRng>,
+    ferris_query: Query<&Ferris, Changed<Ferris>>,
+    particle_query: Query<(), With<DustParticle>>,
+    mut last_pos: Local<Option<UVec2>>,
+) {
+    let ferris = match ferris_query.iter().next() {
+        Some(ferris) => ferris,
+        None => return,
+    };
+    let from = last_pos.replace(ferris.pos);
+    // only single steps, not respawns / rewinds / picking up keys
+    let (from, direction) = match from.and_then(|from| {
+        Direction::from_offset(ferris.pos.as_i32() - from.as_i32()).map(|dir| (from, dir))
+    }) {
+        Some(step) => step,
+        None => return,
+    };
+    let live = particle_query.iter().count();
+    if live >= MAX_DUST_PARTICLES {
+        return;
+    }
+
+    let texture_handle = asset_server.load("bread_crumb.png");
+    let back = -direction.delta().as_f32();
+    // behind ferris at the edge of the cell he is leaving
+    let center = pos_to_translation(&origin, &from, zorder::Layer::Breadcrumbs)
+        + (back * TILE_SIZE * 0.25).extend(0.0);
+    for _ in 0..DUST_PER_STEP.min(MAX_DUST_PARTICLES - live) {
+        let jitter = Vec2::new(rng.0.gen_range(-0.5..=0.5), rng.0.gen_range(-0.5..=0.5));
+        commands
+            .spawn_bundle(SpriteBundle {
+                material: materials.add(ColorMaterial {
+                    color: Color::rgba(0.6, 0.55, 0.45, 0.8),
+                    texture: Some(texture_handle.clone()),
+                }),
+                transform: Transform {
+                    translation: center + (jitter * TILE_SIZE * 0.25).extend(0.0),
+                    scale: Vec3::new(0.5, 0.5, 1.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .insert(DustParticle {
+                velocity: (back + jitter) * DUST_SPEED,
+                lifetime: Timer::from_seconds(DUST_LIFETIME, false),
+            });
+    }
+}
+
+fn spawn_bursts(
+    origin: Res<MapOrigin>,
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut rng: ResMut<GameRng>,
+    tunables: Res<Tunables>,
+    scheme: Res<ColorScheme>,
+    mut key_events: EventReader<KeyPickedUp>,
+    mut door_events: EventReader<DoorOpened>,
+    particle_query: Query<(), With<DustParticle>>,
+) {
+    let bursts: Vec<(UVec2, u8)> = key_events
+        .iter()
+        .map(|event| (event.pos, event.color_id))
+        .chain(door_events.iter().map(|event| (event.pos, event.color_id)))
+        .collect();
+    let mut live = particle_query.iter().count();
+    let texture_handle = asset_server.load("bread_crumb.png");
+    for (pos, color_id) in bursts {
+        let count = tunables
+            .burst_size
+            .min(MAX_BURST_SIZE)
+            .min(MAX_BURST_PARTICLES.saturating_sub(live));
+        live += count;
+        let mut color = key_color(color_id, *scheme);
+        color.set_a(0.8);
+        let center = pos_to_translation(&origin, &pos, zorder::Layer::Breadcrumbs);
+        for i in 0..count {
+            // evenly around the cell, with some jitter in angle and speed
+            let angle =
+                (i as f32 + rng.0.gen_range(0.0..1.0)) / count as f32 * std::f32::consts::TAU;
+            let speed = BURST_SPEED * rng.0.gen_range(0.5..=1.0);
+            commands
+                .spawn_bundle(SpriteBundle {
+                    material: materials.add(ColorMaterial {
+                        color,
+                        texture: Some(texture_handle.clone()),
+                    }),
+                    transform: Transform {
+                        translation: center,
+                        scale: Vec3::new(0.5, 0.5, 1.0),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .insert(DustParticle {
+                    velocity: Vec2::new(angle.cos(), angle.sin()) * speed,
+                    lifetime: Timer::from_seconds(BURST_LIFETIME, false),
+                });
+        }
+    }
+}
+
+fn update_dust(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut query: Query<(
+        Entity,
+        &mut DustParticle,
+        &mut Transform,
+        &Handle<ColorMaterial>,
+    )>,
+) {
+    for (entity, mut particle, mut transform, material) in query.iter_mut() {
+        particle.lifetime.tick(time.delta());
+        if particle.lifetime.finished() {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        transform.translation += (particle.velocity * time.delta_seconds()).extend(0.0);
+        if let Some(material) = materials.get_mut(material) {
+            material
+                .color
+                .set_a(0.8 * (1.0 - particle.lifetime.percent()));
+        }
+    }
+}
+
+fn motion_trail_input(keyboard_input: Res<Input<KeyCode>>, mut motion_trail: ResMut<MotionTrail>) {
+    if keyboard_input.just_pressed(KeyCode::F10) {
+        motion_trail.0 = !motion_trail.0;
+        info!("motion trail: {}", motion_trail.0);
+    }
+}
+
+fn spawn_trail_ghosts(
+    mut commands: Commands,
+    time: Res<Time>,
+    motion_trail: Res<MotionTrail>,
+    mut last_translation: Local<Option<Vec3>>,
+    mut since_spawn: Local<f32>,
+    ferris_query: Query<
+        (&Transform, &TextureAtlasSprite, &Handle<TextureAtlas>),
+        (With<Ferris>, Without<TrailGhost>),
+    >,
+    ghost_query: Query<(), With<TrailGhost>>,
+) {
+    let (transform, sprite, atlas) = match ferris_query.iter().next() {
+        Some(ferris) if motion_trail.0 => ferris,
+        _ => {
+            *last_translation = None;
+            return;
+        }
+    };
+    let last = last_translation.replace(transform.translation);
+    let speed = last.map_or(0.0, |last| {
+        (transform.translation - last).length() / time.delta_seconds().max(f32::EPSILON)
+    });
+    *since_spawn += time.delta_seconds();
+    if speed < TRAIL_MIN_SPEED
+        || *since_spawn < TRAIL_INTERVAL
+        || ghost_query.iter().count() >= MAX_TRAIL_GHOSTS
+    {
+        return;
+    }
+    *since_spawn = 0.0;
+
+    let mut color = sprite.color;
+    color.set_a(0.5);
+    commands
+        .spawn_bundle(SpriteSheetBundle {
+            texture_atlas: atlas.clone(),
+            sprite: TextureAtlasSprite {
+                index: sprite.index,
+                color,
+                ..Default::default()
+            },
+            transform: Transform {
+                // behind ferris
+                translation: transform
+                    .translation
+                    .truncate()
+                    .extend(zorder::Layer::Breadcrumbs.z()),
+                ..*transform
+            },
+            ..Default::default()
+        })
+        .insert(TrailGhost {
+            lifetime: Timer::from_seconds(TRAIL_LIFETIME, false),
+        });
+}
+
+fn fade_trail_ghosts(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut TrailGhost, &mut TextureAtlasSprite)>,
+) {
+    for (entity, mut ghost, mut sprite) in query.iter_mut() {
+        ghost.lifetime.tick(time.delta());
+        if ghost.lifetime.finished() {
+            commands.entity(entity).despawn();
+        } else {
+            sprite.color.set_a(0.5 * (1.0 - ghost.lifetime.percent()));
+        }
+    }
+}
+
+// hides breadcrumbs and overlay sprites outside of the chase camera's view, which adds
+// up on maps much bigger than the screen
+fn cull_offscreen(
+    windows: Res<Windows>,
+    camera_query: Query<&Transform, With<ChaseCamera>>,
+    mut query: Query<
+        (&Transform, &mut Visible),
+        Or<(
+            With<Breadcrumb>,
+            With<PathLine>,
+            With<PathHighlight>,
+            With<DebugOverlayText>,
+        )>,
+    >,
+) {
+    let (window, camera) = match (windows.get_primary(), camera_query.iter().next()) {
+        (Some(window), Some(camera)) => (window, camera),
+        _ => return,
+    };
+    // one tile of margin, so nothing pops in at the border
+    let half_extent = Vec2::new(
+        window.width() * 0.5 * camera.scale.x,
+        window.height() * 0.5 * camera.scale.y,
+    ) + Vec2::splat(TILE_SIZE);
+    let min = camera.translation.truncate() - half_extent;
+    let max = camera.translation.truncate() + half_extent;
+
+    for (transform, mut visible) in query.iter_mut() {
+        let pos = transform.translation.truncate();
+        let on_screen = pos.x >= min.x && pos.x <= max.x && pos.y >= min.y && pos.y <= max.y;
+        // avoid triggering change detection every frame
+        if visible.is_visible != on_screen {
+            visible.is_visible = on_screen;
+        }
+    }
+}
+
+fn spawn_exit_arrow(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    commands
+        .spawn_bundle(SpriteBundle {
+            material: materials.add(asset_server.load("exit_arrow.png").into()),
+            visible: Visible {
+                is_visible: false,
+                is_transparent: true,
+            },
+            ..Default::default()
+        })
+        .insert(ExitArrow);
+}
+
+fn update_exit_arrow(
+    origin: Res<MapOrigin>,
+    windows: Res<Windows>,
+    camera_query: Query<&Transform, (With<ChaseCamera>, Without<ExitArrow>)>,
+    end_query: Query<&EndPos>,
+    mut arrow_query: Query<(&mut Transform, &mut Visible), With<ExitArrow>>,
+) {
+    let (window, camera, end_pos) = match (
+        windows.get_primary(),
+        camera_query.iter().next(),
+        end_query.iter().next(),
+    ) {
+        (Some(window), Some(camera), Some(end_pos)) => (window, camera, end_pos),
+        _ => return,
+    };
+    // keep the arrow one tile inside of the border
+    let half_extent = Vec2::new(
+        window.width() * 0.5 * camera.scale.x,
+        window.height() * 0.5 * camera.scale.y,
+    ) - Vec2::splat(TILE_SIZE);
+    let center = camera.translation.truncate();
+    // the arrow points to the closest exit
+    let dir = match end_pos
+        .0
+        .iter()
+        .map(|pos| pos_to_translation(&origin, pos, zorder::Layer::Overlay).truncate() - center)
+        .min_by(|a, b| a.length_squared().partial_cmp(&b.length_squared()).unwrap())
+    {
+        Some(dir) => dir,
+        None => return,
+    };
+    let on_screen = dir.x.abs() <= half_extent.x && dir.y.abs() <= half_extent.y;
+
+    for (mut transform, mut visible) in arrow_query.iter_mut() {
+        if visible.is_visible == on_screen {
+            visible.is_visible = !on_screen;
+        }
+        if on_screen {
+            continue;
+        }
+        // scale the direction down until it touches the border of the view
+        let scale = (half_extent.x / dir.x.abs()).min(half_extent.y / dir.y.abs());
+        transform.translation = (center + dir * scale).extend(zorder::Layer::Overlay.z());
+        transform.rotation = Quat::from_rotation_z(dir.y.atan2(dir.x));
+    }
+}
+
+fn path_render_input(keyboard_input: Res<Input<KeyCode>>, mut path_render: ResMut<PathRender>) {
+    if keyboard_input.just_pressed(KeyCode::P) {
+        *path_render = match *path_render {
+            PathRender::Crumbs => PathRender::Line,
+            PathRender::Line => PathRender::Crumbs,
+        };
+        info!("path render: {:?}", *path_render);
+    }
+}
+
+// (start, end) of the line segments connecting the cell centers of a path
+fn path_segments(origin: &MapOrigin, path: impl Iterator<Item = UVec2>) -> Vec<(Vec2, Vec2)> {
+    let centers: Vec<Vec2> = path
+        .map(|pos| pos_to_translation(origin, &pos, zorder::Layer::Breadcrumbs).truncate())
+        .collect();
+    centers.windows(2).map(|w| (w[0], w[1])).collect()
+}
+
+fn show_solution_line(
+    origin: Res<MapOrigin>,
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut material: Local<Option<Handle<ColorMaterial>>>,
+    path_render: Res<PathRender>,
+    solution_query: Query<(&Ferris, &Solution, ChangeTrackers<Solution>)>,
+    removed_solutions: RemovedComponents<Solution>,
+    line_query: Query<Entity, With<PathLine>>,
+) {
+    let solution_changed = solution_query
+        .iter()
+        .any(|(_, _, tracker)| tracker.is_changed())
+        || removed_solutions.iter().next().is_some();
+    if !path_render.is_changed() && !solution_changed {
+        return;
+    }
+    for entity in line_query.iter() {
+        commands.entity(entity).despawn();
+    }
+    if *path_render != PathRender::Line {
+        return;
+    }
+    let material = material
+        .get_or_insert_with(|| materials.add(Color::rgba(1.0, 0.8, 0.2, 0.8).into()))
+        .clone();
+
+    for (ferris, solution, _) in solution_query.iter() {
+        // the line starts at ferris and follows the steps that are still ahead
+        let path = std::iter::once(ferris.pos).chain(solution.steps.iter().map(|state| state.pos));
+        for (start, end) in path_segments(&origin, path) {
+            let delta = end - start;
+            let mut transform = Transform::from_translation(
+                ((start + end) * 0.5).extend(zorder::Layer::Breadcrumbs.z()),
+            );
+            transform.rotation = Quat::from_rotation_z(delta.y.atan2(delta.x));
+            commands
+                .spawn_bundle(SpriteBundle {
+                    material: material.clone(),
+                    sprite: Sprite::new(Vec2::new(
+                        delta.length() + PATH_LINE_WIDTH,
+                        PATH_LINE_WIDTH,
+                    )),
+                    transform,
+                    ..Default::default()
+                })
+                .insert(PathLine);
+        }
+    }
+}
+
+fn show_path_highlight(
+    origin: Res<MapOrigin>,
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut material: Local<Option<Handle<ColorMaterial>>>,
+    mut highlighted: Local<Vec<UVec2>>,
+    mut won_events: EventReader<GameWon>,
+    mut ready_events: EventReader<TileGridReady>,
+    solution_query: Query<(&Solution, ChangeTrackers<Solution>)>,
+    highlight_query: Query<Entity, With<PathHighlight>>,
+) {
+    let clear = won_events.iter().count() + ready_events.iter().count() > 0;
+    let changed = solution_query.iter().find(|(solution, tracker)| {
+        tracker.is_added() || (tracker.is_changed() && solution.path != *highlighted)
+    });
+    if !clear && changed.is_none() {
+        return;
+    }
+    for entity in highlight_query.iter() {
+        commands.entity(entity).despawn();
+    }
+    let solution = match changed {
+        // the highlight stays hidden for replays of the same path after the exit
+        Some((solution, _)) if !clear => solution,
+        _ => return,
+    };
+    let material = material
+        .get_or_insert_with(|| materials.add(Color::rgba(1.0, 1.0, 1.0, 0.15).into()))
+        .clone();
+    for pos in solution.path.iter() {
+        commands
+            .spawn_bundle(SpriteBundle {
+                material: material.clone(),
+                sprite: Sprite::new(Vec2::splat(TILE_SIZE)),
+                transform: Transform::from_translation(pos_to_translation(
+                    &origin,
+                    pos,
+                    zorder::Layer::PathHighlight,
+                )),
+                ..Default::default()
+            })
+            .insert(PathHighlight);
+    }
+    *highlighted = solution.path.clone();
+}
+
+fn debug_overlay_input(keyboard_input: Res<Input<KeyCode>>, mut overlay: ResMut<DebugOverlay>) {
+    if keyboard_input.just_pressed(KeyCode::F3) {
+        overlay.0 = !overlay.0;
+    }
+}
+
+// mouse cursor position in the window and the cell under it, see translation_to_pos.
+// None if the cursor is outside of the window or the map.
+fn cursor_cell(
+    origin: &MapOrigin,
+    grid: &TileGrid,
+    windows: &Windows,
+    camera: &Transform,
+) -> Option<(Vec2, UVec2)> {
+    let window = windows.get_primary()?;
+    let cursor = window.cursor_position()?;
+    let window_size = Vec2::new(window.width(), window.height());
+    let world =
+        camera.translation.truncate() + (cursor - window_size * 0.5) * camera.scale.truncate();
+    Some((cursor, translation_to_pos(origin, grid, world)?))
+}
+
+// what a tile does to ferris, for diagnostics
+fn tile_behavior(tile: Option<u16>) -> String {
+    match tile {
+        None | Some(FLOOR_TILE) => "floor".to_string(),
+        Some(START_TILE) => "start".to_string(),
+        Some(END_TILE) => "exit".to_string(),
+        Some(CHECKPOINT_TILE) => "checkpoint".to_string(),
+        Some(tile) => {
+            if let Some(color_id) = door_color_id(tile) {
+                format!("door (color {})", color_id)
+            } else if let Some(color_id) = key_color_id(tile) {
+                format!("key (color {})", color_id)
+            } else {
+                "wall".to_string()
+            }
+        }
+    }
+}
+
+fn spawn_tile_tooltip(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                ..Default::default()
+            },
+            text: Text::with_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/DejaVuSansMono.ttf"),
+                    font_size: 14.0,
+                    color: Color::WHITE,
+                },
+                Default::default(),
+            ),
+            visible: Visible {
+                is_visible: false,
+                is_transparent: true,
+            },
+            ..Default::default()
+        })
+        .insert(TileTooltip);
+}
+
+fn update_tile_tooltip(
+    origin: Res<MapOrigin>,
+    windows: Res<Windows>,
+    overlay: Res<DebugOverlay>,
+    grid: Res<TileGrid>,
+    camera_query: Query<&Transform, With<ChaseCamera>>,
+    mut tooltip_query: Query<(&mut Text, &mut Style, &mut Visible), With<TileTooltip>>,
+) {
+    let hovered = match camera_query.iter().next() {
+        Some(camera) if overlay.0 => cursor_cell(&origin, &grid, &windows, camera),
+        _ => None,
+    };
+
+    for (mut text, mut style, mut visible) in tooltip_query.iter_mut() {
+        let (cursor, cell) = match hovered {
+            Some(hovered) => hovered,
+            None => {
+                visible.is_visible = false;
+                continue;
+            }
+        };
+        let tile = grid.get(cell);
+        let value = format!(
+            "{:?} {} {}",
+            cell,
+            tile.map_or("-".to_string(), |tile| tile.to_string()),
+            tile_behavior(tile)
+        );
+        if text.sections[0].value != value {
+            text.sections[0].value = value;
+        }
+        visible.is_visible = true;
+        style.position = Rect {
+            left: Val::Px(cursor.x + 12.0),
+            bottom: Val::Px(cursor.y + 12.0),
+            ..Default::default()
+        };
+    }
+}
+
+fn click_to_move_input(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut click_to_move: ResMut<ClickToMove>,
+) {
+    if keyboard_input.just_pressed(KeyCode::M) {
+        click_to_move.0 = !click_to_move.0;
+        info!("click to move: {}", click_to_move.0);
+    }
+}
+
+// hovered cell, ferris' state when the preview was computed and the path to the cell
+type MovePreviewState = (UVec2, Ferris, Option<VecDeque<Ferris>>);
+
+fn click_to_move(
+    origin: Res<MapOrigin>,
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut preview_materials: Local<Option<(Handle<ColorMaterial>, Handle<ColorMaterial>)>>,
+    mut preview: Local<Option<MovePreviewState>>,
+    mouse_input: Res<Input<MouseButton>>,
+    windows: Res<Windows>,
+    click_to_move: Res<ClickToMove>,
+    edit_mode: Res<EditMode>,
+    grid: Res<TileGrid>,
+    rules: Res<Rules>,
+    solve_options: Res<SolveOptions>,
+    camera_query: Query<&Transform, With<ChaseCamera>>,
+    mut ferris_query: Query<(Entity, &Ferris, &mut TargetTracker)>,
+    preview_query: Query<Entity, With<MovePreview>>,
+) {
+    let hovered = match camera_query.iter().next() {
+        Some(camera) if click_to_move.0 && !edit_mode.0 => {
+            cursor_cell(&origin, &grid, &windows, camera).map(|(_, cell)| cell)
+        }
+        _ => None,
+    };
+    let ferris = ferris_query.iter_mut().next();
+    let (cell, (ferris_entity, ferris, mut target_tracker)) = match (hovered, ferris) {
+        (Some(cell), Some(ferris)) => (cell, ferris),
+        _ => {
+            if preview.take().is_some() {
+                for entity in preview_query.iter() {
+                    commands.entity(entity).despawn();
+                }
+            }
+            return;
+        }
+    };
+
+    let up_to_date = matches!(&*preview, Some((c, f, _)) if *c == cell && f == ferris);
+    if !up_to_date {
+        for entity in preview_query.iter() {
+            commands.entity(entity).despawn();
+        }
+        // the target is not an exit, so whatever the exit needs does not apply to it
+        let mut rules = rules.clone();
+        rules.exit_condition = ExitCondition::Always;
+        let path = solve_grid(&grid, &rules, ferris, &[cell], &solve_options)
+            .map(|(path, _)| path.into_iter().collect::<VecDeque<_>>());
+
+        let (cell_material, path_material) = preview_materials
+            .get_or_insert_with(|| {
+                (
+                    materials.add(Color::rgba(1.0, 1.0, 0.3, 0.35).into()),
+                    materials.add(Color::rgba(1.0, 1.0, 1.0, 0.1).into()),
+                )
+            })
+            .clone();
+        let path_cells = path.iter().flatten().map(|state| state.pos);
+        let cells = std::iter::once((cell, cell_material)).chain(
+            path_cells
+                .filter(|pos| *pos != cell)
+                .map(|pos| (pos, path_material.clone())),
+        );
+        for (pos, material) in cells {
+            commands
+                .spawn_bundle(SpriteBundle {
+                    material,
+                    sprite: Sprite::new(Vec2::splat(TILE_SIZE)),
+                    transform: Transform::from_translation(pos_to_translation(
+                        &origin,
+                        &pos,
+                        zorder::Layer::PathHighlight,
+                    )),
+                    ..Default::default()
+                })
+                .insert(MovePreview);
+        }
+        *preview = Some((cell, ferris.clone(), path));
+    }
+
+    if !mouse_input.just_pressed(MouseButton::Left) {
+        return;
+    }
+    let mut steps = match preview.as_ref().and_then(|(_, _, path)| path.clone()) {
+        Some(steps) => steps,
+        None => {
+            info!("no path to {:?}", cell);
+            return;
+        }
+    };
+    steps.pop_front();
+    target_tracker.count += 1;
+    commands.entity(ferris_entity).insert(Solution {
+        steps: steps.clone(),
+        expected: ferris.clone(),
+        start: ferris.clone(),
+        start_grid: grid.clone(),
+        path: solution_path(ferris, &steps),
+        recorded: steps,
+        plays: 1,
+        visits: HashMap::new(),
+    });
+}
+
+fn edit_mode_input(keyboard_input: Res<Input<KeyCode>>, mut edit_mode: ResMut<EditMode>) {
+    if keyboard_input.just_pressed(KeyCode::E) {
+        edit_mode.0 = !edit_mode.0;
+        info!("edit mode: {}", edit_mode.0);
+    }
+}
+
+// tile after tile in the editor: floor, wall, start, exit, checkpoint, keys, doors and
+// back to floor
+fn next_edit_tile(tile: Option<u16>) -> Option<u16> {
+    let cycle: Vec<Option<u16>> = [
+        None,
+        Some(WALL_TILE),
+        Some(START_TILE),
+        Some(END_TILE),
+        Some(CHECKPOINT_TILE),
+    ]
+    .iter()
+    .copied()
+    .chain((0..NUM_COLORS).map(|color_id| Some(key_tile(color_id))))
+    .chain((0..NUM_COLORS).map(|color_id| Some(door_tile(color_id))))
+    .collect();
+    let current = match tile {
+        Some(FLOOR_TILE) => None,
+        tile => tile,
+    };
+    // anything else (e.g. wall variants) counts as wall
+    let i = cycle.iter().position(|t| *t == current).unwrap_or(1);
+    cycle[(i + 1) % cycle.len()]
+}
+
+// puts the next tile of the editor cycle on cell, into the level start as well: edits are
+// part of the level, restarts keep them and save writes them
+fn edit_tile(grid: &mut TileGrid, level_start: &mut LevelStart, cell: UVec2) -> Option<u16> {
+    let tile = next_edit_tile(grid.get(cell));
+    grid.set(cell, tile);
+    level_start.0.set(cell, tile);
+    tile
+}
+
+fn edit_tiles(
+    origin: Res<MapOrigin>,
+    mut commands: Commands,
+    mouse_input: Res<Input<MouseButton>>,
+    edit_mode: Res<EditMode>,
+    windows: Res<Windows>,
+    camera_query: Query<&Transform, With<ChaseCamera>>,
+    mut grid: ResMut<TileGrid>,
+    mut level_start: ResMut<LevelStart>,
+    mut map_query: MapQuery,
+    mut pending_chunks: ResMut<PendingChunkUpdates>,
+    mut edited_events: EventWriter<MapEdited>,
+) {
+    if !edit_mode.0 || !mouse_input.just_pressed(MouseButton::Left) {
+        return;
+    }
+    let cell = match camera_query
+        .iter()
+        .next()
+        .and_then(|camera| cursor_cell(&origin, &grid, &windows, camera))
+    {
+        Some((_, cell)) => cell,
+        None => return,
+    };
+    let tile = edit_tile(&mut grid, &mut level_start, cell);
+    info!("edit {:?}: {}", cell, tile_behavior(tile));
+    edited_events.send(MapEdited);
+
+    // walls around the cell may need another variant now
+    for y in -1..=1 {
+        for x in -1..=1 {
+            let n = cell.as_i32() + IVec2::new(x, y);
+            if !grid.in_bounds(n) {
+                continue;
+            }
+            let pos = n.as_u32();
+            let texture_index = match grid.get(pos) {
+                Some(WALL_TILE) => wall_autotile(wall_mask(&grid, pos)),
+                Some(texture_index) if pos == cell => texture_index,
+                None if pos == cell => {
+                    let _ = map_query.despawn_tile(&mut commands, pos.into(), LEVEL_ID, LAYER_ID);
+                    pending_chunks.0.insert((LAYER_ID, pos));
+                    continue;
+                }
+                _ => continue,
+            };
+            let tile = Tile {
+                texture_index,
+                ..Default::default()
+            };
+            let _ = map_query.set_tile(&mut commands, pos.into(), tile, LEVEL_ID, LAYER_ID);
+            pending_chunks.0.insert((LAYER_ID, pos));
+        }
+    }
+}
+
+// cost of entering each cell (tile_cost), 'x' for cells that can't be entered
+fn show_debug_overlay(
+    origin: Res<MapOrigin>,
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    overlay: Res<DebugOverlay>,
+    grid: Res<TileGrid>,
+    text_query: Query<Entity, With<DebugOverlayText>>,
+) {
+    if !overlay.is_changed() && !grid.is_changed() {
+        return;
+    }
+    for entity in text_query.iter() {
+        commands.entity(entity).despawn();
+    }
+    if !overlay.0 {
+        return;
+    }
+    let style = TextStyle {
+        font: asset_server.load("fonts/DejaVuSansMono.ttf"),
+        font_size: 8.0,
+        color: Color::rgba(1.0, 1.0, 1.0, 0.8),
+    };
+    let alignment = TextAlignment {
+        vertical: VerticalAlign::Center,
+        horizontal: HorizontalAlign::Center,
+    };
+    let size = grid.size();
+    for y in 0..size.y {
+        for x in 0..size.x {
+            let pos = UVec2::new(x, y);
+            let label = match tile_cost(grid.get(pos)) {
+                Some(cost) => cost.to_string(),
+                None => "x".to_string(),
+            };
+            commands
+                .spawn_bundle(Text2dBundle {
+                    text: Text::with_section(label, style.clone(), alignment),
+                    transform: Transform::from_translation(pos_to_translation(
+                        &origin,
+                        &pos,
+                        zorder::Layer::Overlay,
+                    )),
+                    ..Default::default()
+                })
+                .insert(DebugOverlayText);
+        }
+    }
+}
+
+fn dump_ascii_input(
+    keyboard_input: Res<Input<KeyCode>>,
+    grid: Res<TileGrid>,
+    ferris_query: Query<&Ferris>,
+) {
+    if keyboard_input.just_pressed(KeyCode::X) {
+        let ferris = ferris_query.iter().next().map(|ferris| ferris.pos);
+        info!("current level:\n{}", tilegrid_to_ascii(&grid, ferris));
+    }
+}
+
+fn show_grid_input(keyboard_input: Res<Input<KeyCode>>, mut show_grid: ResMut<ShowGrid>) {
+    if keyboard_input.just_pressed(KeyCode::G) {
+        show_grid.0 = !show_grid.0;
+    }
+}
+
+fn show_grid(
+    origin: Res<MapOrigin>,
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    show_grid: Res<ShowGrid>,
+    grid: Res<TileGrid>,
+    mut drawn_size: Local<UVec2>,
+    line_query: Query<Entity, With<GridLine>>,
+) {
+    // consuming tiles changes the grid too, only the size matters here
+    if !show_grid.is_changed() && grid.size() == *drawn_size {
+        return;
+    }
+    *drawn_size = grid.size();
+    for entity in line_query.iter() {
+        commands.entity(entity).despawn();
+    }
+    if !show_grid.0 || grid.is_empty() {
+        return;
+    }
+    let size = grid.size();
+    let (min, max) = (
+        cell_origin(&origin, &UVec2::ZERO),
+        cell_origin(&origin, &size),
+    );
+    let center = (min + max) * 0.5;
+    let extent = max - min;
+    let material = materials.add(Color::rgba(1.0, 1.0, 1.0, 0.15).into());
+    let mut spawn_line = |center: Vec2, size: Vec2| {
+        commands
+            .spawn_bundle(SpriteBundle {
+                material: material.clone(),
+                sprite: Sprite::new(size),
+                transform: Transform::from_translation(center.extend(zorder::Layer::Grid.z())),
+                ..Default::default()
+            })
+            .insert(GridLine);
+    };
+    for x in 0..=size.x {
+        let x = cell_origin(&origin, &UVec2::new(x, 0)).x;
+        spawn_line(Vec2::new(x, center.y), Vec2::new(1.0, extent.y));
+    }
+    for y in 0..=size.y {
+        let y = cell_origin(&origin, &UVec2::new(0, y)).y;
+        spawn_line(Vec2::new(center.x, y), Vec2::new(extent.x, 1.0));
+    }
+}
+
+// Single move from the arrow keys pressed in one frame, in the order they were pressed:
+// the last one wins, so ferris never moves diagonally.
+fn resolve_direction<'a>(pressed: impl Iterator<Item = &'a KeyCode>) -> Option<Direction> {
+    pressed
+        .filter_map(|key_code| match key_code {
+            KeyCode::Up => Some(Direction::Up),
+            KeyCode::Down => Some(Direction::Down),
+            KeyCode::Left => Some(Direction::Left),
+            KeyCode::Right => Some(Direction::Right),
+            _ => None,
+        })
+        .last()
+}
+
+// R (or a SolveRequested event) solves the level and starts playback, or cancels a
+// running playback with SolveToggles
+fn solve_input(
+    mut commands: Commands,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut query: Query<
+        (
+            Entity,
+            &Ferris,
+            &EndPos,
+            &mut TargetTracker,
+            Option<&Solution>,
+            &StartState,
+        ),
+        Without<Celebrating>,
+    >,
+    solve_options: Res<SolveOptions>,
+    solve_toggles: Res<SolveToggles>,
+    solve_from: Res<SolveFrom>,
+    mut no_path_events: EventWriter<NoPath>,
+    grid: Res<TileGrid>,
+    level_start: Res<LevelStart>,
+    rules: Res<Rules>,
+    mut solve_events: EventReader<SolveRequested>,
+) {
+    let solve_requested =
+        keyboard_input.just_pressed(KeyCode::R) || solve_events.iter().count() > 0;
+    if !solve_requested {
+        return;
+    }
+    for (ferris_entity, ferris, end_pos, mut target_tracker, solution, start_state) in
+        query.iter_mut()
+    {
+        if solve_toggles.0 && solution.map_or(false, |solution| !solution.steps.is_empty()) {
+            info!("solution playback cancelled");
+            commands.entity(ferris_entity).remove::<Solution>();
+        } else if *solve_from == SolveFrom::Start {
+            // solve on the map as it was at the start
+            let mut solution = solve(
+                &level_start.0,
+                &rules,
+                start_state.0.clone(),
+                &end_pos.0,
+                &solve_options,
+            );
+            if solution.is_empty() {
+                no_path_events.send(NoPath);
+                commands.entity(ferris_entity).remove::<Solution>();
+                continue;
+            }
+            solution.pop_front();
+            target_tracker.count += 1;
+            // nothing played yet: play_solution resets ferris and the map first,
+            // like it does for PlaybackLoops
+            commands.entity(ferris_entity).insert(Solution {
+                steps: VecDeque::new(),
+                expected: ferris.clone(),
+                start: start_state.0.clone(),
+                start_grid: level_start.0.clone(),
+                path: solution_path(&start_state.0, &solution),
+                recorded: solution,
+                plays: 0,
+                visits: HashMap::new(),
+            });
+        } else {
+            let mut solution = solve(&grid, &rules, ferris.clone(), &end_pos.0, &solve_options);
+            // a successful solve contains at least the start state
+            if solution.is_empty() {
+                no_path_events.send(NoPath);
+                commands.entity(ferris_entity).remove::<Solution>();
+                continue;
+            }
+            solution.pop_front();
+            target_tracker.count += 1;
+            commands.entity(ferris_entity).insert(Solution {
+                steps: solution.clone(),
+                expected: ferris.clone(),
+                start: ferris.clone(),
+                start_grid: grid.clone(),
+                path: solution_path(ferris, &solution),
+                recorded: solution,
+                plays: 1,
+                visits: HashMap::new(),
+            });
+        }
+    }
+}
+
+fn character_input(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut keyboard_events: EventReader<KeyboardInput>,
+    mut query: Query<(&mut Ferris, &mut Facing), Without<Celebrating>>,
+    grid: Res<TileGrid>,
+    rules: Res<Rules>,
+    mut key_events: EventWriter<KeyPickedUp>,
+    mut door_events: EventWriter<DoorOpened>,
+    mut bump_events: EventWriter<WallBump>,
+) {
+    // Input only knows which keys are pressed, the events come in the order of the presses.
+    // Keys swallowed by the console are not just pressed.
+    let pressed: Vec<KeyCode> = keyboard_events
+        .iter()
+        .filter(|event| event.state == ElementState::Pressed)
+        .filter_map(|event| event.key_code)
+        .filter(|key_code| keyboard_input.just_pressed(*key_code))
+        .collect();
+    for (mut ferris, mut facing) in query.iter_mut() {
+        let direction = match resolve_direction(pressed.iter()) {
+            Some(direction) => direction,
+            None => continue,
+        };
+        // facing follows the input even if the move turns out to be blocked
+        match direction {
+            Direction::Left => *facing = Facing::Left,
+            Direction::Right => *facing = Facing::Right,
+            _ => (),
+        }
+        let new_pos = ferris.pos.as_i32() + direction.delta();
+        if !grid.in_bounds(new_pos) {
+            continue;
+        }
+        if let Some((new_state, interaction)) = apply_move(&grid, &rules, &ferris, direction) {
+            *ferris = new_state;
+            send_interaction_events(interaction, ferris.pos, &mut key_events, &mut door_events);
+        } else {
+            bump_events.send(WallBump {
+                pos: new_pos.as_u32(),
+            });
+        }
+    }
+}
+
+// ferris got onto a checkpoint, no matter how: manual moves, solution playback or click to
+// move. Runs after the moves and consume_tiles, so the snapshot has the tiles used up on
+// the way.
+fn record_checkpoints(
+    grid: Res<TileGrid>,
+    query: Query<&Ferris, Changed<Ferris>>,
+    mut last_checkpoint: ResMut<LastCheckpoint>,
+) {
+    for ferris in query.iter() {
+        if grid.get(ferris.pos) != Some(CHECKPOINT_TILE) {
+            continue;
+        }
+        // e.g. just rewound to it
+        if matches!(&last_checkpoint.0, Some(checkpoint) if checkpoint.ferris == *ferris) {
+            continue;
+        }
+        info!("checkpoint at {:?}", ferris.pos);
+        last_checkpoint.0 = Some(Checkpoint {
+            ferris: ferris.clone(),
+            grid: grid.clone(),
+        });
+    }
+}
+
+fn shake_on_bump(
+    grid: Res<TileGrid>,
+    mut bump_events: EventReader<WallBump>,
+    mut trauma_events: EventWriter<AddTrauma>,
+) {
+    for event in bump_events.iter() {
+        // ran into a locked door (or an exit that is still closed)
+        if grid.get(event.pos).map_or(false, |tile| {
+            tile == END_TILE || door_color_id(tile).is_some()
+        }) {
+            trauma_events.send(AddTrauma(0.3));
+        }
+    }
+}
+
+fn apply_wall_bump_penalty(
+    grid: Res<TileGrid>,
+    penalty: Res<WallBumpPenalty>,
+    mut bump_events: EventReader<WallBump>,
+    mut died_events: EventWriter<Died>,
+    mut failed_events: EventWriter<LevelFailed>,
+) {
+    for event in bump_events.iter() {
+        if tile_cost(grid.get(event.pos)).is_some() {
+            continue;
+        }
+        match *penalty {
+            WallBumpPenalty::None => (),
+            WallBumpPenalty::Life => died_events.send(Died),
+            WallBumpPenalty::Fail => failed_events.send(LevelFailed),
+        }
+    }
+}
+
+// every lethal event ends up here: respawn at the last checkpoint (or the start) while
+// there are lives left, game over otherwise
+fn handle_deaths(
+    origin: Res<MapOrigin>,
+    mut commands: Commands,
+    mut died_events: EventReader<Died>,
+    mut lives: ResMut<Lives>,
+    last_checkpoint: Res<LastCheckpoint>,
+    mut query: Query<(Entity, &mut Ferris, &mut Transform, &StartState)>,
+    mut map_query: MapQuery,
+    mut grid: ResMut<TileGrid>,
+    level_start: Res<LevelStart>,
+    mut pending_chunks: ResMut<PendingChunkUpdates>,
+    mut game_over_events: EventWriter<GameOver>,
+) {
+    if died_events.iter().count() == 0 || lives.0 == 0 {
+        return;
+    }
+    lives.0 -= 1;
+    if lives.0 == 0 {
+        info!("game over");
+        game_over_events.send(GameOver);
+        return;
+    }
+    info!("died, {} lives left", lives.0);
+    let target = match &last_checkpoint.0 {
+        Some(checkpoint) => &checkpoint.grid,
+        None => &level_start.0,
+    };
+    restore_grid(
+        &mut commands,
+        &mut map_query,
+        &mut grid,
+        &mut pending_chunks,
+        target,
+    );
+    for (entity, mut ferris, mut transform, start_state) in query.iter_mut() {
+        *ferris = match &last_checkpoint.0 {
+            Some(checkpoint) => checkpoint.ferris.clone(),
+            None => start_state.0.clone(),
+        };
+        transform.translation = pos_to_translation(&origin, &ferris.pos, zorder::Layer::Dynamic);
+        commands.entity(entity).remove::<Solution>();
+    }
+}
+
+fn spawn_lives_hud(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(10.0),
+                    right: Val::Px(10.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text::with_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/DejaVuSansMono.ttf"),
+                    font_size: 20.0,
+                    color: Color::rgb(1.0, 0.5, 0.5),
+                },
+                Default::default(),
+            ),
+            ..Default::default()
+        })
+        .insert(LivesText);
+}
+
+fn track_run_stats(
+    time: Res<Time>,
+    loading: Res<LoadingState>,
+    mut stats: ResMut<RunStats>,
+    ferris_query: Query<&Ferris, Changed<Ferris>>,
+    solution_query: Query<(), Added<Solution>>,
+    mut last_pos: Local<Option<UVec2>>,
+) {
+    if stats.started.is_none() && loading.loaded {
+        stats.started = Some(time.seconds_since_startup());
+    }
+    stats.solves += solution_query.iter().count() as u32;
+    if let Some(ferris) = ferris_query.iter().next() {
+        // single steps only, respawns and rewinds are no moves
+        if let Some(last) = last_pos.replace(ferris.pos) {
+            if Direction::from_offset(ferris.pos.as_i32() - last.as_i32()).is_some() {
+                stats.moves += 1;
+            }
+        }
+    }
+}
+
+fn show_results(
+    mut commands: Commands,
+    time: Res<Time>,
+    asset_server: Res<AssetServer>,
+    level_meta: Res<LevelMeta>,
+    stats: Res<RunStats>,
+    mut bests: ResMut<PersonalBests>,
+    bests_path: Res<BestsPath>,
+    mut won_events: EventReader<GameWon>,
+    panel_query: Query<Entity, With<ResultsPanel>>,
+) {
+    if won_events.iter().count() == 0 {
+        return;
+    }
+    let run = Best {
+        moves: stats.moves,
+        seconds: stats.seconds(time.seconds_since_startup()),
+    };
+    let assisted = stats.solves > 0;
+    let (previous, records) = if assisted {
+        // shown for comparison, but no record
+        (
+            bests.0.get(&level_meta.name).copied(),
+            NewRecords::default(),
+        )
+    } else {
+        let (previous, records) = bests.record(&level_meta.name, run);
+        if let Err(err) = bests.save(&bests_path.0) {
+            error!("failed to save personal bests: {}", err);
+        }
+        (previous, records)
+    };
+    info!("run: {:?} best before: {:?} {:?}", run, previous, records);
+
+    let best_or_dash = |best: Option<String>| best.unwrap_or_else(|| "-".to_string());
+    let record = |new: bool| {
+        if new && !records.first {
+            "  new record!"
+        } else {
+            ""
+        }
+    };
+    let lines = format!(
+        "level complete{}\nmoves: {} (best {}){}\ntime: {:.1}s (best {}){}",
+        if assisted {
+            " - solved with help, no record"
+        } else if records.first {
+            " - first clear!"
+        } else {
+            ""
+        },
+        run.moves,
+        best_or_dash(previous.map(|best| best.moves.to_string())),
+        record(records.moves),
+        run.seconds,
+        best_or_dash(previous.map(|best| format!("{:.1}s", best.seconds))),
+        record(records.time),
+    );
+    let color = if !records.first && (records.moves || records.time) {
+        Color::rgb(1.0, 0.85, 0.3)
+    } else {
+        Color::WHITE
+    };
+
+    for entity in panel_query.iter() {
+        commands.entity(entity).despawn();
+    }
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(60.0),
+                    left: Val::Px(20.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text::with_section(
+                lines,
+                TextStyle {
+                    font: asset_server.load("fonts/DejaVuSansMono.ttf"),
+                    font_size: 24.0,
+                    color,
+                },
+                Default::default(),
+            ),
+            ..Default::default()
+        })
+        .insert(ResultsPanel);
+}
+
+// a restart or rewind ends the celebration, and with it the finished run
+fn hide_results(
+    mut commands: Commands,
+    mut stats: ResMut<RunStats>,
+    removed: RemovedComponents<Celebrating>,
+    mut ready_events: EventReader<TileGridReady>,
+    mut failed_events: EventReader<LevelFailed>,
+    mut game_over_events: EventReader<GameOver>,
+    panel_query: Query<Entity, With<ResultsPanel>>,
+) {
+    let new_attempt = removed.iter().count()
+        + ready_events.iter().count()
+        + failed_events.iter().count()
+        + game_over_events.iter().count()
+        > 0;
+    if !new_attempt {
+        return;
+    }
+    *stats = RunStats::default();
+    for entity in panel_query.iter() {
+        commands.entity(entity).despawn();
+    }
+}
+
+fn quit_dialog(
+    mut commands: Commands,
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    asset_server: Res<AssetServer>,
+    bests: Res<PersonalBests>,
+    bests_path: Res<BestsPath>,
+    mut dialog: ResMut<QuitDialog>,
+    mut exit_events: EventWriter<bevy::app::AppExit>,
+    text_query: Query<Entity, With<QuitDialogText>>,
+) {
+    if !dialog.open {
+        if !keyboard_input.just_pressed(KeyCode::Escape) {
+            return;
+        }
+        dialog.open = true;
+        commands
+            .spawn_bundle(TextBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    // below, the results panel is up top
+                    position: Rect {
+                        bottom: Val::Px(20.0),
+                        left: Val::Px(20.0),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                text: Text::with_section(
+                    "quit? (y/n)",
+                    TextStyle {
+                        font: asset_server.load("fonts/DejaVuSansMono.ttf"),
+                        font_size: 24.0,
+                        color: Color::WHITE,
+                    },
+                    Default::default(),
+                ),
+                ..Default::default()
+            })
+            .insert(QuitDialogText);
+        keyboard_input.clear();
+        return;
+    }
+
+    let (yes, no) = (
+        keyboard_input.just_pressed(KeyCode::Y),
+        keyboard_input.just_pressed(KeyCode::N) || keyboard_input.just_pressed(KeyCode::Escape),
+    );
+    keyboard_input.clear();
+    if yes {
+        // bests are saved on every win already, this only catches a failed save
+        if let Err(err) = bests.save(&bests_path.0) {
+            error!("failed to save personal bests: {}", err);
+        }
+        info!("quit");
+        exit_events.send(bevy::app::AppExit);
+    } else if !no {
+        return;
+    }
+    dialog.open = false;
+    for entity in text_query.iter() {
+        commands.entity(entity).despawn();
+    }
+}
+
+fn spawn_key_inventory(mut commands: Commands) {
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(36.0),
+                    right: Val::Px(10.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(KeyInventoryText);
+}
+
+fn update_key_inventory(
+    asset_server: Res<AssetServer>,
+    scheme: Res<ColorScheme>,
+    ferris_query: Query<(&Ferris, ChangeTrackers<Ferris>)>,
+    mut text_query: Query<&mut Text, With<KeyInventoryText>>,
+) {
+    let ferris = match ferris_query.iter().next() {
+        Some((ferris, tracker)) if tracker.is_changed() || scheme.is_changed() => ferris,
+        _ => return,
+    };
+    let font = asset_server.load("fonts/DejaVuSansMono.ttf");
+    for mut text in text_query.iter_mut() {
+        text.sections = key_inventory_sections(&ferris.keys, *scheme)
+            .into_iter()
+            .map(|(value, color)| TextSection {
+                value,
+                style: TextStyle {
+                    font: font.clone(),
+                    font_size: 20.0,
+                    color,
+                },
+            })
+            .collect();
+    }
+}
+
+fn update_lives_hud(
+    lives: Res<Lives>,
+    penalty: Res<WallBumpPenalty>,
+    mut query: Query<(&mut Text, &mut Visible), With<LivesText>>,
+) {
+    if !lives.is_changed() && !penalty.is_changed() {
+        return;
+    }
+    for (mut text, mut visible) in query.iter_mut() {
+        text.sections[0].value = format!("lives: {}", lives.0);
+        visible.is_visible = *penalty == WallBumpPenalty::Life;
+    }
+}
+
+// back to the start with fresh lives
+fn restart_failed_level(
+    origin: Res<MapOrigin>,
+    mut commands: Commands,
+    mut failed_events: EventReader<LevelFailed>,
+    mut game_over_events: EventReader<GameOver>,
+    mut query: Query<(Entity, &mut Ferris, &mut Transform, &StartState)>,
+    mut map_query: MapQuery,
+    mut grid: ResMut<TileGrid>,
+    level_start: Res<LevelStart>,
+    mut pending_chunks: ResMut<PendingChunkUpdates>,
+    mut lives: ResMut<Lives>,
+    mut last_checkpoint: ResMut<LastCheckpoint>,
+    asset_server: Res<AssetServer>,
+) {
+    let game_over = game_over_events.iter().count() > 0;
+    if failed_events.iter().count() == 0 && !game_over {
+        return;
+    }
+    info!("level failed");
+    restore_grid(
+        &mut commands,
+        &mut map_query,
+        &mut grid,
+        &mut pending_chunks,
+        &level_start.0,
+    );
+    for (entity, mut ferris, mut transform, start_state) in query.iter_mut() {
+        *ferris = start_state.0.clone();
+        transform.translation = pos_to_translation(&origin, &ferris.pos, zorder::Layer::Dynamic);
+        commands
+            .entity(entity)
+            .remove::<Solution>()
+            .remove::<Celebrating>()
+            .remove::<PlayAnimation>();
+    }
+    *lives = Lives::default();
+    last_checkpoint.0 = None;
+    ferris_lab::toast::spawn_toast(
+        &mut commands,
+        asset_server.load("fonts/DejaVuSansMono.ttf"),
+        if game_over {
+            "game over"
+        } else {
+            "level failed"
+        },
+        Color::rgb(1.0, 0.4, 0.4),
+    );
+}
+
+fn rewind_input(
+    origin: Res<MapOrigin>,
+    mut commands: Commands,
+    keyboard_input: Res<Input<KeyCode>>,
+    last_checkpoint: Res<LastCheckpoint>,
+    mut query: Query<(Entity, &mut Ferris, &mut Transform)>,
+    mut map_query: MapQuery,
+    mut grid: ResMut<TileGrid>,
+    mut pending_chunks: ResMut<PendingChunkUpdates>,
+) {
+    if !keyboard_input.just_pressed(KeyCode::Back) {
+        return;
+    }
+    let checkpoint = match &last_checkpoint.0 {
+        Some(checkpoint) => checkpoint,
+        None => {
+            info!("no checkpoint to rewind to");
+            return;
+        }
+    };
+    restore_grid(
+        &mut commands,
+        &mut map_query,
+        &mut grid,
+        &mut pending_chunks,
+        &checkpoint.grid,
+    );
+
+    for (entity, mut ferris, mut transform) in query.iter_mut() {
+        *ferris = checkpoint.ferris.clone();
+        transform.translation = pos_to_translation(&origin, &ferris.pos, zorder::Layer::Dynamic);
+        commands
+            .entity(entity)
+            .remove::<Solution>()
+            .remove::<Celebrating>()
+            .remove::<PlayAnimation>();
+    }
+    info!("rewound to checkpoint at {:?}", checkpoint.ferris.pos);
+}
+
+// puts the map back into an earlier state (level start, checkpoint, solution start)
+fn restore_grid(
+    commands: &mut Commands,
+    map_query: &mut MapQuery,
+    grid: &mut TileGrid,
+    pending_chunks: &mut PendingChunkUpdates,
+    target: &TileGrid,
+) {
+    // nothing to go back to while a (re)loaded map is not ready yet
+    if grid.size() != target.size() {
+        return;
+    }
+    // only touch the tiles changed since then
+    let size = grid.size();
+    for y in 0..size.y {
+        for x in 0..size.x {
+            let pos = UVec2::new(x, y);
+            let tile = target.get(pos);
+            if grid.get(pos) == tile {
+                continue;
+            }
+            match tile {
+                Some(texture_index) => {
+                    let tile = Tile {
+                        texture_index,
+                        ..Default::default()
+                    };
+                    let _ = map_query.set_tile(commands, pos.into(), tile, LEVEL_ID, LAYER_ID);
+                }
+                None => {
+                    let _ = map_query.despawn_tile(commands, pos.into(), LEVEL_ID, LAYER_ID);
+                }
+            }
+            pending_chunks.0.insert((LAYER_ID, pos));
+        }
+    }
+    *grid = target.clone();
+}
+
+fn detect_win(
+    query: Query<(&Ferris, &EndPos), Changed<Ferris>>,
+    mut won_events: EventWriter<GameWon>,
+) {
+    for (ferris, end_pos) in query.iter() {
+        if end_pos.0.contains(&ferris.pos) {
+            info!("exit reached");
+            won_events.send(GameWon);
+        }
+    }
+}
+
+fn start_celebrating(
+    mut commands: Commands,
+    mut won_events: EventReader<GameWon>,
+    query: Query<(Entity, &Ferris, &EndPos)>,
+) {
+    if won_events.iter().count() == 0 {
+        return;
+    }
+    for (entity, ferris, end_pos) in query.iter() {
+        if end_pos.0.contains(&ferris.pos) {
+            commands
+                .entity(entity)
+                .insert(Celebrating)
+                .insert(PlayAnimation::new("win"));
+        }
+    }
+}
+
+fn portal_exit_input(keyboard_input: Res<Input<KeyCode>>, mut portal_exit: ResMut<PortalExit>) {
+    if keyboard_input.just_pressed(KeyCode::F9) {
+        portal_exit.0 = !portal_exit.0;
+        info!("portal exit: {}", portal_exit.0);
+    }
+}
+
+fn start_exit_animation(
+    mut commands: Commands,
+    portal_exit: Res<PortalExit>,
+    query: Query<Entity, (Added<Celebrating>, Without<ExitingAnimation>)>,
+) {
+    if !portal_exit.0 {
+        return;
+    }
+    for entity in query.iter() {
+        commands.entity(entity).insert(ExitingAnimation {
+            timer: Timer::from_seconds(EXIT_ANIMATION_DURATION, false),
+        });
+    }
+}
+
+fn animate_exit(
+    origin: Res<MapOrigin>,
+    time: Res<Time>,
+    time_scale: Res<TimeScale>,
+    mut query: Query<(&Ferris, &mut Transform, &mut ExitingAnimation)>,
+    mut exited_events: EventWriter<LevelExited>,
+) {
+    for (ferris, mut transform, mut exiting) in query.iter_mut() {
+        // the win is detected on the move, wait for the walk to the exit to finish
+        let target = pos_to_translation(&origin, &ferris.pos, zorder::Layer::Dynamic);
+        let offset = target - transform.translation;
+        if !offset.x.is_epsilon_zero() || !offset.y.is_epsilon_zero() {
+            continue;
+        }
+        if exiting.timer.finished() {
+            transform.scale = Vec3::new(0.0, 0.0, 1.0);
+            continue;
+        }
+        exiting.timer.tick(time.delta().mul_f32(time_scale.0));
+        let t = exiting.timer.percent();
+        // slow start, then sucked in quickly
+        let scale = 1.0 - t * t;
+        transform.scale = Vec3::new(scale, scale, 1.0);
+        transform.rotation = Quat::from_rotation_z(t * EXIT_SPIN * std::f32::consts::TAU);
+        if exiting.timer.just_finished() {
+            info!("level exited");
+            exited_events.send(LevelExited);
+        }
+    }
+}
+
+// restarts and rewinds end the celebration, bring ferris back to normal with it
+fn stop_exit_animation(
+    mut commands: Commands,
+    mut query: Query<(Entity, &mut Transform), (With<ExitingAnimation>, Without<Celebrating>)>,
+) {
+    for (entity, mut transform) in query.iter_mut() {
+        transform.scale = Vec3::splat(1.0);
+        transform.rotation = Quat::IDENTITY;
+        commands.entity(entity).remove::<ExitingAnimation>();
+    }
+}
+
+// moves, time and solves come from RunStats, so run before hide_results resets it
+fn record_metrics(
+    time: Res<Time>,
+    level_meta: Res<LevelMeta>,
+    stats: Res<RunStats>,
+    mut metrics: ResMut<MetricsLogger>,
+    mut died_events: EventReader<Died>,
+    mut won_events: EventReader<GameWon>,
+    mut failed_events: EventReader<LevelFailed>,
+    mut game_over_events: EventReader<GameOver>,
+) {
+    if !metrics.is_enabled() {
+        return;
+    }
+    metrics.current.deaths += died_events.iter().count() as u32;
+
+    let won = won_events.iter().count() > 0;
+    let failed = failed_events.iter().count() + game_over_events.iter().count() > 0;
+    if won || failed {
+        metrics.current.moves = stats.moves;
+        metrics.current.solves = stats.solves;
+        let seconds = stats.seconds(time.seconds_since_startup());
+        match metrics.finish_attempt(&level_meta.name, won, seconds) {
+            Ok(record) => info!("attempt: {:?}", record),
+            Err(err) => error!("failed to write metrics: {}", err),
+        }
+    }
+}
+
+fn shake_on_win(mut won_events: EventReader<GameWon>, mut trauma_events: EventWriter<AddTrauma>) {
+    if won_events.iter().count() > 0 {
+        trauma_events.send(AddTrauma(0.6));
+    }
+}
+
+fn play_sound_effects(
+    asset_server: Res<AssetServer>,
+    audio: Res<Audio>,
+    mixer: Res<AudioMixer>,
+    mut key_events: EventReader<KeyPickedUp>,
+    mut door_events: EventReader<DoorOpened>,
+    mut won_events: EventReader<GameWon>,
+) {
+    let play = |path: &str| {
+        mixer.play(&audio, asset_server.load(path), SoundCategory::Sfx);
+    };
+    for _ in key_events.iter() {
+        play("sounds/key.wav");
+    }
+    for _ in door_events.iter() {
+        play("sounds/door.wav");
+    }
+    for _ in won_events.iter() {
+        play("sounds/win.wav");
+    }
+}
+
+fn spawn_key_popups(
+    origin: Res<MapOrigin>,
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+    mut atlas: Local<Option<Handle<TextureAtlas>>>,
+    duration: Res<KeyPopupDuration>,
+    mut key_events: EventReader<KeyPickedUp>,
+) {
+    for event in key_events.iter() {
+        let atlas = atlas
+            .get_or_insert_with(|| {
+                let texture_handle = asset_server.load("labyrinth.png");
+                texture_atlases.add(TextureAtlas::from_grid(
+                    texture_handle,
+                    Vec2::splat(TILE_SIZE),
+                    16,
+                    16,
+                ))
+            })
+            .clone();
+        let from = pos_to_translation(&origin, &event.pos, zorder::Layer::Overlay);
+        commands
+            .spawn_bundle(SpriteSheetBundle {
+                texture_atlas: atlas,
+                sprite: TextureAtlasSprite::new(key_tile(event.color_id) as u32),
+                transform: Transform::from_translation(from),
+                ..Default::default()
+            })
+            .insert(KeyPopup {
+                from,
+                timer: Timer::from_seconds(duration.0, false),
+            });
+    }
+}
+
+fn animate_key_popups(
+    mut commands: Commands,
+    time: Res<Time>,
+    ferris_query: Query<&Transform, (With<Ferris>, Without<KeyPopup>)>,
+    mut query: Query<(Entity, &mut KeyPopup, &mut Transform)>,
+) {
+    let target = ferris_query
+        .iter()
+        .next()
+        .map(|transform| transform.translation);
+    for (entity, mut popup, mut transform) in query.iter_mut() {
+        popup.timer.tick(time.delta());
+        if popup.timer.finished() {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        let t = popup.timer.percent();
+        // pop up to 1.5x size, then shrink into ferris
+        let scale = if t < 0.3 {
+            1.0 + t / 0.3 * 0.5
+        } else {
+            1.5 - (t - 0.3) / 0.7 * 1.2
+        };
+        transform.scale = Vec3::new(scale, scale, 1.0);
+        if let Some(target) = target {
+            // ease in, slow at first so the pop is visible
+            let to = Vec3::new(target.x, target.y, popup.from.z);
+            transform.translation = popup.from.lerp(to, t * t);
+        }
+    }
+}
+
+fn send_interaction_events(
+    interaction: Interaction,
+    pos: UVec2,
+    key_events: &mut EventWriter<KeyPickedUp>,
+    door_events: &mut EventWriter<DoorOpened>,
+) {
+    match interaction {
+        Interaction::PickUpKey(color_id) => key_events.send(KeyPickedUp { pos, color_id }),
+        Interaction::OpenDoor(color_id) => door_events.send(DoorOpened { pos, color_id }),
+        Interaction::None => (),
+    }
+}
+
+// removes (or hides) key and door tiles after ferris used them, no matter if he was moved
+// by input or by solution playback
+fn consume_tiles(
+    mut commands: Commands,
+    mut key_events: EventReader<KeyPickedUp>,
+    mut door_events: EventReader<DoorOpened>,
+    mut map_query: MapQuery,
+    consume_mode: Res<ConsumeMode>,
+    mut grid: ResMut<TileGrid>,
+    mut pending_chunks: ResMut<PendingChunkUpdates>,
+) {
+    let keys = key_events.iter().map(|event| event.pos);
+    let doors = door_events.iter().map(|event| event.pos);
+    for pos in keys.chain(doors) {
+        consume_tile(
+            &mut commands,
+            &mut map_query,
+            *consume_mode,
+            &mut grid,
+            &mut pending_chunks,
+            pos.into(),
+        );
+    }
+}
+
+// Q drops the held key onto ferris' cell
+fn drop_key_input(
+    keyboard_input: Res<Input<KeyCode>>,
+    grid: Res<TileGrid>,
+    rules: Res<Rules>,
+    mut query: Query<&mut Ferris, Without<Celebrating>>,
+    mut drop_events: EventWriter<KeyDropped>,
+) {
+    if !keyboard_input.just_pressed(KeyCode::Q) {
+        return;
+    }
+    for mut ferris in query.iter_mut() {
+        match try_drop_key(&rules, &ferris, grid.get(ferris.pos)) {
+            Some((new_state, color_id)) => {
+                *ferris = new_state;
+                drop_events.send(KeyDropped {
+                    pos: ferris.pos,
+                    color_id,
+                });
+            }
+            None => info!("no key to drop here"),
+        }
+    }
+}
+
+fn place_dropped_keys(
+    mut commands: Commands,
+    mut drop_events: EventReader<KeyDropped>,
+    mut map_query: MapQuery,
+    mut grid: ResMut<TileGrid>,
+    mut pending_chunks: ResMut<PendingChunkUpdates>,
+) {
+    for event in drop_events.iter() {
+        grid.set(event.pos, Some(key_tile(event.color_id)));
+        let tile = Tile {
+            texture_index: key_tile(event.color_id),
+            ..Default::default()
+        };
+        let _ = map_query.set_tile(&mut commands, event.pos.into(), tile, LEVEL_ID, LAYER_ID);
+        pending_chunks.0.insert((LAYER_ID, event.pos));
+    }
+}
+
+fn consume_tile(
+    commands: &mut Commands,
+    map_query: &mut MapQuery,
+    consume_mode: ConsumeMode,
+    grid: &mut TileGrid,
+    pending_chunks: &mut PendingChunkUpdates,
+    pos: TilePos,
+) {
+    match consume_mode {
+        ConsumeMode::Despawn => {
+            grid.set(pos.into(), None);
+            let _ = map_query.despawn_tile(commands, pos, LEVEL_ID, LAYER_ID);
+        }
+        ConsumeMode::Hide => {
+            grid.set(pos.into(), Some(FLOOR_TILE));
+            let floor = Tile {
+                texture_index: FLOOR_TILE,
+                ..Default::default()
+            };
+            let _ = map_query.set_tile(commands, pos, floor, LEVEL_ID, LAYER_ID);
+        }
+    }
+    pending_chunks.0.insert((LAYER_ID, pos.into()));
+}
+
+fn flush_chunk_updates(mut pending_chunks: ResMut<PendingChunkUpdates>, mut map_query: MapQuery) {
+    if pending_chunks.0.is_empty() {
+        return;
+    }
+    let mut notified = HashSet::new();
+    for (layer_id, pos) in pending_chunks.0.drain() {
+        // any tile of a chunk is good enough to re-mesh all of it
+        let chunk_size = map_query
+            .get_layer(LEVEL_ID, layer_id)
+            .map(|(_, layer)| layer.settings.chunk_size);
+        if let Some(chunk_size) = chunk_size {
+            if !notified.insert((layer_id, pos / chunk_size)) {
+                continue;
+            }
+        }
+        map_query.notify_chunk_for_tile(pos.into(), LEVEL_ID, layer_id);
+    }
+}
+
+fn spawn_console(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(10.0),
+                    left: Val::Px(10.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text::with_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/DejaVuSansMono.ttf"),
+                    font_size: 16.0,
+                    color: Color::rgb(0.8, 1.0, 0.8),
+                },
+                Default::default(),
+            ),
+            visible: Visible {
+                is_visible: false,
+                is_transparent: true,
+            },
+            ..Default::default()
+        })
+        .insert(ConsoleText);
+}
+
+// ` toggles the console
+fn console_input(
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    mut char_events: EventReader<ReceivedCharacter>,
+    mut console: ResMut<Console>,
+    mut command_events: EventWriter<ConsoleCommand>,
+) {
+    // always read the characters, so nothing typed while closed shows up later
+    let chars: Vec<char> = char_events.iter().map(|event| event.char).collect();
+
+    if keyboard_input.just_pressed(KeyCode::Grave) {
+        console.open = !console.open;
+        console.input.clear();
+        keyboard_input.clear();
+        return;
+    }
+    if !console.open {
+        return;
+    }
+    for c in chars {
+        if !c.is_control() && c != '`' {
+            console.input.push(c);
+        }
+    }
+    if keyboard_input.just_pressed(KeyCode::Back) {
+        console.input.pop();
+    }
+    if keyboard_input.just_pressed(KeyCode::Return) {
+        let line = std::mem::take(&mut console.input);
+        console.print(format!("> {}", line));
+        match parse_command(&line) {
+            Ok(command) => command_events.send(ConsoleCommand(command)),
+            Err(err) => console.print(format!("error: {}", err)),
+        }
+    }
+    keyboard_input.clear();
+}
+
+fn run_console_commands(
+    origin: Res<MapOrigin>,
+    mut commands: Commands,
+    mut command_events: EventReader<ConsoleCommand>,
+    mut console: ResMut<Console>,
+    mut ferris_query: Query<(
+        Entity,
+        &mut Ferris,
+        &mut Transform,
+        &mut TargetTracker,
+        Option<&Solution>,
+        &StartState,
+    )>,
+    grid: Res<TileGrid>,
+    rules: Res<Rules>,
+    level_start: Res<LevelStart>,
+    level_meta: Res<LevelMeta>,
+    map_source: Res<MapSource>,
+    asset_server: Res<AssetServer>,
+    current_level: Res<CurrentLevel>,
+    mut solve_events: EventWriter<SolveRequested>,
+    mut load_level_events: EventWriter<LoadLevel>,
+    mut load_map_events: EventWriter<LoadMap>,
+) {
+    for ConsoleCommand(command) in command_events.iter() {
+        match command {
+            Command::Goto(pos) => {
+                if !grid.in_bounds(pos.as_i32()) {
+                    console.print(format!("error: {:?} is outside of the map", pos));
+                    continue;
+                }
+                for (_, mut ferris, mut transform, ..) in ferris_query.iter_mut() {
+                    ferris.pos = *pos;
+                    transform.translation =
+                        pos_to_translation(&origin, pos, zorder::Layer::Dynamic);
+                }
+            }
+            Command::GiveKey(color_id) => {
+                for (_, mut ferris, ..) in ferris_query.iter_mut() {
+                    ferris.keys[*color_id as usize] += 1;
+                    ferris.collected[*color_id as usize] = true;
+                }
+            }
+            Command::Level(n) => {
+                if *map_source != MapSource::Ldtk {
+                    console.print("error: only LDTK maps have levels");
+                } else if *n >= LEVELS.len() {
+                    console.print(format!("error: there are {} levels", LEVELS.len()));
+                } else {
+                    load_level_events.send(LoadLevel(*n));
+                }
+            }
+            Command::Solve => solve_events.send(SolveRequested),
+            Command::Reload => {
+                if *map_source != MapSource::Ldtk {
+                    console.print("error: only LDTK maps can be reloaded");
+                    continue;
+                }
+                // sends a Modified event once loaded, process_loaded_tile_maps does the rest
+                asset_server.reload_asset(LEVELS[current_level.0]);
+            }
+            Command::Export => {
+                for (_, _, _, _, solution, _) in ferris_query.iter_mut() {
+                    let solution = match solution {
+                        Some(solution) => solution,
+                        None => {
+                            console.print("error: no solution to export, press R first");
+                            continue;
+                        }
+                    };
+                    let path = std::iter::once(&solution.start).chain(solution.recorded.iter());
+                    match Replay::from_path(level_meta.uid, path) {
+                        Ok(replay) => {
+                            let code = encode_replay(&replay);
+                            info!("replay: {}", code);
+                            console.print(code);
+                        }
+                        Err(err) => console.print(format!("error: {}", err)),
+                    }
+                }
+            }
+            Command::Import(code) => {
+                let replay = match decode_replay(code) {
+                    Ok(replay) => replay,
+                    Err(err) => {
+                        console.print(format!("error: {}", err));
+                        continue;
+                    }
+                };
+                if replay.level != level_meta.uid {
+                    console.print(format!("warning: replay is for level {}", replay.level));
+                }
+                // replayed on the map as it was at the start, see SolveFrom::Start
+                for (entity, ferris, _, mut target_tracker, _, start_state) in
+                    ferris_query.iter_mut()
+                {
+                    let mut states: VecDeque<Ferris> =
+                        replay.play(&level_start.0, &rules, &start_state.0).into();
+                    if states.len() != replay.moves.len() + 1 {
+                        console.print("warning: replay is blocked after some moves");
+                    }
+                    states.pop_front();
+                    target_tracker.count += 1;
+                    commands.entity(entity).insert(Solution {
+                        steps: VecDeque::new(),
+                        expected: ferris.clone(),
+                        start: start_state.0.clone(),
+                        start_grid: level_start.0.clone(),
+                        path: solution_path(&start_state.0, &states),
+                        recorded: states,
+                        plays: 0,
+                        visits: HashMap::new(),
+                    });
+                }
+            }
+            Command::Save(path) => {
+                // as the map was at the start, consumed tiles included
+                match std::fs::write(path, tilegrid_to_ascii(&level_start.0, None)) {
+                    Ok(()) => {
+                        info!("saved map to {}", path);
+                        console.print(format!("saved, play it with: ferris_lab {}", path));
+                    }
+                    Err(err) => console.print(format!("error: {}", err)),
+                }
+            }
+            Command::Load(path) => match std::fs::read_to_string(path)
+                .map_err(anyhow::Error::from)
+                .and_then(|ascii| Ok(tilegrid_from_ascii(&ascii)?))
+            {
+                Ok(new_grid) => {
+                    info!("loading map from {}", path);
+                    load_map_events.send(LoadMap(new_grid));
+                }
+                Err(err) => console.print(format!("error: {}", err)),
+            },
+            // see apply_settings
+            Command::Set(..) => (),
+        }
+    }
+}
+
+// value of a `set` option by name
+fn parse_setting<T: Copy>(value: &str, choices: &[(&str, T)]) -> Result<T, String> {
+    choices
+        .iter()
+        .find(|(name, _)| *name == value)
+        .map(|(_, setting)| *setting)
+        .ok_or_else(|| {
+            let names: Vec<&str> = choices.iter().map(|(name, _)| *name).collect();
+            format!("expected one of {}", names.join(", "))
+        })
+}
+
+fn parse_key_order(value: &str) -> Result<OrderConstraint, String> {
+    if value == "none" {
+        return Ok(OrderConstraint::default());
+    }
+    value
+        .split(',')
+        .map(|color_id| match color_id.parse() {
+            Ok(color_id) if color_id < NUM_COLORS => Ok(color_id),
+            _ => Err(format!("expected color ids below {} or none", NUM_COLORS)),
+        })
+        .collect::<Result<_, _>>()
+        .map(OrderConstraint)
+}
+
+// `set <option> <value>` console commands
+fn apply_settings(
+    mut command_events: EventReader<ConsoleCommand>,
+    mut console: ResMut<Console>,
+    mut consume_mode: ResMut<ConsumeMode>,
+    mut penalty: ResMut<WallBumpPenalty>,
+    mut lives: ResMut<Lives>,
+    mut tunables: ResMut<Tunables>,
+    mut rules: ResMut<Rules>,
+    mut focus_pause: ResMut<FocusPause>,
+) {
+    for ConsoleCommand(command) in command_events.iter() {
+        let (option, value) = match command {
+            Command::Set(option, value) => (option.as_str(), value.as_str()),
+            _ => continue,
+        };
+        let result = match option {
+            "consume" => parse_setting(
+                value,
+                &[
+                    ("despawn", ConsumeMode::Despawn),
+                    ("hide", ConsumeMode::Hide),
+                ],
+            )
+            .map(|mode| *consume_mode = mode),
+            "penalty" => parse_setting(
+                value,
+                &[
+                    ("none", WallBumpPenalty::None),
+                    ("life", WallBumpPenalty::Life),
+                    ("fail", WallBumpPenalty::Fail),
+                ],
+            )
+            .map(|new_penalty| *penalty = new_penalty),
+            // single makes Q drop the held key
+            "keycap" => parse_setting(
+                value,
+                &[
+                    ("unlimited", KeyCapacity::Unlimited),
+                    ("single", KeyCapacity::Single),
+                ],
+            )
+            .map(|capacity| rules.key_capacity = capacity),
+            "exit" => parse_setting(
+                value,
+                &[
+                    ("always", ExitCondition::Always),
+                    ("allkeys", ExitCondition::AllKeys),
+                    ("key0", ExitCondition::SpecificKey(0)),
+                    ("key1", ExitCondition::SpecificKey(1)),
+                    ("key2", ExitCondition::SpecificKey(2)),
+                ],
+            )
+            .map(|condition| rules.exit_condition = condition),
+            // comma separated color ids, or none
+            "order" => parse_key_order(value).map(|order| rules.key_order = order),
+            // lives left in the current attempt, with `set penalty life`
+            "lives" => match value.parse() {
+                Ok(count) if count > 0 => {
+                    lives.0 = count;
+                    Ok(())
+                }
+                _ => Err("expected a number of lives above 0".to_string()),
+            },
+            // pause while the window is not focused
+            "focuspause" => parse_setting(value, &[("on", true), ("off", false)])
+                .map(|enabled| focus_pause.enabled = enabled),
+            _ => tunables.set(option, value),
+        };
+        match result {
+            Ok(()) => {
+                info!("set {} {}", option, value);
+                console.print(format!("{} = {}", option, value));
+            }
+            Err(err) => console.print(format!("error: {}", err)),
+        }
+    }
+}
+
+fn update_console_text(
+    console: Res<Console>,
+    mut query: Query<(&mut Text, &mut Visible), With<ConsoleText>>,
+) {
+    if !console.is_changed() {
+        return;
+    }
+    for (mut text, mut visible) in query.iter_mut() {
+        visible.is_visible = console.open;
+        let mut value = String::new();
+        for line in console.output.iter() {
+            value.push_str(line);
+            value.push('\n');
+        }
+        value.push_str(&format!("> {}_", console.input));
+        text.sections[0].value = value;
+    }
+}
+
+fn show_no_path(
+    mut commands: Commands,
+    mut events: EventReader<NoPath>,
+    asset_server: Res<AssetServer>,
+    toast_query: Query<Entity, With<ferris_lab::toast::Toast>>,
+) {
+    if events.iter().count() == 0 {
+        return;
+    }
+    // don't stack toasts when solve is requested repeatedly
+    for entity in toast_query.iter() {
+        commands.entity(entity).despawn();
+    }
+    ferris_lab::toast::spawn_toast(
+        &mut commands,
+        asset_server.load("fonts/DejaVuSansMono.ttf"),
+        "no path to the exit with the current keys",
+        Color::rgb(1.0, 0.4, 0.4),
+    );
+}
+
+// color of key / door color_id (0: red, 1: yellow, 2: blue)
+fn key_color(color_id: u8, scheme: ColorScheme) -> Color {
+    match (scheme, color_id) {
+        (ColorScheme::Default, 0) => Color::rgb(1.0, 0.3, 0.3),
+        (ColorScheme::Default, 1) => Color::rgb(1.0, 0.95, 0.45),
+        (ColorScheme::Default, 2) => Color::rgb(0.3, 0.4, 1.0),
+        // orange / sky blue / white are distinguishable with the common color deficiencies
+        (ColorScheme::HighContrast, 0) => Color::rgb(0.9, 0.6, 0.0),
+        (ColorScheme::HighContrast, 1) => Color::rgb(0.35, 0.7, 0.9),
+        (ColorScheme::HighContrast, 2) => Color::WHITE,
+        _ => Color::WHITE,
+    }
+}
+
+// tint applied to a tile. The tileset is already colored, so only the high contrast
+// scheme needs to recolor keys and doors.
+fn tile_tint(texture_index: u16, scheme: ColorScheme) -> Color {
+    let color_id = match color_id(texture_index) {
+        Some(color_id) => color_id,
+        None => return Color::WHITE,
+    };
+    match scheme {
+        ColorScheme::Default => Color::WHITE,
+        ColorScheme::HighContrast => key_color(color_id, scheme),
+    }
+}
+
+// text and color per held key color for the key inventory. High contrast adds a symbol
+// per color, so the keys can be told apart by shape as well.
+fn key_inventory_sections(keys: &[u32; 3], scheme: ColorScheme) -> Vec<(String, Color)> {
+    (0..keys.len())
+        .filter(|i| keys[*i] > 0)
+        .map(|i| {
+            let symbol = match scheme {
+                ColorScheme::Default => "●",
+                ColorScheme::HighContrast => ["●", "▲", "■"][i],
+            };
+            (
+                format!("{} {} ", symbol, keys[i]),
+                key_color(i as u8, scheme),
+            )
+        })
+        .collect()
+}
+
+// white without keys, otherwise the average of the held key colors blended over white
+fn held_keys_tint(keys: &[u32; 3], scheme: ColorScheme) -> Color {
+    let held: Vec<Vec4> = (0..keys.len())
+        .filter(|i| keys[*i] > 0)
+        .map(|i| Vec4::from(key_color(i as u8, scheme)))
+        .collect();
+    if held.is_empty() {
+        return Color::WHITE;
+    }
+    let average = held.iter().fold(Vec4::ZERO, |acc, c| acc + *c) / held.len() as f32;
+    Color::from(Vec4::ONE.lerp(average, 0.6))
+}
+
+fn add_outlines(
+    mut commands: Commands,
+    query: Query<(Entity, &Handle<TextureAtlas>, &TextureAtlasSprite), Added<Outlined>>,
+) {
+    for (entity, texture_atlas, sprite) in query.iter() {
+        commands.entity(entity).with_children(|parent| {
+            parent
+                .spawn_bundle(SpriteSheetBundle {
+                    texture_atlas: texture_atlas.clone(),
+                    sprite: TextureAtlasSprite {
+                        index: sprite.index,
+                        color: Color::rgba(1.0, 1.0, 0.4, 0.6),
+                        ..Default::default()
+                    },
+                    // just behind the parent
+                    transform: Transform {
+                        translation: Vec3::new(0.0, 0.0, -0.5),
+                        scale: Vec3::splat(OUTLINE_SCALE),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .insert(Outline);
+        });
+    }
+}
+
+// the outline follows the animation of its parent
+fn sync_outlines(
+    parent_query: Query<&TextureAtlasSprite, (With<Outlined>, Without<Outline>)>,
+    mut outline_query: Query<(&Parent, &mut TextureAtlasSprite), With<Outline>>,
+) {
+    for (parent, mut sprite) in outline_query.iter_mut() {
+        if let Ok(parent_sprite) = parent_query.get(parent.0) {
+            if sprite.index != parent_sprite.index {
+                sprite.index = parent_sprite.index;
+            }
+        }
+    }
+}
+
+fn remove_outlines(
+    mut commands: Commands,
+    removed: RemovedComponents<Outlined>,
+    outline_query: Query<(Entity, &Parent), With<Outline>>,
+) {
+    for entity in removed.iter() {
+        for (outline, parent) in outline_query.iter() {
+            if parent.0 == entity {
+                commands.entity(outline).despawn();
+            }
+        }
+    }
+}
+
+fn tint_ferris_by_keys(
+    scheme: Res<ColorScheme>,
+    mut query: QuerySet<(
+        Query<(&Ferris, &mut TextureAtlasSprite)>,
+        Query<(&Ferris, &mut TextureAtlasSprite), Or<(Changed<Ferris>, Added<TextureAtlasSprite>)>>,
+    )>,
+) {
+    if scheme.is_changed() {
+        for (ferris, mut sprite) in query.q0_mut().iter_mut() {
+            sprite.color = held_keys_tint(&ferris.keys, *scheme);
+        }
+    } else {
+        for (ferris, mut sprite) in query.q1_mut().iter_mut() {
+            sprite.color = held_keys_tint(&ferris.keys, *scheme);
+        }
+    }
+}
+
+fn color_scheme_input(keyboard_input: Res<Input<KeyCode>>, mut scheme: ResMut<ColorScheme>) {
+    if keyboard_input.just_pressed(KeyCode::H) {
+        *scheme = match *scheme {
+            ColorScheme::Default => ColorScheme::HighContrast,
+            ColorScheme::HighContrast => ColorScheme::Default,
+        };
+        info!("color scheme: {:?}", *scheme);
+    }
+}
+
+fn light_radius_input(keyboard_input: Res<Input<KeyCode>>, mut light_radius: ResMut<LightRadius>) {
+    if keyboard_input.just_pressed(KeyCode::L) {
+        light_radius.0 = match light_radius.0 {
+            Some(_) => None,
+            None => Some(6.0),
+        };
+        info!("light radius: {:?}", light_radius.0);
+    }
+}
+
+fn reveal_all_input(keyboard_input: Res<Input<KeyCode>>, mut reveal_all: ResMut<RevealAll>) {
+    if keyboard_input.just_pressed(KeyCode::F4) {
+        reveal_all.0 = !reveal_all.0;
+        info!("reveal all: {}", reveal_all.0);
+    }
+}
+
+fn keys_across_levels_input(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut keys_across_levels: ResMut<KeysAcrossLevels>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F7) {
+        keys_across_levels.0 = !keys_across_levels.0;
+        info!("keys across levels: {}", keys_across_levels.0);
+    }
+}
+
+fn animation_sync_input(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut animation_sync: ResMut<AnimationSync>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F8) {
+        *animation_sync = match *animation_sync {
+            AnimationSync::Timed => AnimationSync::PerStep,
+            AnimationSync::PerStep => AnimationSync::Timed,
+        };
+        info!("animation sync: {:?}", *animation_sync);
+    }
+}
+
+fn camera_mode_input(keyboard_input: Res<Input<KeyCode>>, mut camera_mode: ResMut<CameraMode>) {
+    if keyboard_input.just_pressed(KeyCode::F6) {
+        *camera_mode = match *camera_mode {
+            CameraMode::Chase => CameraMode::FrameAll,
+            CameraMode::FrameAll => CameraMode::Chase,
+        };
+        info!("camera mode: {:?}", *camera_mode);
+    }
+}
+
+fn deadzone_input(
+    mut commands: Commands,
+    keyboard_input: Res<Input<KeyCode>>,
+    tunables: Res<Tunables>,
+    camera_query: Query<(Entity, Option<&Deadzone>), With<ChaseCamera>>,
+) {
+    if !keyboard_input.just_pressed(KeyCode::B) {
+        return;
+    }
+    for (entity, deadzone) in camera_query.iter() {
+        if deadzone.is_some() {
+            commands.entity(entity).remove::<Deadzone>();
+            info!("camera deadzone off");
+        } else {
+            let deadzone = Deadzone::square(tunables.chase_max_distance);
+            info!("camera deadzone: {:?}", deadzone);
+            commands.entity(entity).insert(deadzone);
+        }
+    }
+}
+
+fn tile_wobble_input(keyboard_input: Res<Input<KeyCode>>, mut wobble: ResMut<TileWobble>) {
+    if keyboard_input.just_pressed(KeyCode::T) {
+        wobble.0 = !wobble.0;
+    }
+}
+
+fn wobble_tiles(
+    time: Res<Time>,
+    wobble: Res<TileWobble>,
+    tunables: Res<Tunables>,
+    chunk_query: Query<(&Transform, &Handle<Mesh>), With<Chunk>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    if !wobble.0 && !wobble.is_changed() {
+        return;
+    }
+    let t = time.seconds_since_startup() as f32;
+    // turning it off puts every tile back onto its cell
+    let amplitude = if wobble.0 {
+        tunables.wobble_amplitude
+    } else {
+        0.0
+    };
+    for (transform, mesh) in chunk_query.iter() {
+        let positions = match meshes
+            .get_mut(mesh)
+            .and_then(|mesh| mesh.attribute_mut(Mesh::ATTRIBUTE_POSITION))
+        {
+            Some(VertexAttributeValues::Float32x3(positions)) => positions,
+            _ => continue,
+        };
+        let chunk_cell = (transform.translation.truncate() / TILE_SIZE).round();
+        // a quad per tile. Chunks are meshed again whenever a tile changes, so the cell
+        // is worked out from the quad every time instead of being remembered.
+        for quad in positions.chunks_exact_mut(4) {
+            let (min, max) = quad.iter().fold(
+                (Vec2::splat(f32::MAX), Vec2::splat(f32::MIN)),
+                |(min, max), v| {
+                    let v = Vec2::new(v[0], v[1]);
+                    (min.min(v), max.max(v))
+                },
+            );
+            // one tile in mesh units
+            let size = max - min;
+            if size.x <= 0.0 || size.y <= 0.0 {
+                continue;
+            }
+            let local_cell = (min / size).round();
+            let cell = chunk_cell + local_cell;
+            let offset = wobble_offset(IVec2::new(cell.x as i32, cell.y as i32), t, amplitude);
+            let shift = local_cell * size + offset * size / TILE_SIZE - min;
+            for v in quad.iter_mut() {
+                v[0] += shift.x;
+                v[1] += shift.y;
+            }
+        }
+    }
+}
+
+fn pair_pulse_input(keyboard_input: Res<Input<KeyCode>>, mut pulse: ResMut<PairPulse>) {
+    if keyboard_input.just_pressed(KeyCode::K) {
+        pulse.0 = match pulse.0 {
+            Some(_) => None,
+            None => Some(Timer::from_seconds(PAIR_PULSE_DURATION, false)),
+        };
+    }
+}
+
+fn door_glow_input(keyboard_input: Res<Input<KeyCode>>, mut door_glow: ResMut<DoorGlow>) {
+    if keyboard_input.just_pressed(KeyCode::O) {
+        door_glow.0 = !door_glow.0;
+        info!("door glow: {}", door_glow.0);
+    }
+}
+
+// decoration tiles around ferris are faded out, so he can always be seen below them. Only
+// runs when ferris moves, tiles he left get their full alpha back.
+fn fade_decoration_tiles(
+    fade: Res<DecorationFade>,
+    ferris_query: Query<(&Ferris, ChangeTrackers<Ferris>)>,
+    mut tile_query: Query<(&mut Tile, &TilePos, &TileParent)>,
+    mut pending_chunks: ResMut<PendingChunkUpdates>,
+) {
+    let ferris = match ferris_query.iter().next() {
+        Some((ferris, tracker)) if tracker.is_changed() || fade.is_changed() => ferris,
+        _ => return,
+    };
+    for (mut tile, pos, parent) in tile_query.iter_mut() {
+        if parent.layer_id != DECORATION_LAYER_ID {
+            continue;
+        }
+        let d = (UVec2::from(*pos).as_i32() - ferris.pos.as_i32()).abs();
+        let alpha = if d.x.max(d.y) <= 1 { fade.0 } else { 1.0 };
+        if tile.color.a() != alpha {
+            tile.color.set_a(alpha);
+            pending_chunks
+                .0
+                .insert((DECORATION_LAYER_ID, (*pos).into()));
+        }
+    }
+}
+
+// the decoration layer is drawn above ferris instead of stacked right above the map
+fn raise_decoration_layer(mut query: Query<(&Layer, &mut Transform), Added<Layer>>) {
+    for (layer, mut transform) in query.iter_mut() {
+        if layer.settings.layer_id == DECORATION_LAYER_ID {
+            // layers are children of the map, which sits at Layer::Map
+            transform.translation.z = zorder::Layer::Decoration.z() - zorder::Layer::Map.z();
+        }
+    }
+}
+
+fn light_brightness(distance: f32, radius: f32) -> f32 {
+    (1.0 - distance / radius).clamp(0.0, 1.0)
+}
+
+// combines the color scheme tint with the torch light dimming. Empty floor cells have no
+// tile and just show the background.
+fn update_tile_colors(
+    time: Res<Time>,
+    scheme: Res<ColorScheme>,
+    light_radius: Res<LightRadius>,
+    reveal_all: Res<RevealAll>,
+    door_glow: Res<DoorGlow>,
+    rules: Res<Rules>,
+    mut pulse: ResMut<PairPulse>,
+    ferris_query: Query<(&Ferris, ChangeTrackers<Ferris>)>,
+    mut tile_query: Query<(&mut Tile, &TilePos, &TileParent, ChangeTrackers<Tile>)>,
+    mut pending_chunks: ResMut<PendingChunkUpdates>,
+) {
+    // all colors pulse in sync, alpha goes down to 0.2 and back up
+    // (only borrowed mutably while pulsing, so is_changed stays meaningful)
+    let mut pulse_alpha = None;
+    if pulse.0.is_some() {
+        let finished = match pulse.0.as_mut() {
+            Some(timer) => {
+                timer.tick(time.delta());
+                let phase = timer.elapsed_secs() * PAIR_PULSE_FREQUENCY * std::f32::consts::TAU;
+                pulse_alpha = Some(0.6 + 0.4 * phase.cos());
+                timer.finished()
+            }
+            None => false,
+        };
+        if finished {
+            pulse.0 = None;
+            pulse_alpha = None;
+        }
+    }
+
+    let ferris = ferris_query.iter().next();
+    // pulse is changed every frame while pulsing and once more when it ends
+    let update_all = scheme.is_changed()
+        || light_radius.is_changed()
+        || reveal_all.is_changed()
+        || door_glow.is_changed()
+        || pulse.is_changed()
+        || ferris.map_or(false, |(_, tracker)| tracker.is_changed());
+
+    for (mut tile, pos, parent, tracker) in tile_query.iter_mut() {
+        // new tiles always need their color, existing ones only if something changed.
+        // Decoration tiles are left to fade_decoration_tiles.
+        if parent.layer_id != LAYER_ID || (!update_all && !tracker.is_added()) {
+            continue;
+        }
+        let mut color = match (pulse_alpha, color_id(tile.texture_index)) {
+            (Some(alpha), Some(color_id)) => {
+                let mut color = key_color(color_id, *scheme);
+                color.set_a(alpha);
+                color
+            }
+            _ => tile_tint(tile.texture_index, *scheme),
+        };
+        if let (true, Some(color_id), Some((ferris, _))) =
+            (door_glow.0, door_color_id(tile.texture_index), ferris)
+        {
+            let d = (UVec2::from(*pos).as_i32() - ferris.pos.as_i32()).abs();
+            if d.x + d.y == 1
+                && ferris.keys[color_id as usize] >= rules.keys_required(UVec2::from(*pos))
+            {
+                color = Color::rgba(
+                    color.r() * DOOR_GLOW,
+                    color.g() * DOOR_GLOW,
+                    color.b() * DOOR_GLOW,
+                    color.a(),
+                );
+            }
+        }
+        if let (false, Some(radius), Some((ferris, _))) = (reveal_all.0, light_radius.0, ferris) {
+            let d = (UVec2::from(*pos).as_i32() - ferris.pos.as_i32()).abs();
+            let brightness = light_brightness((d.x + d.y) as f32, radius);
+            color = Color::rgba(
+                color.r() * brightness,
+                color.g() * brightness,
+                color.b() * brightness,
+                color.a(),
+            );
+        }
+        if tile.color != color {
+            tile.color = color;
+            pending_chunks.0.insert((LAYER_ID, (*pos).into()));
+        }
+    }
+}
+
+fn play_solution(
+    origin: Res<MapOrigin>,
+    mut commands: Commands,
+    mut query: Query<
+        (Entity, &mut Ferris, &mut Solution, &EndPos, &mut Transform),
+        Changed<TargetTracker>,
+    >,
+    mut map_query: MapQuery,
+    mut grid: ResMut<TileGrid>,
+    rules: Res<Rules>,
+    solve_options: Res<SolveOptions>,
+    playback_loops: Res<PlaybackLoops>,
+    solve_result: Res<SolveResult>,
+    mut pending_chunks: ResMut<PendingChunkUpdates>,
+    mut key_events: EventWriter<KeyPickedUp>,
+    mut door_events: EventWriter<DoorOpened>,
+    mut drop_events: EventWriter<KeyDropped>,
+    mut no_path_events: EventWriter<NoPath>,
+) {
+    for (entity, mut ferris, mut solution, end_pos, mut transform) in query.iter_mut() {
+        if solution.steps.is_empty() {
+            let loop_again = match playback_loops.0 {
+                Some(loops) => solution.plays < loops,
+                None => true,
+            };
+            if !loop_again || solution.recorded.is_empty() || *ferris != solution.expected {
+                continue;
+            }
+            // replay from start: put the map back as it was and jump ferris back
+            restore_grid(
+                &mut commands,
+                &mut map_query,
+                &mut grid,
+                &mut pending_chunks,
+                &solution.start_grid,
+            );
+            commands
+                .entity(entity)
+                .remove::<Celebrating>()
+                .remove::<PlayAnimation>();
+            *ferris = solution.start.clone();
+            transform.translation =
+                pos_to_translation(&origin, &ferris.pos, zorder::Layer::Dynamic);
+            solution.steps = solution.recorded.clone();
+            solution.expected = ferris.clone();
+            solution.plays += 1;
+            solution.visits.clear();
+            info!("replaying solution ({})", solution.plays);
+            continue;
+        }
+        if *ferris != solution.expected {
+            info!("ferris left the solution path, solving again");
+            let mut steps = solve(&grid, &rules, ferris.clone(), &end_pos.0, &solve_options);
+            if steps.pop_front().is_none() {
+                // no looping back to the start either, playback is over
+                no_path_events.send(NoPath);
+                commands.entity(entity).remove::<Solution>();
+                continue;
+            }
+            solution.path = solution_path(&ferris, &steps);
+            solution.steps = steps;
+            solution.expected = ferris.clone();
+        }
+        // info!("next");
+        // timer.tick(time.delta());
+        // walking applies one step per arrival, teleporting all of them at once. The
+        // grid only changes once consume_tiles handled the events, so cells used up by
+        // earlier steps of this frame are tracked here, like the solver does.
+        let mut consumed = HashSet::new();
+        while let Some(next) = solution.steps.pop_front() {
+            if next.pos == ferris.pos {
+                // a step in place is dropping the held key
+                let tile = if consumed.contains(&ferris.pos) {
+                    None
+                } else {
+                    grid.get(ferris.pos)
+                };
+                match try_drop_key(&rules, &ferris, tile) {
+                    Some((new_state, color_id)) => {
+                        *ferris = new_state;
+                        solution.expected = ferris.clone();
+                        drop_events.send(KeyDropped {
+                            pos: ferris.pos,
+                            color_id,
+                        });
+                    }
+                    None => {
+                        warn!("solution can't drop a key here, stopping playback");
+                        solution.steps.clear();
+                    }
+                }
+            } else {
+                // apply the step like a manual move, so tiles get consumed the same way
+                let step = if consumed.contains(&next.pos) {
+                    // plain floor by now
+                    let mut new_state = ferris.clone();
+                    new_state.pos = next.pos;
+                    Some((new_state, Interaction::None))
+                } else {
+                    step_to(&grid, &rules, &ferris, next.pos)
+                };
+                match step {
+                    Some((new_state, interaction)) => {
+                        if interaction != Interaction::None {
+                            consumed.insert(next.pos);
+                        }
+                        *ferris = new_state;
+                        solution.expected = ferris.clone();
+                        send_interaction_events(
+                            interaction,
+                            ferris.pos,
+                            &mut key_events,
+                            &mut door_events,
+                        );
+                    }
+                    None => {
+                        warn!(
+                            "solution step to {:?} is blocked, stopping playback",
+                            next.pos
+                        );
+                        solution.steps.clear();
                     }
                 }
-            } else {
-                successors.push((new_state, 1));
+            }
+            let visits = solution.visits.entry(ferris.clone()).or_insert(0);
+            *visits += 1;
+            if *visits > MAX_STATE_VISITS {
+                warn!(
+                    "solution reached {:?} {} times, stopping playback",
+                    ferris.pos, visits
+                );
+                solution.steps.clear();
+            }
+            if *solve_result == SolveResult::Walk {
+                break;
             }
         }
-        successors
-    };
-    let heuristic = |state: &Ferris| {
-        let d = end_pos.as_i32() - state.pos.as_i32();
-        d.x.abs() + d.y.abs()
-    };
-    let success = |state: &Ferris| state.pos == *end_pos;
-    let res = astar::astar(&start_state, successors, heuristic, success);
+        if *solve_result == SolveResult::Teleport {
+            transform.translation =
+                pos_to_translation(&origin, &ferris.pos, zorder::Layer::Dynamic);
+        }
+    }
+}
 
-    if let Some(res) = res {
-        // info!("len: {}", res.1);
-        // for state in res.0.iter() {
-        //     info!("{:?}", state);
-        // }
-        res.0.iter().cloned().collect()
-    } else {
-        error!("no path found");
-        VecDeque::new()
+trait IsEplsilonZero {
+    fn is_epsilon_zero(&self) -> bool;
+}
+
+impl IsEplsilonZero for f32 {
+    fn is_epsilon_zero(&self) -> bool {
+        self.abs() < f32::EPSILON
     }
 }
 
-fn show_solution(
-    mut commands: Commands,
-    asset_server: Res<AssetServer>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
-    solution_query: Query<&VecDeque<Ferris>, Added<VecDeque<Ferris>>>,
-) {
-    for solution in solution_query.iter() {
-        let texture_handle = asset_server.load("bread_crumb.png");
+fn solve_result_input(keyboard_input: Res<Input<KeyCode>>, mut solve_result: ResMut<SolveResult>) {
+    if keyboard_input.just_pressed(KeyCode::V) {
+        *solve_result = match *solve_result {
+            SolveResult::Walk => SolveResult::Teleport,
+            SolveResult::Teleport => SolveResult::Walk,
+        };
+        info!("solve result: {:?}", *solve_result);
+    }
+}
 
-        for state in solution.iter() {
-            info!("{:?}", state);
-            commands.spawn_bundle(SpriteBundle {
-                material: materials.add(texture_handle.clone().into()),
-                transform: Transform::from_translation(pos_to_translation(&state.pos)),
-                ..Default::default()
-            });
-        }
+fn movement_style_input(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut movement_style: ResMut<MovementStyle>,
+) {
+    if keyboard_input.just_pressed(KeyCode::I) {
+        *movement_style = match *movement_style {
+            MovementStyle::Tweened => MovementStyle::Instant,
+            MovementStyle::Instant => MovementStyle::Tweened,
+        };
+        info!("movement style: {:?}", *movement_style);
     }
 }
 
-fn is_walkable_tile(texture_index: u16) -> bool {
-    let res = (5..=7).contains(&texture_index)
-        || texture_index == START_TILE
-        || texture_index == END_TILE;
-    // info!("{} {}", texture_index, res);
-    res
+fn time_scale_input(keyboard_input: Res<Input<KeyCode>>, mut time_scale: ResMut<TimeScale>) {
+    // pressing the same key again goes back to normal speed
+    let toggle = |current: f32, target: f32| if current == target { 1.0 } else { target };
+
+    if keyboard_input.just_pressed(KeyCode::LBracket) {
+        time_scale.0 = toggle(time_scale.0, 0.25);
+        info!("time scale: {}", time_scale.0);
+    }
+    if keyboard_input.just_pressed(KeyCode::RBracket) {
+        time_scale.0 = toggle(time_scale.0, 4.0);
+        info!("time scale: {}", time_scale.0);
+    }
 }
 
-fn character_input(
+fn playback_speed_input(
     mut commands: Commands,
     keyboard_input: Res<Input<KeyCode>>,
-    mut query: Query<(Entity, &mut Ferris, &mut Timer, &EndPos, &mut TargetTracker)>,
-    tile_query: Query<(&Tile, &TilePos)>,
-    mut map_query: MapQuery,
+    asset_server: Res<AssetServer>,
+    mut playback_speed: ResMut<PlaybackSpeed>,
+    toast_query: Query<Entity, With<ferris_lab::toast::Toast>>,
 ) {
-    for (ferris_entity, mut ferris, mut timer, end_pos, mut target_tracker) in query.iter_mut() {
-        let mut new_x = ferris.pos.x as i32;
-        let mut new_y = ferris.pos.y as i32;
-        for key_code in keyboard_input.get_just_pressed() {
-            match key_code {
-                KeyCode::Up => new_y += 1,
-                KeyCode::Down => new_y -= 1,
-                KeyCode::Left => new_x -= 1,
-                KeyCode::Right => new_x += 1,
-                KeyCode::R => {
-                    let mut solution =
-                        solve(&mut map_query, ferris.clone(), &end_pos.0, &tile_query);
-                    solution.pop_front();
-                    target_tracker.count += 1;
-                    commands.entity(ferris_entity).insert(solution);
-                }
-                _ => (),
-            }
-        }
+    let factor = if keyboard_input.just_pressed(KeyCode::PageUp) {
+        2.0
+    } else if keyboard_input.just_pressed(KeyCode::PageDown) {
+        0.5
+    } else {
+        return;
+    };
+    playback_speed.0 = (playback_speed.0 * factor).clamp(MIN_PLAYBACK_SPEED, MAX_PLAYBACK_SPEED);
+    info!("playback speed: {}", playback_speed.0);
 
-        new_x = new_x.clamp(0, 15);
-        new_y = new_y.clamp(0, 15);
-        let mut can_move = true;
-        let mut despawn = false;
-        let new_pos = TilePos(new_x as u32, new_y as u32);
-        if let Ok(tile_ent) = map_query.get_tile_entity(new_pos, LEVEL_ID, LAYER_ID) {
-            if let Ok((tile, _)) = tile_query.get(tile_ent) {
-                if (5..=7).contains(&tile.texture_index) {
-                    ferris.keys[(tile.texture_index - 5) as usize] = true;
-                    despawn = true;
-                }
-                can_move = is_walkable_tile(tile.texture_index);
-                if (2..=4).contains(&tile.texture_index) {
-                    can_move = ferris.keys[(tile.texture_index - 2) as usize];
-                    despawn = can_move;
-                }
-            }
-        }
+    for entity in toast_query.iter() {
+        commands.entity(entity).despawn();
+    }
+    ferris_lab::toast::spawn_toast(
+        &mut commands,
+        asset_server.load("fonts/DejaVuSansMono.ttf"),
+        &format!("playback speed: {}x", playback_speed.0),
+        Color::WHITE,
+    );
+}
 
-        if despawn {
-            map_query.despawn_tile(&mut commands, new_pos, LEVEL_ID, LAYER_ID);
-            map_query.notify_chunk_for_tile(new_pos, LEVEL_ID, LAYER_ID);
-        }
-        if can_move {
-            ferris.pos = new_pos.into();
-        }
+fn step_mode_input(keyboard_input: Res<Input<KeyCode>>, mut step_mode: ResMut<StepMode>) {
+    if keyboard_input.just_pressed(KeyCode::Comma) {
+        step_mode.active = !step_mode.active;
+        info!("step mode: {}", step_mode.active);
     }
+    step_mode.step_requested = keyboard_input.just_pressed(KeyCode::Period);
 }
 
-fn play_solution(mut query: Query<(&mut Ferris, &mut VecDeque<Ferris>), Changed<TargetTracker>>) {
-    for (mut ferris, mut solution) in query.iter_mut() {
-        // info!("next");
-        // timer.tick(time.delta());
-        if !solution.is_empty() {
-            *ferris = solution.pop_front().unwrap();
+fn pause_on_focus_loss(
+    mut focus_events: EventReader<bevy::window::WindowFocused>,
+    mut focus_pause: ResMut<FocusPause>,
+    mut step_mode: ResMut<StepMode>,
+) {
+    for event in focus_events.iter() {
+        if !focus_pause.enabled {
+            continue;
+        }
+        if !event.focused && !step_mode.active {
+            info!("focus lost, pausing");
+            step_mode.active = true;
+            focus_pause.paused = true;
+        } else if event.focused && focus_pause.paused {
+            // still active, unless it was turned off manually in the meantime
+            if step_mode.active {
+                info!("focus regained, resuming");
+                step_mode.active = false;
+            }
+            focus_pause.paused = false;
         }
     }
 }
 
-trait IsEplsilonZero {
-    fn is_epsilon_zero(&self) -> bool;
+fn run_if_stepping(step_mode: Res<StepMode>) -> ShouldRun {
+    if !step_mode.active || step_mode.step_requested {
+        ShouldRun::Yes
+    } else {
+        ShouldRun::No
+    }
 }
 
-impl IsEplsilonZero for f32 {
-    fn is_epsilon_zero(&self) -> bool {
-        self.abs() < f32::EPSILON
+// input moving ferris: only once loaded, and one move per step in step mode
+fn run_if_playing(loading: Res<LoadingState>, step_mode: Res<StepMode>) -> ShouldRun {
+    if loading.loaded && (!step_mode.active || step_mode.step_requested) {
+        ShouldRun::Yes
+    } else {
+        ShouldRun::No
     }
 }
 
-fn move_ferris(mut query: Query<(&Ferris, &mut Transform, &mut TargetTracker)>) {
-    for (ferris, mut transform, mut target_tracker) in query.iter_mut() {
-        let target_pos = pos_to_translation(&ferris.pos);
+fn move_ferris(
+    origin: Res<MapOrigin>,
+    tunables: Res<Tunables>,
+    time_scale: Res<TimeScale>,
+    playback_speed: Res<PlaybackSpeed>,
+    squash_stretch: Res<SquashStretch>,
+    movement_style: Res<MovementStyle>,
+    time: Res<Time>,
+    mut since_step: Local<f32>,
+    mut query: Query<(
+        &Ferris,
+        &mut Transform,
+        &mut TargetTracker,
+        &mut TextureAtlasSprite,
+        &mut Facing,
+        Option<&Solution>,
+    )>,
+) {
+    // only solution playback is sped up or slowed down, manual moves keep their pace
+    let playing = query.iter().any(|(.., solution)| solution.is_some());
+    let speed = time_scale.0 * if playing { playback_speed.0 } else { 1.0 };
+
+    // instant moves are done right away, so solution playback is paced by time instead
+    const INSTANT_STEP_INTERVAL: f32 = 0.15;
+    *since_step += time.delta_seconds() * speed;
+    let instant_step_due = *since_step >= INSTANT_STEP_INTERVAL;
+    if instant_step_due {
+        *since_step = 0.0;
+    }
+
+    for (ferris, mut transform, mut target_tracker, mut sprite, mut facing, _) in query.iter_mut() {
+        let target_pos = pos_to_translation(&origin, &ferris.pos, zorder::Layer::Dynamic);
 
         let xoffs = target_pos.x - transform.translation.x;
         let yoffs = target_pos.y - transform.translation.y;
 
-        const STEP_SIZE: f32 = 0.5;
+        if *movement_style == MovementStyle::Instant {
+            if !xoffs.is_epsilon_zero() || !yoffs.is_epsilon_zero() {
+                // there is no walk to animate, so turn and take one step of the cycle
+                if !xoffs.is_epsilon_zero() {
+                    *facing = if xoffs < 0.0 {
+                        Facing::Left
+                    } else {
+                        Facing::Right
+                    };
+                }
+                let frames = facing.frames();
+                sprite.index = if frames.contains(&(sprite.index + 1)) {
+                    sprite.index + 1
+                } else {
+                    frames.start
+                };
+                transform.translation = target_pos;
+            }
+            if instant_step_due {
+                target_tracker.count += 1;
+            }
+            transform.scale = Vec3::splat(1.0);
+            continue;
+        }
+
+        let step_size = tunables.step_size * speed;
 
         if !xoffs.is_epsilon_zero() || !yoffs.is_epsilon_zero() {
-            if xoffs.abs() <= STEP_SIZE {
+            if xoffs.abs() <= step_size {
                 transform.translation.x = target_pos.x;
             } else {
-                transform.translation.x += xoffs.signum() * STEP_SIZE;
+                transform.translation.x += xoffs.signum() * step_size;
             }
-            if yoffs.abs() <= STEP_SIZE {
+            if yoffs.abs() <= step_size {
                 transform.translation.y = target_pos.y;
             } else {
-                transform.translation.y += yoffs.signum() * STEP_SIZE;
+                transform.translation.y += yoffs.signum() * step_size;
             }
         }
 
@@ -370,32 +4690,104 @@ fn move_ferris(mut query: Query<(&Ferris, &mut Transform, &mut TargetTracker)>)
 
         if xoffs.is_epsilon_zero() && yoffs.is_epsilon_zero() {
             target_tracker.count += 1;
+            transform.scale = Vec3::splat(1.0);
+        } else {
+            // progress through the current step: squash at 0.0, stretch towards 1.0
+            let progress = 1.0 - (xoffs.abs().max(yoffs.abs()) / 16.0).min(1.0);
+            let s = squash_stretch.amplitude * (progress * std::f32::consts::PI).cos();
+            transform.scale = Vec3::new(1.0 + s, 1.0 - s, 1.0);
         }
     }
 }
 
 fn animate_character_system(
+    origin: Res<MapOrigin>,
+    tunables: Res<Tunables>,
     time: Res<Time>,
-    mut query: Query<(&Ferris, &mut Transform, &mut TextureAtlasSprite, &mut Timer)>,
+    time_scale: Res<TimeScale>,
+    animation_sync: Res<AnimationSync>,
+    spritesheets: Res<Assets<spritesheet::Spritesheet>>,
+    mut was_moving: Local<bool>,
+    mut query: Query<(
+        &Ferris,
+        &mut Transform,
+        &mut TextureAtlasSprite,
+        &mut Timer,
+        &mut Facing,
+        &Handle<spritesheet::Spritesheet>,
+        Option<&mut PlayAnimation>,
+    )>,
+    mut finished_events: EventWriter<AnimationFinished>,
 ) {
-    for (ferris, transform, mut sprite, mut timer) in query.iter_mut() {
-        timer.tick(time.delta());
-        if timer.just_finished() {
-            let target_pos = pos_to_translation(&ferris.pos);
+    for (ferris, transform, mut sprite, mut timer, mut facing, sheet, animation) in query.iter_mut()
+    {
+        // the timer runs for the duration of the current frame, as set in the spritesheet
+        let frame_duration = spritesheets
+            .get(sheet)
+            .and_then(|sheet| sheet.durations.get(sprite.index as usize))
+            .map(|ms| std::time::Duration::from_millis(*ms as u64))
+            .unwrap_or_else(|| std::time::Duration::from_secs_f32(tunables.animation_interval));
+        if timer.duration() != frame_duration {
+            timer.set_duration(frame_duration);
+        }
+        timer.tick(time.delta().mul_f32(time_scale.0));
+        if let Some(mut animation) = animation {
+            // the walk cycle is off while it plays, also with AnimationSync::PerStep
+            if !timer.just_finished() || animation.finished {
+                continue;
+            }
+            // the range from the spritesheet, or just stand still on the idle frame
+            let idle = facing.frames().start;
+            let sheet = spritesheets.get(sheet);
+            let frames = sheet
+                .and_then(|sheet| sheet.ranges.get(&animation.name).cloned())
+                .unwrap_or(idle..idle + 1);
+            let one_shot = sheet.map_or(false, |sheet| sheet.one_shot.contains(&animation.name));
+            if one_shot && sprite.index + 1 >= frames.end && frames.contains(&sprite.index) {
+                // stay on the last frame
+                animation.finished = true;
+                finished_events.send(AnimationFinished(animation.name.clone()));
+                continue;
+            }
+            sprite.index += 1;
+            if !frames.contains(&sprite.index) {
+                sprite.index = frames.start;
+            }
+        } else {
+            let target_pos = pos_to_translation(&origin, &ferris.pos, zorder::Layer::Dynamic);
 
             let xoffs = target_pos.x - transform.translation.x;
             let yoffs = target_pos.y - transform.translation.y;
+            let moving = !xoffs.is_epsilon_zero() || !yoffs.is_epsilon_zero();
+            let step_completed = *was_moving && !moving;
+            *was_moving = moving;
 
-            if !xoffs.is_epsilon_zero() || !yoffs.is_epsilon_zero() {
-                sprite.index += 1;
+            let (update, advance) = match *animation_sync {
+                AnimationSync::Timed => (timer.just_finished(), timer.just_finished() && moving),
+                AnimationSync::PerStep => (true, step_completed),
+            };
+            if !update {
+                continue;
+            }
 
-                if xoffs.signum().is_negative() {
-                    if !(0..4).contains(&sprite.index) {
-                        sprite.index = 0;
-                    }
-                } else if !(4..8).contains(&sprite.index) {
-                    sprite.index = 4;
+            // moves not caused by input (e.g. solution playback) turn ferris as well
+            if !xoffs.is_epsilon_zero() {
+                *facing = if xoffs.signum().is_negative() {
+                    Facing::Left
+                } else {
+                    Facing::Right
+                };
+            }
+
+            let frames = facing.frames();
+            if advance {
+                sprite.index += 1;
+                if !frames.contains(&sprite.index) {
+                    sprite.index = frames.start;
                 }
+            } else if !frames.contains(&sprite.index) {
+                // idle, e.g. after bumping into a wall: just turn around
+                sprite.index = frames.start;
             }
         }
     }
@@ -407,24 +4799,31 @@ fn process_loaded_tile_maps(
     mut commands: Commands,
     mut map_events: EventReader<AssetEvent<LdtkMap>>,
     maps: Res<Assets<LdtkMap>>,
-    mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<ColorMaterial>>,
     mut query: Query<(Entity, &Handle<LdtkMap>, &mut Map, &mut Transform)>,
-    new_maps: Query<&Handle<LdtkMap>, Added<Handle<LdtkMap>>>,
-    layer_query: Query<&Layer>,
-    chunk_query: Query<&Chunk>,
+    // also replaced handles, a level that is loaded already sends no Created event
+    new_maps: Query<&Handle<LdtkMap>, Changed<Handle<LdtkMap>>>,
+    mut level_meta: ResMut<LevelMeta>,
+    mut rules: ResMut<Rules>,
+    keys_across_levels: Res<KeysAcrossLevels>,
     ferris_query: Query<(Entity, &Ferris)>,
+    placed_query: Query<Entity, With<PlacedTile>>,
+    mut grid: ResMut<TileGrid>,
+    mut last_checkpoint: ResMut<LastCheckpoint>,
+    mut grid_ready_events: EventWriter<TileGridReady>,
+    origin: Res<MapOrigin>,
 ) {
-    let mut changed_maps = Vec::<Handle<LdtkMap>>::default();
+    // with whether it is the same map loaded again
+    let mut changed_maps = Vec::<(Handle<LdtkMap>, bool)>::default();
     for event in map_events.iter() {
         match event {
             AssetEvent::Created { handle } => {
                 log::info!("Map added!");
-                changed_maps.push(handle.clone());
+                changed_maps.push((handle.clone(), false));
             }
             AssetEvent::Modified { handle } => {
                 log::info!("Map changed!");
-                changed_maps.push(handle.clone());
+                changed_maps.push((handle.clone(), true));
             }
             AssetEvent::Removed { handle } => {
                 log::info!("Map removed!");
@@ -432,7 +4831,7 @@ fn process_loaded_tile_maps(
                 // events are ordered so future modification events are ok
                 changed_maps = changed_maps
                     .into_iter()
-                    .filter(|changed_handle| changed_handle == handle)
+                    .filter(|(changed_handle, _)| changed_handle == handle)
                     .collect();
             }
         }
@@ -440,17 +4839,22 @@ fn process_loaded_tile_maps(
 
     // If we have new map entities add them to the changed_maps list.
     for new_map_handle in new_maps.iter() {
-        changed_maps.push(new_map_handle.clone());
+        changed_maps.push((new_map_handle.clone(), false));
     }
 
-    for changed_map in changed_maps.iter() {
+    for (changed_map, reloaded) in changed_maps.iter() {
         for (_, map_handle, mut map, mut transform) in query.iter_mut() {
             // only deal with currently changed map
             if map_handle != changed_map {
                 continue;
             }
 
-            transform.translation.y = 16.0 * 16.0;
+            // map_position does it once the layers exist if the height is not known yet
+            if let Some(rows) = maps.get(changed_map).and_then(ldtk_rows) {
+                let translation = origin.top_down_translation(rows);
+                transform.translation.x = translation.x;
+                transform.translation.y = translation.y;
+            }
         }
 
         // info!("changed map: {:?}", changed_map);
@@ -468,24 +4872,625 @@ fn process_loaded_tile_maps(
         //     }
         // }
 
-        for (entity, _) in ferris_query.iter() {
+        let mut carried_over = Ferris::default();
+        for (entity, ferris) in ferris_query.iter() {
+            carried_over = carry_over(ferris, keys_across_levels.0, *reloaded);
+            commands.entity(entity).despawn();
+        }
+        last_checkpoint.0 = None;
+        // rebuilt by update_tile_grid once the new tiles exist (unless there is an IntGrid)
+        *grid = TileGrid::default();
+
+        for entity in placed_query.iter() {
             commands.entity(entity).despawn();
         }
+        if let Some(ldtk_map) = maps.get(changed_map) {
+            *level_meta = ldtk_level_meta(ldtk_map);
+            info!("level: {:?}", *level_meta);
+            let entity_tiles = ldtk_entity_tiles(ldtk_map);
+            for (pos, texture_index) in entity_tiles.iter().copied() {
+                commands.spawn().insert(PlacedTile { pos, texture_index });
+            }
+            rules.door_keys = ldtk_door_keys(ldtk_map);
+            rules.key_order = OrderConstraint(level_meta.key_order.clone());
+            if let Some(mut collision_grid) = ldtk_intgrid_collision(ldtk_map) {
+                info!("collision from intgrid: {:?}", collision_grid.size());
+                // the entities are in the grid right away, their PlacedTiles only draw them
+                for (pos, texture_index) in entity_tiles {
+                    collision_grid.set(pos, Some(texture_index));
+                }
+                *grid = collision_grid;
+                grid_ready_events.send(TileGridReady);
+            }
+        }
         // transform.translation.y = map.
 
-        commands
-            .spawn()
-            .insert(Ferris {
-                pos: UVec2::splat(0),
-                keys: [false; 3],
+        spawn_ferris(&mut commands, carried_over);
+    }
+}
+
+// what ferris takes along into the next map: his keys (held and collected ones) with
+// KeysAcrossLevels. A reload of the same map, e.g. after editing it, starts it over
+// empty handed like a restart.
+fn carry_over(ferris: &Ferris, keys_across_levels: bool, reloaded: bool) -> Ferris {
+    if !keys_across_levels || reloaded {
+        return Ferris::default();
+    }
+    Ferris {
+        keys: ferris.keys,
+        collected: ferris.collected,
+        ..Default::default()
+    }
+}
+
+// placed at the start tile by init_ferris once the tile grid exists, with whatever else
+// ferris starts the level with (see carry_over)
+fn spawn_ferris(commands: &mut Commands, ferris: Ferris) {
+    commands
+        .spawn()
+        .insert(ferris)
+        .insert(ChaseCameraTarget)
+        .insert(TargetTracker::default());
+}
+
+fn switch_level(
+    mut load_level_events: EventReader<LoadLevel>,
+    mut current_level: ResMut<CurrentLevel>,
+    asset_server: Res<AssetServer>,
+    mut commands: Commands,
+    map_query: Query<Entity, With<Handle<LdtkMap>>>,
+) {
+    let n = match load_level_events.iter().last() {
+        Some(LoadLevel(n)) => *n,
+        None => return,
+    };
+    info!("switching to level {}: {}", n, LEVELS[n]);
+    current_level.0 = n;
+    let handle: Handle<LdtkMap> = asset_server.load(LEVELS[n]);
+    for entity in map_query.iter() {
+        commands.entity(entity).insert(handle.clone());
+    }
+}
+
+fn spawn_generated_map(
+    origin: Res<MapOrigin>,
+    mut commands: Commands,
+    map_source: Res<MapSource>,
+    grid: Res<TileGrid>,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut map_query: MapQuery,
+    mut grid_ready_events: EventWriter<TileGridReady>,
+) {
+    if *map_source != MapSource::Generated {
+        return;
+    }
+    spawn_tilemap(
+        &origin,
+        &mut commands,
+        &asset_server,
+        &mut materials,
+        &mut map_query,
+        &grid,
+    );
+    grid_ready_events.send(TileGridReady);
+    spawn_ferris(&mut commands, Ferris::default());
+}
+
+// builds a tilemap with the same map / layer ids as the LDTK one from the TileGrid, so
+// the rest of the game does not care where the map came from
+fn spawn_tilemap(
+    origin: &MapOrigin,
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    materials: &mut Assets<ColorMaterial>,
+    map_query: &mut MapQuery,
+    grid: &TileGrid,
+) {
+    const CHUNK_SIZE: u32 = 8;
+    let texture_handle = asset_server.load("labyrinth.png");
+    let material_handle = materials.add(ColorMaterial::texture(texture_handle));
+
+    let map_entity = commands.spawn().id();
+    let mut map = Map::new(LEVEL_ID, map_entity);
+
+    let size = grid.size();
+    let settings = LayerSettings::new(
+        UVec2::new(
+            (size.x + CHUNK_SIZE - 1) / CHUNK_SIZE,
+            (size.y + CHUNK_SIZE - 1) / CHUNK_SIZE,
+        ),
+        UVec2::splat(CHUNK_SIZE),
+        Vec2::splat(TILE_SIZE),
+        Vec2::new(256.0, 256.0),
+    );
+    let (mut layer_builder, _) =
+        LayerBuilder::<TileBundle>::new(commands, settings, LEVEL_ID, LAYER_ID);
+    for (pos, texture_index) in grid.iter() {
+        // the grid keeps WALL_TILE, only the visuals use the variants
+        let texture_index = if texture_index == WALL_TILE {
+            wall_autotile(wall_mask(grid, pos))
+        } else {
+            texture_index
+        };
+        let tile = Tile {
+            texture_index,
+            ..Default::default()
+        };
+        let _ = layer_builder.set_tile(pos.into(), tile.into());
+    }
+    let layer_entity = map_query.build_layer(commands, layer_builder, material_handle);
+    map.add_layer(commands, LAYER_ID, layer_entity);
+
+    commands
+        .entity(map_entity)
+        .insert(map)
+        .insert(Transform::from_translation(
+            origin.0.extend(zorder::Layer::Map.z()),
+        ))
+        .insert(GlobalTransform::default());
+
+    info!("generated map: {:?}", size);
+}
+
+// replaces the current map, LDTK or generated, like a new level: ferris starts over at the
+// start of the new map
+fn load_map(
+    origin: Res<MapOrigin>,
+    mut commands: Commands,
+    mut load_map_events: EventReader<LoadMap>,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut map_query: MapQuery,
+    mut map_source: ResMut<MapSource>,
+    mut grid: ResMut<TileGrid>,
+    mut level_meta: ResMut<LevelMeta>,
+    mut rules: ResMut<Rules>,
+    mut last_checkpoint: ResMut<LastCheckpoint>,
+    mut grid_ready_events: EventWriter<TileGridReady>,
+    ferris_query: Query<Entity, With<Ferris>>,
+    placed_query: Query<Entity, With<PlacedTile>>,
+) {
+    let new_grid = match load_map_events.iter().last() {
+        Some(LoadMap(new_grid)) => new_grid.clone(),
+        None => return,
+    };
+    map_query.despawn(&mut commands, LEVEL_ID);
+    for entity in ferris_query.iter().chain(placed_query.iter()) {
+        commands.entity(entity).despawn();
+    }
+    *map_source = MapSource::Generated;
+    *level_meta = LevelMeta::default();
+    // the rest comes with the tiles of the new map, see update_key_colors
+    rules.door_keys = Default::default();
+    rules.key_order = OrderConstraint::default();
+    last_checkpoint.0 = None;
+    *grid = new_grid;
+
+    spawn_tilemap(
+        &origin,
+        &mut commands,
+        &asset_server,
+        &mut materials,
+        &mut map_query,
+        &grid,
+    );
+    grid_ready_events.send(TileGridReady);
+    spawn_ferris(&mut commands, Ferris::default());
+}
+
+// ferris keeps his keys when the next level is loaded, instead of starting it empty
+// handed (toggled with F7). Reloading the same level starts it over, see carry_over.
+#[derive(Default)]
+struct KeysAcrossLevels(bool);
+
+// authored information about the current level, from the custom fields of the LDTK level
+#[derive(Debug, Default, Clone, PartialEq)]
+struct LevelMeta {
+    // uid of the LDTK level, replays are tied to it. 0 for generated maps.
+    uid: u32,
+    name: String,
+    author: Option<String>,
+    // intended number of moves
+    par: Option<u32>,
+    // camera scale for this level, Tunables::camera_scale if not set
+    zoom: Option<f32>,
+    // color ids in the order their keys have to be picked up, see OrderConstraint
+    key_order: Vec<u8>,
+}
+
+// fields `name`, `author`, `par`, `zoom` and `key_order` of the first level, all optional.
+// The name falls back to the level identifier.
+fn ldtk_level_meta(ldtk_map: &LdtkMap) -> LevelMeta {
+    let level = match ldtk_map.project.levels.first() {
+        Some(level) => level,
+        None => return LevelMeta::default(),
+    };
+    let field = |identifier: &str| {
+        level
+            .field_instances
+            .iter()
+            .find(|field| field.identifier == identifier)
+            .and_then(|field| field.value.as_ref())
+    };
+    LevelMeta {
+        uid: level.uid as u32,
+        name: field("name")
+            .and_then(|value| value.as_str())
+            .unwrap_or(&level.identifier)
+            .to_string(),
+        author: field("author")
+            .and_then(|value| value.as_str())
+            .map(|author| author.to_string()),
+        par: field("par")
+            .and_then(|value| value.as_u64())
+            .map(|par| par as u32),
+        zoom: field("zoom")
+            .and_then(|value| value.as_f64())
+            .map(|zoom| zoom as f32),
+        key_order: field("key_order")
+            .and_then(|value| value.as_array())
+            .map(|order| {
+                order
+                    .iter()
+                    .filter_map(|color_id| color_id.as_u64())
+                    .map(|color_id| color_id as u8)
+                    .collect()
             })
-            .insert(ChaseCameraTarget)
-            .insert(TargetTracker::default());
+            .unwrap_or_default(),
+    }
+}
+
+fn show_level_meta(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    level_meta: Res<LevelMeta>,
+) {
+    if !level_meta.is_changed() || level_meta.name.is_empty() {
+        return;
+    }
+    let mut message = level_meta.name.clone();
+    if let Some(author) = &level_meta.author {
+        message += &format!(" by {}", author);
+    }
+    if let Some(par) = level_meta.par {
+        message += &format!(" (par {})", par);
+    }
+    ferris_lab::toast::spawn_toast(
+        &mut commands,
+        asset_server.load("fonts/DejaVuSansMono.ttf"),
+        &message,
+        Color::WHITE,
+    );
+}
+
+// maps the `Type` field of an LDTK entity to the tile index with the same behavior.
+// Names follow the IntGrid value identifiers of the labyrinth project.
+fn entity_type_to_tile(entity_type: &str) -> Option<u16> {
+    match entity_type {
+        "start" => Some(START_TILE),
+        "end" => Some(END_TILE),
+        "red_door" => Some(door_tile(0)),
+        "yellow_door" => Some(door_tile(1)),
+        "blue_door" => Some(door_tile(2)),
+        "red_key" => Some(key_tile(0)),
+        "yellow_key" => Some(key_tile(1)),
+        "blue_key" => Some(key_tile(2)),
+        "checkpoint" => Some(CHECKPOINT_TILE),
+        _ => None,
+    }
+}
+
+fn ldtk_entity_tiles(ldtk_map: &LdtkMap) -> Vec<(UVec2, u16)> {
+    let mut tiles = Vec::new();
+    let layers = match ldtk_map
+        .project
+        .levels
+        .first()
+        .and_then(|level| level.layer_instances.as_ref())
+    {
+        Some(layers) => layers,
+        None => return tiles,
+    };
+
+    for layer in layers
+        .iter()
+        .filter(|layer| layer.layer_instance_type == "Entities")
+    {
+        for entity in layer.entity_instances.iter() {
+            let entity_type = entity
+                .field_instances
+                .iter()
+                .find(|field| field.identifier == "Type")
+                .and_then(|field| field.value.as_ref())
+                .and_then(|value| value.as_str());
+
+            match entity_type.and_then(entity_type_to_tile) {
+                Some(texture_index) if entity.grid.len() == 2 => {
+                    // ldtk grid coordinates go top-down, tile positions bottom-up
+                    let x = entity.grid[0] as u32;
+                    let y = (layer.c_hei - 1 - entity.grid[1]) as u32;
+                    tiles.push((UVec2::new(x, y), texture_index));
+                }
+                _ => warn!(
+                    "ignoring ldtk entity {} with type {:?}",
+                    entity.identifier, entity_type
+                ),
+            }
+        }
+    }
+    tiles
+}
+
+// optional `Keys` field of door entities: the number of keys of its color the door needs
+fn ldtk_door_keys(ldtk_map: &LdtkMap) -> HashMap<UVec2, u32> {
+    let mut door_keys = HashMap::new();
+    let layers = match ldtk_map
+        .project
+        .levels
+        .first()
+        .and_then(|level| level.layer_instances.as_ref())
+    {
+        Some(layers) => layers,
+        None => return door_keys,
+    };
+
+    for layer in layers
+        .iter()
+        .filter(|layer| layer.layer_instance_type == "Entities")
+    {
+        for entity in layer.entity_instances.iter() {
+            let keys = entity
+                .field_instances
+                .iter()
+                .find(|field| field.identifier == "Keys")
+                .and_then(|field| field.value.as_ref())
+                .and_then(|value| value.as_u64());
+            if let (Some(keys), 2) = (keys, entity.grid.len()) {
+                let x = entity.grid[0] as u32;
+                let y = (layer.c_hei - 1 - entity.grid[1]) as u32;
+                door_keys.insert(UVec2::new(x, y), keys as u32);
+            }
+        }
+    }
+    door_keys
+}
+
+// maps an IntGrid value of the labyrinth project to the tile index with the same behavior
+fn intgrid_value_to_tile(value: i64) -> Option<u16> {
+    match value {
+        1 => Some(WALL_TILE),
+        2 => Some(START_TILE),
+        3 => Some(END_TILE),
+        // keys
+        4..=6 => Some(key_tile((value - 4) as u8)),
+        // doors
+        7..=9 => Some(door_tile((value - 7) as u8)),
+        _ => None,
+    }
+}
+
+// height of the level in tiles, that of its highest layer
+fn ldtk_rows(ldtk_map: &LdtkMap) -> Option<u32> {
+    ldtk_map
+        .project
+        .levels
+        .first()?
+        .layer_instances
+        .as_ref()?
+        .iter()
+        .map(|layer| layer.c_hei as u32)
+        .max()
+}
+
+// Builds the TileGrid from the first IntGrid layer of the level, so collision does not
+// depend on the visual tiles (which can then be purely decorative).
+fn ldtk_intgrid_collision(ldtk_map: &LdtkMap) -> Option<TileGrid> {
+    let layer = ldtk_map
+        .project
+        .levels
+        .first()?
+        .layer_instances
+        .as_ref()?
+        .iter()
+        .find(|layer| layer.layer_instance_type == "IntGrid")?;
+
+    let width = layer.c_wid as u32;
+    let height = layer.c_hei as u32;
+    let mut grid = TileGrid::new(UVec2::new(width, height));
+    for (i, value) in layer.int_grid_csv.iter().enumerate() {
+        // csv is row major, top row first
+        let x = i as u32 % width;
+        let y = height - 1 - i as u32 / width;
+        grid.set(UVec2::new(x, y), intgrid_value_to_tile(*value));
+    }
+    Some(grid)
+}
+
+fn place_entity_tiles(
+    mut commands: Commands,
+    query: Query<(Entity, &PlacedTile)>,
+    mut map_query: MapQuery,
+    mut pending_chunks: ResMut<PendingChunkUpdates>,
+) {
+    for (entity, placed) in query.iter() {
+        let pos: TilePos = placed.pos.into();
+        let tile = Tile {
+            texture_index: placed.texture_index,
+            ..Default::default()
+        };
+        // fails until the ldtk layer has been spawned, so just retry next frame
+        if map_query
+            .set_tile(&mut commands, pos, tile, LEVEL_ID, LAYER_ID)
+            .is_ok()
+        {
+            pending_chunks.0.insert((LAYER_ID, placed.pos));
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+fn update_tile_grid(
+    mut grid: ResMut<TileGrid>,
+    mut ready_events: EventWriter<TileGridReady>,
+    tile_query: Query<(&Tile, &TilePos)>,
+    placed_query: Query<&PlacedTile>,
+    layer_query: Query<&Layer>,
+) {
+    // only copy the map once after loading, afterwards the grid is kept up to date
+    // by whoever changes tiles
+    if !grid.is_empty() || tile_query.iter().next().is_none() {
+        return;
+    }
+
+    let mut size = UVec2::ZERO;
+    for layer in layer_query.iter() {
+        let layer_size = layer.get_layer_size_in_tiles();
+        size = size.max(UVec2::new(layer_size.0, layer_size.1));
+    }
+
+    let mut new_grid = TileGrid::new(size);
+    for (tile, pos) in tile_query.iter() {
+        new_grid.set((*pos).into(), Some(tile.texture_index));
+    }
+    for placed in placed_query.iter() {
+        new_grid.set(placed.pos, Some(placed.texture_index));
+    }
+    *grid = new_grid;
+    info!("tile grid ready: {:?}", grid.size());
+    ready_events.send(TileGridReady);
+}
+
+fn snapshot_level_start(
+    mut ready_events: EventReader<TileGridReady>,
+    grid: Res<TileGrid>,
+    mut level_start: ResMut<LevelStart>,
+) {
+    if ready_events.iter().count() == 0 {
+        return;
+    }
+    level_start.0 = grid.clone();
+}
+
+fn update_key_colors(
+    mut ready_events: EventReader<TileGridReady>,
+    mut edited_events: EventReader<MapEdited>,
+    grid: Res<TileGrid>,
+    mut rules: ResMut<Rules>,
+) {
+    if ready_events.iter().count() + edited_events.iter().count() == 0 {
+        return;
+    }
+    rules.key_colors = key_colors(&grid);
+}
+
+fn check_key_door_balance(
+    mut ready_events: EventReader<TileGridReady>,
+    mut edited_events: EventReader<MapEdited>,
+    grid: Res<TileGrid>,
+    mut warnings: EventWriter<LevelBalanceWarning>,
+) {
+    if ready_events.iter().count() + edited_events.iter().count() == 0 {
+        return;
+    }
+
+    let mut keys = [0u32; NUM_COLORS as usize];
+    let mut doors = [0u32; NUM_COLORS as usize];
+    for (_, texture_index) in grid.iter() {
+        if let Some(color_id) = door_color_id(texture_index) {
+            doors[color_id as usize] += 1;
+        } else if let Some(color_id) = key_color_id(texture_index) {
+            keys[color_id as usize] += 1;
+        }
+    }
+
+    for color_id in 0..NUM_COLORS as usize {
+        let (keys, doors) = (keys[color_id], doors[color_id]);
+        if doors > 0 && keys == 0 {
+            error!("{} door(s) of color {} but no key", doors, color_id);
+        } else if keys > 0 && doors == 0 {
+            warn!("{} key(s) of color {} but no door", keys, color_id);
+        } else {
+            continue;
+        }
+        warnings.send(LevelBalanceWarning {
+            color_id: color_id as u8,
+            keys,
+            doors,
+        });
+    }
+}
+
+// multiple exits are fine, no or multiple starts are an authoring mistake
+fn check_start_tiles(
+    mut ready_events: EventReader<TileGridReady>,
+    mut edited_events: EventReader<MapEdited>,
+    grid: Res<TileGrid>,
+    mut failures: EventWriter<LevelValidationFailed>,
+) {
+    if ready_events.iter().count() + edited_events.iter().count() == 0 {
+        return;
+    }
+
+    let starts = grid.find_all(START_TILE);
+    if starts.is_empty() {
+        let message = "map has no start tile, ferris can't be placed".to_string();
+        error!("{}", message);
+        failures.send(LevelValidationFailed(message));
+    }
+    if starts.len() > 1 {
+        let message = format!(
+            "{} start tiles at {:?}, using {:?}",
+            starts.len(),
+            starts,
+            starts[0]
+        );
+        error!("{}", message);
+        failures.send(LevelValidationFailed(message));
+    }
+}
+
+// the editor may have moved the start or put down or removed exits
+fn update_start_and_exits(
+    origin: Res<MapOrigin>,
+    mut edited_events: EventReader<MapEdited>,
+    grid: Res<TileGrid>,
+    mut ferris_query: Query<(&mut StartState, &mut EndPos)>,
+    mut marker_query: Query<&mut Transform, With<StartMarker>>,
+) {
+    if edited_events.iter().count() == 0 {
+        return;
+    }
+    let exits = grid.find_all(END_TILE);
+    if exits.is_empty() {
+        warn!("map has no end tile");
+    }
+    let start_pos = grid.find(START_TILE);
+    if start_pos.is_none() {
+        warn!("map has no start tile, keeping the old start");
+    }
+    for (mut start_state, mut end_pos) in ferris_query.iter_mut() {
+        end_pos.0 = exits.clone();
+        match start_pos {
+            Some(pos) if pos != start_state.0.pos => {
+                start_state.0 = Ferris {
+                    pos,
+                    ..start_state.0.clone()
+                };
+            }
+            _ => continue,
+        }
+        for mut transform in marker_query.iter_mut() {
+            transform.translation =
+                pos_to_translation(&origin, &start_state.0.pos, zorder::Layer::StartMarker);
+        }
     }
 }
 
+// only LDTK maps, generated ones are laid out bottom-up at the origin by spawn_tilemap
 fn map_position(
-    mut map_query: Query<(&Map, &mut Transform), Changed<Map>>,
+    origin: Res<MapOrigin>,
+    mut map_query: Query<(&Map, &mut Transform), (Changed<Map>, With<Handle<LdtkMap>>)>,
     layer_query: Query<&Layer>,
 ) {
     for (map, mut transform) in map_query.iter_mut() {
@@ -500,6 +5505,9 @@ fn map_position(
             }
         }
         info!("maxy: {}", maxy);
-        transform.translation.y = maxy as f32 * 16.0;
+        // ldtk rows go top-down, shift the map so its bottom row sits at the origin
+        let translation = origin.top_down_translation(maxy);
+        transform.translation.x = translation.x;
+        transform.translation.y = translation.y;
     }
 }
@@ -0,0 +1,61 @@
+use bevy::math::UVec2;
+use rand::Rng;
+
+use crate::grid::{TileGrid, DIRECTIONS, END_TILE, START_TILE, WALL_TILE};
+
+// Perfect maze (exactly one path between any two cells) of size.x * size.y cells, carved
+// with a randomized depth first search. Cells sit on odd tile coordinates with walls in
+// between, so the map is 2 * size + 1 tiles big. The start is the bottom left cell and
+// the exit the top right one. Draws from rng only, the same seed gives the same maze.
+pub fn generate_maze(size: UVec2, rng: &mut impl Rng) -> TileGrid {
+    // start and exit need cells of their own
+    let size = size.max(UVec2::new(2, 1));
+    let mut grid = TileGrid::new(size * 2 + UVec2::ONE);
+    let grid_size = grid.size();
+    for y in 0..grid_size.y {
+        for x in 0..grid_size.x {
+            grid.set(UVec2::new(x, y), Some(WALL_TILE));
+        }
+    }
+
+    let tile_pos = |cell: UVec2| cell * 2 + UVec2::ONE;
+    let index = |cell: UVec2| (cell.y * size.x + cell.x) as usize;
+    let mut visited = vec![false; (size.x * size.y) as usize];
+    visited[0] = true;
+    grid.set(tile_pos(UVec2::ZERO), None);
+    let mut stack = vec![UVec2::ZERO];
+    while let Some(cell) = stack.last().copied() {
+        let unvisited: Vec<UVec2> = DIRECTIONS
+            .iter()
+            .map(|dir| cell.as_i32() + *dir)
+            .filter(|n| n.x >= 0 && n.y >= 0 && (n.x as u32) < size.x && (n.y as u32) < size.y)
+            .map(|n| n.as_u32())
+            .filter(|n| !visited[index(*n)])
+            .collect();
+        if unvisited.is_empty() {
+            stack.pop();
+            continue;
+        }
+        let next = unvisited[rng.gen_range(0..unvisited.len())];
+        visited[index(next)] = true;
+        // the wall between the two cells goes as well
+        grid.set((tile_pos(cell) + tile_pos(next)) / 2, None);
+        grid.set(tile_pos(next), None);
+        stack.push(next);
+    }
+
+    grid.set(tile_pos(UVec2::ZERO), Some(START_TILE));
+    grid.set(tile_pos(size - UVec2::ONE), Some(END_TILE));
+    grid
+}
+
+// maze size in cells as given to FERRIS_MAZE, e.g. "20x12"
+pub fn parse_maze_size(value: &str) -> Option<UVec2> {
+    let mut parts = value.split('x');
+    let width = parts.next()?.trim().parse().ok()?;
+    let height = parts.next()?.trim().parse().ok()?;
+    if parts.next().is_some() || width == 0 || height == 0 {
+        return None;
+    }
+    Some(UVec2::new(width, height))
+}
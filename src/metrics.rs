@@ -0,0 +1,60 @@
+use std::{fs::OpenOptions, io::Write, path::PathBuf};
+
+use serde::Serialize;
+
+// One line of the metrics file: what happened during a single attempt at a level.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct AttemptRecord {
+    pub level: String,
+    pub moves: u32,
+    pub seconds: f64,
+    // solutions shown (solver or replay)
+    pub solves: u32,
+    pub deaths: u32,
+    pub won: bool,
+}
+
+impl AttemptRecord {
+    pub fn to_json_line(&self) -> serde_json::Result<String> {
+        serde_json::to_string(self)
+    }
+}
+
+// Collects the record of the running attempt and appends it to a JSON lines file when the
+// attempt ends. Without a path nothing is recorded.
+#[derive(Default)]
+pub struct MetricsLogger {
+    path: Option<PathBuf>,
+    pub current: AttemptRecord,
+}
+
+impl MetricsLogger {
+    pub fn new(path: Option<PathBuf>) -> Self {
+        MetricsLogger {
+            path,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.path.is_some()
+    }
+
+    // completes the running attempt, appends it to the file and starts the next one
+    pub fn finish_attempt(
+        &mut self,
+        level: &str,
+        won: bool,
+        seconds: f64,
+    ) -> anyhow::Result<AttemptRecord> {
+        let mut record = std::mem::take(&mut self.current);
+        record.level = level.to_string();
+        record.won = won;
+        record.seconds = seconds;
+        if let Some(path) = &self.path {
+            let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+            writeln!(file, "{}", record.to_json_line()?)?;
+        }
+        Ok(record)
+    }
+}
@@ -0,0 +1,134 @@
+use bevy::math::UVec2;
+use thiserror::Error;
+
+use crate::{
+    ferris::Ferris,
+    grid::{Direction, TileGrid},
+    rules::{apply_move, Rules},
+};
+
+// A recorded run: the moves ferris made from the start of a level.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Replay {
+    pub level: u32,
+    pub moves: Vec<Direction>,
+}
+
+#[derive(Debug, Error)]
+pub enum ReplayError {
+    #[error("not a replay code")]
+    BadHeader,
+    #[error("replay version {0} is not supported")]
+    UnsupportedVersion(u32),
+    #[error("invalid replay data: {0}")]
+    Base64(#[from] base64::DecodeError),
+    #[error("replay data is truncated")]
+    Truncated,
+    #[error("states {0} and {1} of the path are not neighbors")]
+    NotNeighbors(usize, usize),
+    // replays only know the four directions ferris can be moved in
+    #[error("diagonal solutions can't be exported (states {0} and {1} of the path)")]
+    Diagonal(usize, usize),
+}
+
+impl Replay {
+    // moves between consecutive states of a path (e.g. a solution)
+    pub fn from_path<'a>(
+        level: u32,
+        path: impl Iterator<Item = &'a Ferris>,
+    ) -> Result<Replay, ReplayError> {
+        let positions: Vec<UVec2> = path.map(|state| state.pos).collect();
+        let moves = positions
+            .windows(2)
+            .enumerate()
+            .map(|(i, w)| {
+                let offset = w[1].as_i32() - w[0].as_i32();
+                if offset.x.abs() == 1 && offset.y.abs() == 1 {
+                    return Err(ReplayError::Diagonal(i, i + 1));
+                }
+                Direction::from_offset(offset).ok_or(ReplayError::NotNeighbors(i, i + 1))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Replay { level, moves })
+    }
+
+    // states after each move, starting from start. Stops at the first blocked move.
+    pub fn play(&self, grid: &TileGrid, rules: &Rules, start: &Ferris) -> Vec<Ferris> {
+        let mut states = vec![start.clone()];
+        for direction in self.moves.iter() {
+            let current = states.last().unwrap();
+            match apply_move(grid, rules, current, *direction) {
+                Some((next, _)) => states.push(next),
+                None => break,
+            }
+        }
+        states
+    }
+}
+
+const HEADER: &str = "FL";
+const VERSION: u32 = 1;
+// moves per run byte: 2 bits direction, 6 bits run length - 1
+const MAX_RUN: usize = 64;
+
+fn direction_bits(direction: Direction) -> u8 {
+    match direction {
+        Direction::Up => 0,
+        Direction::Down => 1,
+        Direction::Left => 2,
+        Direction::Right => 3,
+    }
+}
+
+fn bits_direction(bits: u8) -> Direction {
+    match bits & 3 {
+        0 => Direction::Up,
+        1 => Direction::Down,
+        2 => Direction::Left,
+        _ => Direction::Right,
+    }
+}
+
+// "FL<version>:" followed by base64 of the level (u32, little endian) and the run length
+// encoded moves
+pub fn encode_replay(replay: &Replay) -> String {
+    let mut bytes = replay.level.to_le_bytes().to_vec();
+    let mut moves = replay.moves.iter().peekable();
+    while let Some(direction) = moves.next() {
+        let mut run = 1;
+        while run < MAX_RUN && moves.peek() == Some(&direction) {
+            moves.next();
+            run += 1;
+        }
+        bytes.push(direction_bits(*direction) | (((run - 1) as u8) << 2));
+    }
+    format!(
+        "{}{}:{}",
+        HEADER,
+        VERSION,
+        base64::encode_config(&bytes, base64::URL_SAFE_NO_PAD)
+    )
+}
+
+pub fn decode_replay(code: &str) -> Result<Replay, ReplayError> {
+    let (version, data) = code
+        .trim()
+        .strip_prefix(HEADER)
+        .and_then(|rest| rest.split_once(':'))
+        .ok_or(ReplayError::BadHeader)?;
+    let version: u32 = version.parse().map_err(|_| ReplayError::BadHeader)?;
+    if version != VERSION {
+        return Err(ReplayError::UnsupportedVersion(version));
+    }
+    let bytes = base64::decode_config(data, base64::URL_SAFE_NO_PAD)?;
+    if bytes.len() < 4 {
+        return Err(ReplayError::Truncated);
+    }
+    let level = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    let mut moves = Vec::new();
+    for byte in &bytes[4..] {
+        let run = (byte >> 2) as usize + 1;
+        moves.extend(std::iter::repeat(bits_direction(*byte)).take(run));
+    }
+    Ok(Replay { level, moves })
+}
@@ -0,0 +1,19 @@
+use rand::{rngs::StdRng, SeedableRng};
+
+pub const DEFAULT_SEED: u64 = 0x5eed_f3a1;
+
+// The only source of randomness in the game. Everything random draws from it, so a run
+// (and any generated level) can be reproduced from its seed.
+pub struct GameRng(pub StdRng);
+
+impl GameRng {
+    pub fn from_seed(seed: u64) -> Self {
+        GameRng(StdRng::seed_from_u64(seed))
+    }
+}
+
+impl Default for GameRng {
+    fn default() -> Self {
+        GameRng::from_seed(DEFAULT_SEED)
+    }
+}
@@ -0,0 +1,259 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use bevy::math::UVec2;
+
+use crate::{
+    ferris::Ferris,
+    grid::{
+        color_id, door_color_id, get_neighboring_pos, key_color_id, Direction, TileGrid,
+        CHECKPOINT_TILE, END_TILE, FLOOR_TILE, START_TILE,
+    },
+};
+
+// what happens to a key when ferris opens a door with it
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum KeyConsumption {
+    // a key opens every door of its color
+    Permanent,
+    // opening a door uses up the key, so each key opens exactly one door
+    PerDoor,
+}
+
+impl Default for KeyConsumption {
+    fn default() -> Self {
+        KeyConsumption::Permanent
+    }
+}
+
+// what ferris needs to be able to step onto the exit
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ExitCondition {
+    Always,
+    // having collected a key of every color on the map (Rules::key_colors)
+    AllKeys,
+    // holding the key with this color id
+    SpecificKey(u8),
+}
+
+impl Default for ExitCondition {
+    fn default() -> Self {
+        ExitCondition::Always
+    }
+}
+
+impl ExitCondition {
+    pub fn is_met(self, ferris: &Ferris, key_colors: &[u8]) -> bool {
+        match self {
+            ExitCondition::Always => true,
+            ExitCondition::AllKeys => key_colors
+                .iter()
+                .all(|color_id| ferris.collected[*color_id as usize]),
+            ExitCondition::SpecificKey(color_id) => {
+                ferris.keys.get(color_id as usize).copied().unwrap_or(0) > 0
+            }
+        }
+    }
+}
+
+// how many keys ferris can carry at once
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum KeyCapacity {
+    Unlimited,
+    // a single held key. Other keys are walked over until the held one is dropped.
+    Single,
+}
+
+impl Default for KeyCapacity {
+    fn default() -> Self {
+        KeyCapacity::Unlimited
+    }
+}
+
+// order in which keys have to be picked up, by color id: a key can only be picked up once
+// the one before it in the list has been collected. Keys not in the list can be picked
+// up any time. Empty (no constraint) by default.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OrderConstraint(pub Vec<u8>);
+
+impl OrderConstraint {
+    pub fn allows(&self, ferris: &Ferris, color_id: u8) -> bool {
+        match self.0.iter().position(|id| *id == color_id) {
+            Some(i) if i > 0 => ferris.collected[self.0[i - 1] as usize],
+            _ => true,
+        }
+    }
+}
+
+// configurable game rules, used by manual movement and the solver alike
+#[derive(Clone, Debug, Default)]
+pub struct Rules {
+    pub key_consumption: KeyConsumption,
+    pub exit_condition: ExitCondition,
+    pub key_capacity: KeyCapacity,
+    pub key_order: OrderConstraint,
+    // number of keys of its color the door at a position needs, 1 for doors not listed
+    pub door_keys: HashMap<UVec2, u32>,
+    // color ids of the keys on the map as loaded, see key_colors
+    pub key_colors: Vec<u8>,
+}
+
+impl Rules {
+    pub fn keys_required(&self, door_pos: UVec2) -> u32 {
+        self.door_keys.get(&door_pos).copied().unwrap_or(1)
+    }
+}
+
+// what ferris does to the tile he moves onto
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Interaction {
+    None,
+    PickUpKey(u8),
+    OpenDoor(u8),
+}
+
+// color ids that have at least one key on the map, in ascending order
+pub fn key_colors(grid: &TileGrid) -> Vec<u8> {
+    let mut colors: Vec<u8> = grid
+        .iter()
+        .filter_map(|(_, tile)| key_color_id(tile))
+        .collect();
+    colors.sort_unstable();
+    colors.dedup();
+    colors
+}
+
+// cost of moving onto a cell with this tile, None if it can never be entered. Doors,
+// keys and the exit cost the same as floor, whether they can be entered right now is up
+// to try_move.
+pub fn tile_cost(tile: Option<u16>) -> Option<i32> {
+    match tile {
+        None | Some(FLOOR_TILE) | Some(START_TILE) | Some(END_TILE) | Some(CHECKPOINT_TILE) => {
+            Some(1)
+        }
+        Some(tile) if color_id(tile).is_some() => Some(1),
+        _ => None,
+    }
+}
+
+// cells ferris can stand on without interacting with anything
+pub fn is_walkable(tile: Option<u16>) -> bool {
+    matches!(
+        tile,
+        None | Some(FLOOR_TILE) | Some(START_TILE) | Some(CHECKPOINT_TILE)
+    )
+}
+
+// walkable cell closest to from (breadth first, also through walls), from itself if it
+// is walkable. None if the grid has no walkable cell at all.
+pub fn nearest_walkable(grid: &TileGrid, from: UVec2) -> Option<UVec2> {
+    if !grid.in_bounds(from.as_i32()) {
+        return None;
+    }
+    let mut visited = HashSet::new();
+    let mut queue = VecDeque::new();
+    visited.insert(from);
+    queue.push_back(from);
+    while let Some(pos) = queue.pop_front() {
+        if is_walkable(grid.get(pos)) {
+            return Some(pos);
+        }
+        for neighbor in get_neighboring_pos(pos, grid.size()).iter().flatten() {
+            if visited.insert(*neighbor) {
+                queue.push_back(*neighbor);
+            }
+        }
+    }
+    None
+}
+
+// state after ferris drops his key onto the cell he stands on (holding tile), and the
+// color of the dropped key. Only with KeyCapacity::Single and only onto empty floor.
+pub fn try_drop_key(rules: &Rules, state: &Ferris, tile: Option<u16>) -> Option<(Ferris, u8)> {
+    if rules.key_capacity != KeyCapacity::Single || !matches!(tile, None | Some(FLOOR_TILE)) {
+        return None;
+    }
+    let color_id = state.held_key()?;
+    let mut new_state = state.clone();
+    new_state.keys[color_id as usize] -= 1;
+    Some((new_state, color_id))
+}
+
+// single step of ferris in direction, the way every input (keyboard, replays, ...) moves
+// him. None if the step leaves the grid or is blocked.
+pub fn apply_move(
+    grid: &TileGrid,
+    rules: &Rules,
+    state: &Ferris,
+    direction: Direction,
+) -> Option<(Ferris, Interaction)> {
+    let new_pos = state.pos.as_i32() + direction.delta();
+    if !grid.in_bounds(new_pos) {
+        return None;
+    }
+    try_move(grid, rules, state, new_pos.as_u32())
+}
+
+// step onto a neighboring cell, like solution playback and the solver do: apply_move for
+// orthogonal steps, try_move for the diagonal ones of eight way movement
+pub fn step_to(
+    grid: &TileGrid,
+    rules: &Rules,
+    state: &Ferris,
+    pos: UVec2,
+) -> Option<(Ferris, Interaction)> {
+    match Direction::from_offset(pos.as_i32() - state.pos.as_i32()) {
+        Some(direction) => apply_move(grid, rules, state, direction),
+        None => try_move(grid, rules, state, pos),
+    }
+}
+
+// state after ferris moves onto new_pos (which should be a neighbor of his current
+// position). None if the cell is outside of the grid or blocked.
+// Shared by manual movement, solution playback and the solver.
+pub fn try_move(
+    grid: &TileGrid,
+    rules: &Rules,
+    state: &Ferris,
+    new_pos: UVec2,
+) -> Option<(Ferris, Interaction)> {
+    if !grid.in_bounds(new_pos.as_i32()) {
+        return None;
+    }
+    let mut new_state = state.clone();
+    new_state.pos = new_pos;
+
+    match grid.get(new_pos) {
+        None | Some(FLOOR_TILE) | Some(START_TILE) | Some(CHECKPOINT_TILE) => {
+            Some((new_state, Interaction::None))
+        }
+        Some(END_TILE) if rules.exit_condition.is_met(state, &rules.key_colors) => {
+            Some((new_state, Interaction::None))
+        }
+        Some(tile) => {
+            if let Some(color_id) = door_color_id(tile) {
+                let required = rules.keys_required(new_pos);
+                if new_state.keys[color_id as usize] < required {
+                    return None;
+                }
+                if rules.key_consumption == KeyConsumption::PerDoor {
+                    new_state.keys[color_id as usize] -= required;
+                }
+                Some((new_state, Interaction::OpenDoor(color_id)))
+            } else if let Some(color_id) = key_color_id(tile) {
+                if rules.key_capacity == KeyCapacity::Single && state.held_key().is_some() {
+                    // hands full, the key stays where it is
+                    return Some((new_state, Interaction::None));
+                }
+                if !rules.key_order.allows(state, color_id) {
+                    // out of order, the key stays where it is
+                    return Some((new_state, Interaction::None));
+                }
+                new_state.keys[color_id as usize] += 1;
+                new_state.collected[color_id as usize] = true;
+                Some((new_state, Interaction::PickUpKey(color_id)))
+            } else {
+                None
+            }
+        }
+    }
+}
@@ -0,0 +1,327 @@
+use std::{
+    fs,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use bevy::math::{IVec2, UVec2};
+use pathfinding::directed::astar;
+
+use crate::{
+    ascii::tilegrid_from_ascii,
+    ferris::Ferris,
+    grid::{get_neighboring_pos, TileGrid, DIRECTIONS, END_TILE, START_TILE},
+    rules::{step_to, tile_cost, try_drop_key, Interaction, KeyCapacity, KeyConsumption, Rules},
+};
+
+// cost of an orthogonal step onto a cell with tile_cost 1. Costs are scaled by this, so
+// diagonal steps can be priced in between (e.g. 14 for roughly sqrt(2)).
+pub const ORTHOGONAL_COST: i32 = 10;
+
+const DIAGONALS: [IVec2; 4] = [
+    IVec2::new(1, 1),
+    IVec2::new(1, -1),
+    IVec2::new(-1, 1),
+    IVec2::new(-1, -1),
+];
+
+pub struct SolveOptions {
+    // the manhattan heuristic is multiplied by this (weighted A*). Values > 1.0 make the
+    // search expand fewer nodes on big maps, but the path is no longer guaranteed to be
+    // optimal (it is at most heuristic_weight times longer).
+    pub heuristic_weight: f32,
+    // also search diagonal steps. Corners can't be cut, both orthogonal neighbors of a
+    // diagonal step have to be enterable.
+    pub eight_way: bool,
+    // cost of a diagonal step in the same units as ORTHOGONAL_COST. Higher values make
+    // the solver prefer orthogonal steps, anything >= 2 * ORTHOGONAL_COST avoids
+    // diagonals completely.
+    pub diagonal_cost: u32,
+    // the search stops expanding after this many states (giving up if no exit was found), so
+    // maps with a huge or endless state space can't hang the game
+    pub max_expanded: u32,
+}
+
+impl Default for SolveOptions {
+    fn default() -> Self {
+        SolveOptions {
+            heuristic_weight: 1.0,
+            eight_way: false,
+            diagonal_cost: 14,
+            max_expanded: 1_000_000,
+        }
+    }
+}
+
+// search node: ferris plus the key / door cells already used up on the way. Picked up
+// keys are always tracked since they add to the key count, doors only if keys are
+// consumed per door or carried one at a time, otherwise opening a door twice makes no
+// difference. Keys dropped with KeyCapacity::Single are tracked as well.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct SearchState {
+    ferris: Ferris,
+    consumed: Vec<UVec2>,
+    dropped: Vec<(UVec2, u8)>,
+}
+
+fn successors(
+    grid: &TileGrid,
+    rules: &Rules,
+    options: &SolveOptions,
+    state: &SearchState,
+) -> Vec<(SearchState, i32)> {
+    let mut successors = Vec::with_capacity(DIRECTIONS.len() + DIAGONALS.len());
+
+    let enterable = |pos: IVec2| {
+        grid.in_bounds(pos)
+            && (state.consumed.contains(&pos.as_u32())
+                || step_to(grid, rules, &state.ferris, pos.as_u32()).is_some())
+    };
+    // up to four orthogonal and four diagonal neighbors with the cost of stepping there
+    let mut neighbors = [None; 8];
+    for (neighbor, pos) in neighbors
+        .iter_mut()
+        .zip(get_neighboring_pos(state.ferris.pos, grid.size()).iter())
+    {
+        *neighbor = pos.map(|pos| (pos, ORTHOGONAL_COST));
+    }
+    if options.eight_way {
+        let pos = state.ferris.pos.as_i32();
+        for (neighbor, dir) in neighbors[4..].iter_mut().zip(DIAGONALS.iter()) {
+            let corner1 = pos + IVec2::new(dir.x, 0);
+            let corner2 = pos + IVec2::new(0, dir.y);
+            if grid.in_bounds(pos + *dir) && enterable(corner1) && enterable(corner2) {
+                *neighbor = Some(((pos + *dir).as_u32(), options.diagonal_cost as i32));
+            }
+        }
+    }
+
+    let dropped_at = |pos: UVec2| {
+        state
+            .dropped
+            .iter()
+            .position(|(dropped_pos, _)| *dropped_pos == pos)
+    };
+    // dropping the key is a step in place
+    let tile = if state.consumed.contains(&state.ferris.pos) {
+        None
+    } else {
+        grid.get(state.ferris.pos)
+    };
+    if dropped_at(state.ferris.pos).is_none() {
+        if let Some((ferris, color_id)) = try_drop_key(rules, &state.ferris, tile) {
+            let mut dropped = state.dropped.clone();
+            dropped.push((ferris.pos, color_id));
+            dropped.sort_by_key(|(pos, _)| (pos.y, pos.x));
+            successors.push((
+                SearchState {
+                    ferris,
+                    consumed: state.consumed.clone(),
+                    dropped,
+                },
+                ORTHOGONAL_COST,
+            ));
+        }
+    }
+
+    for (pos, step_cost) in std::array::IntoIter::new(neighbors).flatten() {
+        if let Some(i) = dropped_at(pos) {
+            // picked up again if ferris' hands are free, walked over otherwise
+            let mut ferris = state.ferris.clone();
+            ferris.pos = pos;
+            let mut dropped = state.dropped.clone();
+            if ferris.held_key().is_none() {
+                let (_, color_id) = dropped.remove(i);
+                ferris.keys[color_id as usize] += 1;
+            }
+            successors.push((
+                SearchState {
+                    ferris,
+                    consumed: state.consumed.clone(),
+                    dropped,
+                },
+                step_cost,
+            ));
+            continue;
+        }
+        let next = if state.consumed.contains(&pos) {
+            // used up earlier on this path, so it is plain floor now
+            let mut ferris = state.ferris.clone();
+            ferris.pos = pos;
+            Some((ferris, Interaction::None))
+        } else {
+            step_to(grid, rules, &state.ferris, pos)
+        };
+
+        if let Some((ferris, interaction)) = next {
+            let mut consumed = state.consumed.clone();
+            let track_consumed = rules.key_consumption == KeyConsumption::PerDoor
+                || rules.key_capacity == KeyCapacity::Single
+                || matches!(interaction, Interaction::PickUpKey(_));
+            if track_consumed && interaction != Interaction::None {
+                consumed.push(pos);
+                // keep it canonical for hashing
+                consumed.sort_by_key(|pos| (pos.y, pos.x));
+            }
+            // cells used up earlier on this path are floor now
+            let tile = if state.consumed.contains(&pos) {
+                None
+            } else {
+                grid.get(pos)
+            };
+            let cost = tile_cost(tile).unwrap_or(1) * step_cost;
+            successors.push((
+                SearchState {
+                    ferris,
+                    consumed,
+                    dropped: state.dropped.clone(),
+                },
+                cost,
+            ));
+        }
+    }
+    successors
+}
+
+// pure version of the in-game solver: shortest path (list of states including start) and
+// its cost (in ORTHOGONAL_COST units), or None if no exit is reachable. With several exits
+// the path leads to the cheapest one.
+pub fn solve_grid(
+    grid: &TileGrid,
+    rules: &Rules,
+    start: &Ferris,
+    exits: &[UVec2],
+    options: &SolveOptions,
+) -> Option<(Vec<Ferris>, i32)> {
+    solve_grid_expanded(grid, rules, start, exits, options).0
+}
+
+// solve_grid, also returning the number of states the search expanded
+pub fn solve_grid_expanded(
+    grid: &TileGrid,
+    rules: &Rules,
+    start: &Ferris,
+    exits: &[UVec2],
+    options: &SolveOptions,
+) -> (Option<(Vec<Ferris>, i32)>, u32) {
+    let mut expanded = 0u32;
+    // octile distance with eight way movement. A diagonal step is never counted as more
+    // expensive than the two orthogonal steps it replaces, so it stays admissible for any
+    // diagonal_cost.
+    let diagonal_cost = (options.diagonal_cost as i32).min(2 * ORTHOGONAL_COST);
+    let distance = |from: UVec2, to: UVec2| {
+        let d = to.as_i32() - from.as_i32();
+        let d = IVec2::new(d.x.abs(), d.y.abs());
+        if options.eight_way {
+            let diagonal_steps = d.x.min(d.y);
+            diagonal_steps * diagonal_cost + (d.x.max(d.y) - diagonal_steps) * ORTHOGONAL_COST
+        } else {
+            (d.x + d.y) * ORTHOGONAL_COST
+        }
+    };
+    // distance to the closest exit, so it never overestimates with several exits
+    let heuristic = |state: &SearchState| {
+        let cost = exits
+            .iter()
+            .map(|exit| distance(state.ferris.pos, *exit))
+            .min()
+            .unwrap_or(0);
+        (cost as f32 * options.heuristic_weight) as i32
+    };
+    let start = SearchState {
+        ferris: start.clone(),
+        consumed: Vec::new(),
+        dropped: Vec::new(),
+    };
+    let res = astar::astar(
+        &start,
+        |state| {
+            expanded += 1;
+            // no successors ends the search once the open set is used up
+            if expanded > options.max_expanded {
+                return Vec::new();
+            }
+            successors(grid, rules, options, state)
+        },
+        heuristic,
+        // the exit can't be entered before the exit condition is met, checked here anyway
+        // in case ferris starts on it
+        |state| {
+            exits.contains(&state.ferris.pos)
+                && rules
+                    .exit_condition
+                    .is_met(&state.ferris, &rules.key_colors)
+        },
+    );
+    log::debug!(
+        "expanded {} states (heuristic weight {})",
+        expanded,
+        options.heuristic_weight
+    );
+    // a path found after that may not be the shortest one
+    if expanded > options.max_expanded {
+        log::warn!(
+            "solver stopped expanding after {} states",
+            options.max_expanded
+        );
+    }
+    let res = res.map(|(states, cost)| {
+        let path = states.into_iter().map(|state| state.ferris).collect();
+        (path, cost)
+    });
+    (res, expanded)
+}
+
+pub struct BatchResult {
+    pub name: String,
+    // None if the map has no start / end or no path
+    pub cost: Option<i32>,
+    pub duration: Duration,
+}
+
+// solves each map from its start tile to its end tile, e.g. for benchmarking
+pub fn solve_batch(
+    maps: &[(String, TileGrid)],
+    rules: &Rules,
+    options: &SolveOptions,
+) -> Vec<BatchResult> {
+    maps.iter()
+        .map(|(name, grid)| {
+            let start_time = Instant::now();
+            let exits = grid.find_all(END_TILE);
+            let cost = match grid.find(START_TILE) {
+                Some(start_pos) if !exits.is_empty() => {
+                    let start = Ferris::at(start_pos);
+                    solve_grid(grid, rules, &start, &exits, options).map(|(_, cost)| cost)
+                }
+                _ => None,
+            };
+            BatchResult {
+                name: name.clone(),
+                cost,
+                duration: start_time.elapsed(),
+            }
+        })
+        .collect()
+}
+
+// loads all *.txt ascii maps in dir, sorted by file name
+pub fn load_ascii_maps(dir: &Path) -> Result<Vec<(String, TileGrid)>> {
+    let mut paths = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    paths.retain(|path| path.extension().map_or(false, |ext| ext == "txt"));
+    paths.sort();
+
+    let mut maps = Vec::new();
+    for path in paths {
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let grid = tilegrid_from_ascii(&fs::read_to_string(&path)?)?;
+        maps.push((name, grid));
+    }
+    Ok(maps)
+}
@@ -1,10 +1,14 @@
-use anyhow::Result;
 use bevy::{
     asset::{AssetLoader, LoadedAsset},
     prelude::*,
     reflect::TypeUuid,
+    sprite::Rect,
 };
-use std::{collections::HashMap, path::Path};
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+use thiserror::Error;
 
 mod aseprite {
     use serde::Deserialize;
@@ -22,6 +26,34 @@ mod aseprite {
         pub from: u32,
         pub to: u32,
         pub direction: String,
+        // "1" for tags that play once (newer aseprite versions)
+        #[serde(default)]
+        pub repeat: Option<String>,
+        // user data of the tag
+        #[serde(default)]
+        pub data: Option<String>,
+    }
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct Bounds {
+        pub x: u32,
+        pub y: u32,
+        pub w: u32,
+        pub h: u32,
+    }
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct SliceKey {
+        pub frame: u32,
+        pub bounds: Bounds,
+        // only present for nine-patch slices
+        pub center: Option<Bounds>,
+    }
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct Slice {
+        pub name: String,
+        pub keys: Vec<SliceKey>,
     }
     #[derive(Debug, Deserialize)]
     #[serde(rename_all = "camelCase")]
@@ -32,6 +64,8 @@ mod aseprite {
         pub format: String,
         pub scale: String,
         pub frame_tags: Vec<FrameTag>,
+        #[serde(default)]
+        pub slices: Vec<Slice>,
     }
 
     #[derive(Debug, Deserialize)]
@@ -42,20 +76,77 @@ mod aseprite {
     }
 }
 
+#[derive(Debug, Error)]
+pub enum SpritesheetError {
+    #[error("failed to parse spritesheet json: {0}")]
+    Parse(#[from] serde_json::Error),
+    #[error("spritesheet does not reference an image")]
+    MissingImage,
+    #[error("spritesheet has no frame tags")]
+    EmptyFrameTags,
+    #[error("tag '{tag}' ends before it starts (from {from} to {to})")]
+    InvertedTag { tag: String, from: u32, to: u32 },
+    #[error("tag '{tag}' references frame {frame} but only {frames} frames exist")]
+    FrameOutOfRange {
+        tag: String,
+        frame: u32,
+        frames: usize,
+    },
+}
+
+// semantic checks the json structure can't express. Catches authoring mistakes that would
+// otherwise show up as out of range sprite indices.
+fn validate(desc: &aseprite::Desc) -> Result<(), SpritesheetError> {
+    if desc.meta.image.is_empty() {
+        return Err(SpritesheetError::MissingImage);
+    }
+    if desc.meta.frame_tags.is_empty() {
+        return Err(SpritesheetError::EmptyFrameTags);
+    }
+    for tag in desc.meta.frame_tags.iter() {
+        if tag.from > tag.to {
+            return Err(SpritesheetError::InvertedTag {
+                tag: tag.name.clone(),
+                from: tag.from,
+                to: tag.to,
+            });
+        }
+        if tag.to as usize >= desc.frames.len() {
+            return Err(SpritesheetError::FrameOutOfRange {
+                tag: tag.name.clone(),
+                frame: tag.to,
+                frames: desc.frames.len(),
+            });
+        }
+    }
+    Ok(())
+}
+
 #[derive(Debug, TypeUuid)]
 #[uuid = "ab3a0ad8-6fbc-4528-a4a5-90e7bf3fa9e1"]
 pub struct Spritesheet {
     pub image: String,
     pub ranges: HashMap<String, std::ops::Range<u32>>,
     pub durations: Vec<u32>,
+    // tags that stop on their last frame instead of looping: a repeat count of 1, or
+    // "oneshot" in the tag's user data
+    pub one_shot: HashSet<String>,
+    // named regions of the image (in pixels), from the first key of each aseprite slice
+    pub slices: HashMap<String, Rect>,
 }
 
 impl Spritesheet {
-    fn try_from_bytes(asset_path: &Path, bytes: Vec<u8>) -> Result<Spritesheet> {
-        let desc: aseprite::Desc = serde_json::from_slice(&bytes[..]).unwrap();
+    // what the asset loader does with a spritesheet json exported by aseprite
+    pub fn try_from_bytes(
+        asset_path: &Path,
+        bytes: Vec<u8>,
+    ) -> Result<Spritesheet, SpritesheetError> {
+        let desc: aseprite::Desc = serde_json::from_slice(&bytes[..])?;
 
         println!("desc: {:?}", desc);
 
+        validate(&desc)?;
+
         let ranges = desc
             .meta
             .frame_tags
@@ -63,12 +154,40 @@ impl Spritesheet {
             .map(|tag| (tag.name.clone(), tag.from..tag.to))
             .collect();
 
+        let one_shot = desc
+            .meta
+            .frame_tags
+            .iter()
+            .filter(|tag| {
+                tag.repeat.as_deref() == Some("1")
+                    || tag
+                        .data
+                        .as_deref()
+                        .map_or(false, |data| data.contains("oneshot"))
+            })
+            .map(|tag| tag.name.clone())
+            .collect();
+
         let durations = desc.frames.iter().map(|f| f.duration).collect();
 
+        let slices = desc
+            .meta
+            .slices
+            .iter()
+            .filter_map(|slice| {
+                let bounds = &slice.keys.first()?.bounds;
+                let min = Vec2::new(bounds.x as f32, bounds.y as f32);
+                let max = min + Vec2::new(bounds.w as f32, bounds.h as f32);
+                Some((slice.name.clone(), Rect { min, max }))
+            })
+            .collect();
+
         let spritesheet = Spritesheet {
-            image: "".into(),
+            image: desc.meta.image,
             ranges,
             durations,
+            one_shot,
+            slices,
         };
 
         Ok(spritesheet)
@@ -0,0 +1,320 @@
+// Headless App running the gameplay systems on a TileGrid from an ASCII map, for driving
+// ferris with key presses in tests. No window or tile map entities: set_tile and friends
+// of MapQuery just fail, the TileGrid is what tests look at. Time advances by FRAME per
+// step, so everything timed is deterministic.
+use std::{
+    marker::PhantomData,
+    time::{Duration, Instant},
+};
+
+use bevy::{
+    ecs::component::Component,
+    input::{keyboard::KeyboardInput, ElementState},
+    window::WindowFocused,
+};
+
+use super::*;
+
+pub const FRAME: Duration = Duration::from_millis(16);
+
+pub struct TestApp {
+    pub app: App,
+    pub ferris: Entity,
+    now: Instant,
+}
+
+// events of type T seen so far, see TestApp::count
+struct EventCount<T> {
+    count: u32,
+    _marker: PhantomData<T>,
+}
+
+impl<T> Default for EventCount<T> {
+    fn default() -> Self {
+        EventCount {
+            count: 0,
+            _marker: PhantomData,
+        }
+    }
+}
+
+fn count_events<T: Component>(mut events: EventReader<T>, mut count: ResMut<EventCount<T>>) {
+    count.count += events.iter().count() as u32;
+}
+
+impl TestApp {
+    pub fn from_ascii(ascii: &str) -> Self {
+        let grid = tilegrid_from_ascii(ascii).expect("invalid test map");
+        let start = Ferris::at(grid.find(START_TILE).expect("test map has no start"));
+        let exits = grid.find_all(END_TILE);
+        assert!(!exits.is_empty(), "test map has no end");
+        // what update_key_colors does once a map is loaded
+        let rules = Rules {
+            key_colors: key_colors(&grid),
+            ..Default::default()
+        };
+
+        let mut app = App::new();
+        app.insert_resource(LevelStart(grid.clone()))
+            .insert_resource(grid)
+            .init_resource::<Time>()
+            .init_resource::<Windows>()
+            .init_resource::<Input<KeyCode>>()
+            .init_resource::<Input<MouseButton>>()
+            .insert_resource(rules)
+            .init_resource::<SolveOptions>()
+            .init_resource::<SolveToggles>()
+            .init_resource::<SolveFrom>()
+            .init_resource::<SolveResult>()
+            .init_resource::<PlaybackLoops>()
+            .init_resource::<PlaybackSpeed>()
+            .init_resource::<ConsumeMode>()
+            .init_resource::<PendingChunkUpdates>()
+            .init_resource::<LastCheckpoint>()
+            .init_resource::<MapOrigin>()
+            .init_resource::<Tunables>()
+            .init_resource::<TimeScale>()
+            .init_resource::<StepMode>()
+            .init_resource::<FocusPause>()
+            .init_resource::<MovementStyle>()
+            .init_resource::<SquashStretch>()
+            .init_resource::<AnimationSync>()
+            .init_resource::<WallBumpPenalty>()
+            .init_resource::<Lives>()
+            .init_resource::<ColorScheme>()
+            .init_resource::<LightRadius>()
+            .init_resource::<RevealAll>()
+            .init_resource::<DoorGlow>()
+            .init_resource::<PairPulse>()
+            .init_resource::<TileWobble>()
+            .init_resource::<MotionTrail>()
+            .init_resource::<BreadcrumbLifetime>()
+            .init_resource::<KeyPopupDuration>()
+            .init_resource::<KeysAcrossLevels>()
+            .init_resource::<LevelMeta>()
+            .init_resource::<RunStats>()
+            .init_resource::<PortalExit>()
+            .init_resource::<QuitDialog>()
+            .init_resource::<Console>()
+            .init_resource::<CameraMode>()
+            .insert_resource(GameRng::from_seed(DEFAULT_SEED))
+            .add_event::<KeyboardInput>()
+            .add_event::<WindowFocused>()
+            .add_event::<NoPath>()
+            .add_event::<KeyPickedUp>()
+            .add_event::<DoorOpened>()
+            .add_event::<KeyDropped>()
+            .add_event::<WallBump>()
+            .add_event::<SolveRequested>()
+            .add_event::<GameWon>()
+            .add_event::<LevelFailed>()
+            .add_event::<Died>()
+            .add_event::<GameOver>()
+            .add_event::<ConsoleCommand>()
+            .add_event::<AnimationFinished>()
+            .add_event::<LevelExited>()
+            .add_event::<AddTrauma>()
+            .add_event::<TileGridReady>()
+            .add_event::<LevelBalanceWarning>()
+            .add_event::<LevelValidationFailed>()
+            .insert_resource(LoadingState {
+                loaded: true,
+                ..Default::default()
+            })
+            .add_system(step_mode_input.system().label("step_mode_input"))
+            .add_system(
+                character_input
+                    .system()
+                    .label("character_input")
+                    .with_run_criteria(run_if_playing.system())
+                    .after("step_mode_input"),
+            )
+            .add_system(
+                solve_input
+                    .system()
+                    .label("solve_input")
+                    .with_run_criteria(run_if_playing.system())
+                    .after("step_mode_input"),
+            )
+            .add_system(detect_win.system().after("character_input"));
+
+        let ferris = app
+            .world
+            .spawn()
+            .insert(start.clone())
+            .insert(Timer::from_seconds(0.1, true))
+            .insert(EndPos(exits))
+            .insert(TargetTracker::default())
+            .insert(Facing::Right)
+            .insert(StartState(start.clone()))
+            .insert(TextureAtlasSprite::new(0))
+            .insert(Transform::from_translation(pos_to_translation(
+                &MapOrigin::default(),
+                &start.pos,
+                zorder::Layer::Dynamic,
+            )))
+            .insert(GlobalTransform::default())
+            .id();
+
+        // the first update only sets the reference point, no delta yet
+        let now = Instant::now();
+        app.world
+            .get_resource_mut::<Time>()
+            .unwrap()
+            .update_with_instant(now);
+        let mut test_app = TestApp { app, ferris, now };
+        test_app.count::<GameWon>();
+        test_app
+    }
+
+    // asset server and the asset types used by systems spawning sprites. Nothing is
+    // actually loaded, loads of missing assets just fail in the background.
+    pub fn with_assets(&mut self) -> &mut Self {
+        self.app
+            .insert_resource(bevy::tasks::IoTaskPool(bevy::tasks::TaskPool::new()))
+            .add_plugin(bevy::asset::AssetPlugin)
+            .add_asset::<Texture>()
+            .add_asset::<ColorMaterial>()
+            .add_asset::<TextureAtlas>()
+            .add_asset::<spritesheet::Spritesheet>()
+            .add_asset::<LdtkMap>();
+        self
+    }
+
+    pub fn insert<T: Component>(&mut self, resource: T) -> &mut Self {
+        self.app.insert_resource(resource);
+        self
+    }
+
+    pub fn resource<T: Component>(&self) -> &T {
+        self.app.world.get_resource::<T>().unwrap()
+    }
+
+    pub fn resource_mut<T: Component>(&mut self) -> Mut<T> {
+        self.app.world.get_resource_mut::<T>().unwrap()
+    }
+
+    // counts the events of type T from now on, read them with events::<T>()
+    pub fn count<T: Component>(&mut self) -> &mut Self {
+        self.app
+            .init_resource::<EventCount<T>>()
+            .add_system_to_stage(CoreStage::Last, count_events::<T>.system());
+        self
+    }
+
+    pub fn events<T: Component>(&self) -> u32 {
+        self.resource::<EventCount<T>>().count
+    }
+
+    pub fn send<T: Component>(&mut self, event: T) {
+        self.resource_mut::<Events<T>>().send(event);
+    }
+
+    // the key counts as just pressed in the next step, and comes last of all keys
+    // pressed before that step
+    pub fn press(&mut self, key: KeyCode) {
+        self.resource_mut::<Input<KeyCode>>().press(key);
+        self.send(KeyboardInput {
+            scan_code: 0,
+            key_code: Some(key),
+            state: ElementState::Pressed,
+        });
+    }
+
+    pub fn click(&mut self, button: MouseButton) {
+        self.resource_mut::<Input<MouseButton>>().press(button);
+    }
+
+    // runs n frames. Keys are released after the first one, like a short tap.
+    pub fn step(&mut self, n: usize) {
+        for _ in 0..n {
+            self.now += FRAME;
+            let now = self.now;
+            self.resource_mut::<Time>().update_with_instant(now);
+            self.app.update();
+            let mut input = self.resource_mut::<Input<KeyCode>>();
+            let pressed: Vec<KeyCode> = input.get_pressed().copied().collect();
+            for key in pressed {
+                input.release(key);
+            }
+            input.clear();
+            let mut mouse = self.resource_mut::<Input<MouseButton>>();
+            let pressed: Vec<MouseButton> = mouse.get_pressed().copied().collect();
+            for button in pressed {
+                mouse.release(button);
+            }
+            mouse.clear();
+        }
+    }
+
+    // one key press and one frame
+    pub fn tap(&mut self, key: KeyCode) {
+        self.press(key);
+        self.step(1);
+    }
+
+    pub fn ferris(&self) -> Ferris {
+        self.app.world.get::<Ferris>(self.ferris).unwrap().clone()
+    }
+
+    pub fn ferris_pos(&self) -> UVec2 {
+        self.ferris().pos
+    }
+
+    pub fn set_ferris(&mut self, ferris: Ferris) {
+        *self.app.world.get_mut::<Ferris>(self.ferris).unwrap() = ferris;
+    }
+
+    pub fn get<T: Component>(&self) -> Option<&T> {
+        self.app.world.get::<T>(self.ferris)
+    }
+
+    pub fn transform(&self) -> Transform {
+        *self.get::<Transform>().unwrap()
+    }
+
+    // what move_ferris does once ferris visually arrived at his cell, drives playback
+    pub fn arrive(&mut self) {
+        self.app
+            .world
+            .get_mut::<TargetTracker>(self.ferris)
+            .unwrap()
+            .count += 1;
+    }
+
+    pub fn grid(&self) -> &TileGrid {
+        self.resource::<TileGrid>()
+    }
+
+    pub fn won(&self) -> bool {
+        self.events::<GameWon>() > 0
+    }
+}
+
+#[test]
+fn walk_to_exit() {
+    let mut app = TestApp::from_ascii(
+        "######\n\
+         #S.aA#\n\
+         ####E#\n\
+         ######\n",
+    );
+    let start = app.ferris_pos();
+
+    // into the wall
+    app.tap(KeyCode::Up);
+    assert_eq!(app.ferris_pos(), start);
+
+    for _ in 0..2 {
+        app.tap(KeyCode::Right);
+    }
+    assert_eq!(app.ferris_pos(), start + UVec2::new(2, 0));
+    assert_eq!(app.ferris().keys, [1, 0, 0]);
+
+    app.tap(KeyCode::Right);
+    assert!(!app.won());
+
+    app.tap(KeyCode::Down);
+    assert_eq!(app.grid().get(app.ferris_pos()), Some(END_TILE));
+    assert!(app.won());
+}
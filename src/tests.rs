@@ -0,0 +1,3779 @@
+// gameplay tests driving the systems of the game through the testkit harness
+use ferris_lab::grid::get_neighboring_pos;
+use ferris_lab::locks::LockError;
+use ferris_lab::replay::ReplayError;
+use ferris_lab::rules::KeyConsumption;
+use ferris_lab::solver::solve_grid_expanded;
+
+use super::testkit::{TestApp, FRAME};
+use super::*;
+
+// straight corridor, start at (1, 1) and the exit at its end
+const CORRIDOR: &str = "#######\n\
+                        #S....E\n\
+                        #######\n";
+
+// tile entity as the tile map plugin spawns it, without the map / chunk around it
+fn spawn_tile(app: &mut TestApp, pos: UVec2, texture_index: u16, layer_id: u16) -> Entity {
+    let chunk = app.app.world.spawn().id();
+    app.app
+        .world
+        .spawn()
+        .insert(Tile {
+            texture_index,
+            ..Default::default()
+        })
+        .insert(TilePos(pos.x, pos.y))
+        .insert(TileParent {
+            chunk,
+            layer_id,
+            map_id: LEVEL_ID,
+        })
+        .id()
+}
+
+fn tile_color(app: &TestApp, tile: Entity) -> Color {
+    app.app.world.get::<Tile>(tile).unwrap().color
+}
+
+#[test]
+fn decoration_fades_around_ferris() {
+    let mut app = TestApp::from_ascii(CORRIDOR);
+    app.app
+        .add_system(fade_decoration_tiles.system().after("character_input"));
+    let start = app.ferris_pos();
+    let above = spawn_tile(&mut app, start + UVec2::new(0, 1), 40, DECORATION_LAYER_ID);
+    let far = spawn_tile(&mut app, start + UVec2::new(4, 1), 40, DECORATION_LAYER_ID);
+    let gameplay = spawn_tile(&mut app, start + UVec2::new(0, 1), WALL_TILE, LAYER_ID);
+    app.step(1);
+
+    let fade = DecorationFade::default().0;
+    assert_eq!(tile_color(&app, above).a(), fade);
+    assert_eq!(tile_color(&app, far).a(), 1.0);
+    assert_eq!(tile_color(&app, gameplay).a(), 1.0);
+    // re-meshed with the other changed tiles by flush_chunk_updates
+    assert!(app
+        .resource::<PendingChunkUpdates>()
+        .0
+        .contains(&(DECORATION_LAYER_ID, start + UVec2::new(0, 1))));
+
+    // restored once ferris is two cells away
+    app.tap(KeyCode::Right);
+    app.tap(KeyCode::Right);
+    assert_eq!(tile_color(&app, above).a(), 1.0);
+    assert_eq!(tile_color(&app, far).a(), 1.0);
+    app.tap(KeyCode::Right);
+    assert_eq!(tile_color(&app, far).a(), fade);
+}
+
+// key at (3, 1), its door at (5, 1)
+const KEY_AND_DOOR: &str = "#######\n\
+                            #S.a.AE\n\
+                            #######\n";
+
+fn consuming_app(ascii: &str) -> TestApp {
+    let mut app = TestApp::from_ascii(ascii);
+    app.app
+        .add_system(
+            consume_tiles
+                .system()
+                .label("consume_tiles")
+                .after("character_input"),
+        )
+        .add_system(
+            record_checkpoints
+                .system()
+                .after("consume_tiles")
+                .after("play_solution"),
+        )
+        .add_system(apply_settings.system());
+    app
+}
+
+// what restart_failed_level does to the map on LevelFailed
+fn restart(app: &mut TestApp) {
+    app.with_assets();
+    app.app.add_system(restart_failed_level.system());
+    app.send(LevelFailed);
+    app.step(1);
+}
+
+#[test]
+fn consume_hidden_tile_and_restore() {
+    let mut app = consuming_app(KEY_AND_DOOR);
+    let original = app.grid().clone();
+    set(&mut app, "consume", "hide");
+    assert_eq!(*app.resource::<ConsumeMode>(), ConsumeMode::Hide);
+
+    let key = UVec2::new(3, 1);
+    app.tap(KeyCode::Right);
+    app.tap(KeyCode::Right);
+    assert_eq!(app.ferris_pos(), key);
+    assert_eq!(app.grid().get(key), Some(FLOOR_TILE));
+
+    restart(&mut app);
+    assert_eq!(*app.grid(), original);
+    assert_eq!(app.ferris_pos(), UVec2::new(1, 1));
+}
+
+#[test]
+fn despawned_tiles_can_be_restored_too() {
+    let mut app = consuming_app(KEY_AND_DOOR);
+    let original = app.grid().clone();
+    for _ in 0..4 {
+        app.tap(KeyCode::Right);
+    }
+    // key and door are gone
+    assert_eq!(app.grid().get(UVec2::new(3, 1)), None);
+    assert_eq!(app.grid().get(UVec2::new(5, 1)), None);
+
+    restart(&mut app);
+    assert_eq!(*app.grid(), original);
+}
+
+#[test]
+fn solve_from_start_plays_back_from_the_start_cell() {
+    let mut app = playback_app(KEY_AND_DOOR);
+    app.insert(SolveFrom::Start);
+    let original = app.grid().clone();
+    let start = app.ferris_pos();
+    // the key is despawned, solving on the current map would find no path
+    app.tap(KeyCode::Right);
+    app.tap(KeyCode::Right);
+    app.tap(KeyCode::Left);
+    assert_eq!(app.ferris().keys, [1, 0, 0]);
+
+    app.send(SolveRequested);
+    app.step(1);
+    app.arrive();
+    app.step(1);
+    assert_eq!(app.ferris_pos(), start);
+    assert_eq!(app.ferris().keys, [0, 0, 0]);
+    assert_eq!(*app.grid(), original);
+
+    play_out(&mut app, 10);
+    assert!(app.won());
+}
+
+fn playback_app(ascii: &str) -> TestApp {
+    let mut app = consuming_app(ascii);
+    app.app.add_system(
+        play_solution
+            .system()
+            .label("play_solution")
+            .after("solve_input"),
+    );
+    app
+}
+
+// arrivals until playback stopped, at most max
+fn play_out(app: &mut TestApp, max: usize) {
+    for _ in 0..max {
+        app.arrive();
+        app.step(1);
+    }
+}
+
+#[test]
+fn playback_loops_twice() {
+    let mut app = playback_app(KEY_AND_DOOR);
+    app.insert(PlaybackLoops(Some(2)));
+    let original = app.grid().clone();
+    app.tap(KeyCode::R);
+    play_out(&mut app, 30);
+
+    assert_eq!(app.events::<GameWon>(), 2);
+    assert_eq!(app.get::<Solution>().unwrap().plays, 2);
+    // the key and door were consumed again on the second play
+    assert_eq!(app.grid().get(UVec2::new(3, 1)), None);
+    assert_eq!(app.grid().get(UVec2::new(5, 1)), None);
+    assert_ne!(*app.grid(), original);
+}
+
+#[test]
+fn no_path_stops_playback() {
+    let mut app = playback_app("#####\n#S#E#\n#####\n");
+    app.insert(PlaybackLoops(None)).count::<NoPath>();
+    app.tap(KeyCode::R);
+    play_out(&mut app, 3);
+    assert_eq!(app.events::<NoPath>(), 1);
+    assert!(app.get::<Solution>().is_none());
+    assert!(!app.won());
+}
+
+fn set(app: &mut TestApp, option: &str, value: &str) {
+    app.send(ConsoleCommand(Command::Set(option.into(), value.into())));
+    app.step(1);
+}
+
+#[test]
+fn wall_bump_fails_the_level() {
+    let mut app = consuming_app(KEY_AND_DOOR);
+    app.app
+        .add_system(apply_wall_bump_penalty.system().after("character_input"));
+    app.count::<LevelFailed>();
+    let original = app.grid().clone();
+    app.tap(KeyCode::Up);
+    assert_eq!(app.events::<LevelFailed>(), 0);
+
+    set(&mut app, "penalty", "fail");
+    assert_eq!(*app.resource::<WallBumpPenalty>(), WallBumpPenalty::Fail);
+    app.tap(KeyCode::Right);
+    app.tap(KeyCode::Right);
+    app.tap(KeyCode::Up);
+    assert_eq!(app.events::<LevelFailed>(), 1);
+
+    // back to the start, key included
+    restart(&mut app);
+    assert_eq!(app.ferris_pos(), UVec2::new(1, 1));
+    assert_eq!(*app.grid(), original);
+}
+
+// checkpoint at (2, 1)
+const CHECKPOINT: &str = "#######\n\
+                          #S+..E#\n\
+                          #######\n";
+
+fn dying_app(ascii: &str) -> TestApp {
+    let mut app = consuming_app(ascii);
+    app.app
+        .add_system(handle_deaths.system().after("character_input"));
+    app.count::<GameOver>();
+    app
+}
+
+#[test]
+fn died_without_checkpoint_respawns_at_start() {
+    let mut app = dying_app(KEY_AND_DOOR);
+    let original = app.grid().clone();
+    app.tap(KeyCode::Right);
+    app.tap(KeyCode::Right);
+    assert_eq!(app.ferris().keys, [1, 0, 0]);
+
+    app.send(Died);
+    app.step(1);
+    assert_eq!(app.ferris(), Ferris::at(UVec2::new(1, 1)));
+    assert_eq!(*app.grid(), original);
+    assert_eq!(app.resource::<Lives>().0, START_LIVES - 1);
+    assert_eq!(app.events::<GameOver>(), 0);
+}
+
+#[test]
+fn died_respawns_at_checkpoint_until_game_over() {
+    let mut app = dying_app(CHECKPOINT);
+    set(&mut app, "lives", "2");
+    app.tap(KeyCode::Right);
+    app.tap(KeyCode::Right);
+    assert_eq!(app.ferris_pos(), UVec2::new(3, 1));
+
+    app.send(Died);
+    app.step(1);
+    assert_eq!(app.ferris_pos(), UVec2::new(2, 1));
+    assert_eq!(app.resource::<Lives>().0, 1);
+    assert_eq!(app.events::<GameOver>(), 0);
+
+    app.tap(KeyCode::Right);
+    app.send(Died);
+    app.step(1);
+    assert_eq!(app.resource::<Lives>().0, 0);
+    assert_eq!(app.events::<GameOver>(), 1);
+}
+
+#[test]
+fn replay_code_round_trip() {
+    let moves: Vec<Direction> = std::iter::repeat(Direction::Right)
+        .take(70)
+        .chain(vec![Direction::Up, Direction::Left, Direction::Down])
+        .collect();
+    let replay = Replay { level: 258, moves };
+    let code = encode_replay(&replay);
+    assert!(code.starts_with("FL1:"));
+    assert_eq!(decode_replay(&code).unwrap(), replay);
+    assert!(decode_replay("FL1:").is_err());
+    assert!(decode_replay("nonsense").is_err());
+}
+
+#[test]
+fn diagonal_paths_are_not_exported() {
+    let path: Vec<Ferris> = [(1, 1), (2, 1), (3, 2)]
+        .iter()
+        .map(|&(x, y)| Ferris::at(UVec2::new(x, y)))
+        .collect();
+    assert!(matches!(
+        Replay::from_path(0, path.iter()),
+        Err(ReplayError::Diagonal(1, 2))
+    ));
+    // further apart is still not a neighbor
+    let path = vec![Ferris::at(UVec2::new(1, 1)), Ferris::at(UVec2::new(3, 1))];
+    assert!(matches!(
+        Replay::from_path(0, path.iter()),
+        Err(ReplayError::NotNeighbors(0, 1))
+    ));
+}
+
+#[test]
+fn export_and_import_from_level_start() {
+    let mut app = playback_app(KEY_AND_DOOR);
+    app.with_assets();
+    app.app.add_system(run_console_commands.system());
+    app.insert(MapSource::Generated)
+        .insert(SolveFrom::Start)
+        .insert(LevelMeta {
+            uid: 7,
+            ..Default::default()
+        });
+    // the key is gone from the current map
+    app.tap(KeyCode::Right);
+    app.tap(KeyCode::Right);
+    app.tap(KeyCode::R);
+    app.send(ConsoleCommand(Command::Export));
+    app.step(1);
+    let code = app.resource::<Console>().output.back().unwrap().clone();
+    let replay = decode_replay(&code).unwrap();
+    assert_eq!(replay.level, 7);
+    assert_eq!(replay.moves, vec![Direction::Right; 5]);
+
+    app.send(ConsoleCommand(Command::Import(code)));
+    app.step(1);
+    play_out(&mut app, 10);
+    assert!(app.won());
+    let output = &app.resource::<Console>().output;
+    assert!(output.iter().all(|line| !line.starts_with("warning")));
+}
+
+// pixels ferris walked in the frame of a move to the right
+fn first_step_distance(app: &mut TestApp) -> f32 {
+    let x = app.transform().translation.x;
+    app.tap(KeyCode::Right);
+    app.transform().translation.x - x
+}
+
+#[test]
+fn step_size_sets_the_walking_speed() {
+    let mut app = consuming_app(CORRIDOR);
+    app.app
+        .add_system(move_ferris.system().after("character_input"));
+    assert_eq!(first_step_distance(&mut app), Tunables::default().step_size);
+
+    set(&mut app, "step_size", "2");
+    assert_eq!(app.resource::<Tunables>().step_size, 2.0);
+    // finish the walk to the current cell first
+    app.step(40);
+    assert_eq!(first_step_distance(&mut app), 2.0);
+
+    set(&mut app, "step_size", "fast");
+    assert_eq!(app.resource::<Tunables>().step_size, 2.0);
+}
+
+#[test]
+fn teleport_uses_each_key_once() {
+    // both keys are on a dead end, the first one is passed twice
+    let mut app = playback_app(
+        "#############\n\
+         #E.A.A.S.a.a#\n\
+         #############\n",
+    );
+    app.insert(SolveResult::Teleport).insert(Rules {
+        key_consumption: KeyConsumption::PerDoor,
+        ..Default::default()
+    });
+    app.tap(KeyCode::R);
+    app.arrive();
+    app.step(1);
+
+    assert_eq!(app.ferris_pos(), UVec2::new(1, 1));
+    assert_eq!(app.ferris().keys, [0, 0, 0]);
+    assert!(app.won());
+    for x in [3, 5, 9, 11] {
+        assert_eq!(app.grid().get(UVec2::new(x, 1)), None);
+    }
+}
+
+#[test]
+fn drop_and_pick_up_a_single_key() {
+    // key a at (3, 1), key b at (4, 1)
+    let mut app = consuming_app("########\n#S.ab.E#\n########\n");
+    app.app
+        .add_system(drop_key_input.system().label("drop_key_input"))
+        .add_system(place_dropped_keys.system().after("drop_key_input"));
+    set(&mut app, "keycap", "single");
+    assert_eq!(app.resource::<Rules>().key_capacity, KeyCapacity::Single);
+
+    app.tap(KeyCode::Right);
+    app.tap(KeyCode::Right);
+    // b is walked over while holding a
+    app.tap(KeyCode::Right);
+    app.tap(KeyCode::Right);
+    assert_eq!(app.ferris().keys, [1, 0, 0]);
+    assert_eq!(app.grid().get(UVec2::new(3, 1)), None);
+    assert_eq!(app.grid().get(UVec2::new(4, 1)), Some(key_tile(1)));
+
+    app.tap(KeyCode::Q);
+    assert_eq!(app.ferris().keys, [0, 0, 0]);
+    assert_eq!(app.grid().get(UVec2::new(5, 1)), Some(key_tile(0)));
+
+    app.tap(KeyCode::Left);
+    assert_eq!(app.ferris().keys, [0, 1, 0]);
+    assert_eq!(app.grid().get(UVec2::new(4, 1)), None);
+
+    // drop b and take a again
+    app.tap(KeyCode::Q);
+    app.tap(KeyCode::Right);
+    assert_eq!(app.ferris().keys, [1, 0, 0]);
+    assert_eq!(app.grid().get(UVec2::new(4, 1)), Some(key_tile(1)));
+    assert_eq!(app.grid().get(UVec2::new(5, 1)), None);
+}
+
+#[test]
+fn solver_picks_up_keys_in_order() {
+    // a is closer, but b has to come first
+    let mut app = consuming_app("###########\n#a.S.b.ABE#\n###########\n");
+    let solve_path = |app: &TestApp| {
+        let grid = app.grid();
+        let exits = grid.find_all(END_TILE);
+        let start = Ferris::at(grid.find(START_TILE).unwrap());
+        let rules = app.resource::<Rules>();
+        solve_grid(grid, rules, &start, &exits, &SolveOptions::default())
+            .unwrap()
+            .0
+    };
+    let picked = |path: &[Ferris], color_id: usize| {
+        path.iter()
+            .position(|state| state.keys[color_id] > 0)
+            .unwrap()
+    };
+    let unordered = solve_path(&app);
+    assert_eq!(unordered.len(), 11);
+    assert!(picked(&unordered, 0) < picked(&unordered, 1));
+
+    set(&mut app, "order", "1,0");
+    assert_eq!(
+        app.resource::<Rules>().key_order,
+        OrderConstraint(vec![1, 0])
+    );
+    let ordered = solve_path(&app);
+    assert_eq!(ordered.len(), 15);
+    assert!(picked(&ordered, 1) < picked(&ordered, 0));
+
+    // walking over a first leaves it where it is
+    app.tap(KeyCode::Left);
+    app.tap(KeyCode::Left);
+    assert_eq!(app.ferris().keys, [0, 0, 0]);
+    assert_eq!(app.grid().get(UVec2::new(1, 1)), Some(key_tile(0)));
+}
+
+fn console_app() -> TestApp {
+    let mut app = TestApp::from_ascii(CORRIDOR);
+    app.with_assets();
+    app.app
+        .add_event::<LoadLevel>()
+        .add_event::<LoadMap>()
+        .init_resource::<CurrentLevel>()
+        .add_system(run_console_commands.system().label("run_console_commands"))
+        .add_system(switch_level.system().after("run_console_commands"))
+        .add_system(load_map.system().after("run_console_commands"));
+    app.insert(MapSource::Ldtk)
+        .count::<SolveRequested>()
+        .count::<LoadLevel>();
+    app
+}
+
+// what typing line into the console and pressing return does
+fn type_command(app: &mut TestApp, line: &str) {
+    match parse_command(line) {
+        Ok(command) => app.send(ConsoleCommand(command)),
+        Err(err) => app
+            .resource_mut::<Console>()
+            .print(format!("error: {}", err)),
+    }
+    app.step(1);
+}
+
+fn last_output(app: &TestApp) -> String {
+    app.resource::<Console>()
+        .output
+        .back()
+        .cloned()
+        .unwrap_or_default()
+}
+
+#[test]
+fn console_commands_are_dispatched() {
+    let mut app = console_app();
+    type_command(&mut app, "goto 4 1");
+    assert_eq!(app.ferris_pos(), UVec2::new(4, 1));
+    type_command(&mut app, "goto 40 1");
+    assert!(last_output(&app).starts_with("error"));
+    assert_eq!(app.ferris_pos(), UVec2::new(4, 1));
+
+    type_command(&mut app, "givekey 2");
+    assert_eq!(app.ferris().keys, [0, 0, 1]);
+    type_command(&mut app, "givekey 3");
+    assert_eq!(last_output(&app), "error: usage: givekey <0-2>");
+
+    type_command(&mut app, "solve");
+    assert_eq!(app.events::<SolveRequested>(), 1);
+
+    type_command(&mut app, "level 1");
+    assert_eq!(app.events::<LoadLevel>(), 1);
+    assert_eq!(app.resource::<CurrentLevel>().0, 1);
+    type_command(&mut app, "level 7");
+    assert_eq!(app.events::<LoadLevel>(), 1);
+    assert!(last_output(&app).starts_with("error"));
+
+    type_command(&mut app, "fly");
+    assert_eq!(last_output(&app), "error: unknown command 'fly'");
+}
+
+#[test]
+fn edited_map_saves_and_loads_back() {
+    let path = std::env::temp_dir().join(format!("ferris_map_{}.txt", std::process::id()));
+    let mut app = console_app();
+    // a wall in the corridor and the exit moved one cell to the left
+    {
+        let mut grid = app.resource::<TileGrid>().clone();
+        let mut level_start = LevelStart(grid.clone());
+        assert_eq!(
+            edit_tile(&mut grid, &mut level_start, UVec2::new(3, 1)),
+            Some(WALL_TILE)
+        );
+        for _ in 0..3 {
+            edit_tile(&mut grid, &mut level_start, UVec2::new(5, 1));
+        }
+        assert_eq!(grid.get(UVec2::new(5, 1)), Some(END_TILE));
+        assert_eq!(grid, level_start.0);
+        app.insert(grid).insert(level_start);
+    }
+    let edited = app.grid().clone();
+
+    type_command(&mut app, &format!("save {}", path.display()));
+    assert!(last_output(&app).starts_with("saved"));
+    // the map changes some more, loading puts it back
+    let mut grid = app.resource_mut::<TileGrid>();
+    grid.set(UVec2::new(3, 1), None);
+    type_command(&mut app, &format!("load {}", path.display()));
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(*app.grid(), edited);
+    assert_eq!(*app.resource::<MapSource>(), MapSource::Generated);
+
+    type_command(&mut app, "load /nonexistent/map.txt");
+    assert!(last_output(&app).starts_with("error"));
+    assert_eq!(*app.grid(), edited);
+}
+
+#[test]
+fn edited_start_and_exit_move_with_the_tiles() {
+    let mut app = TestApp::from_ascii(CORRIDOR);
+    app.app
+        .add_event::<MapEdited>()
+        .add_system(update_start_and_exits.system());
+    // keys carried over from the last level
+    let carried = Ferris {
+        keys: [1, 0, 0],
+        collected: [true, false, false],
+        ..app.ferris()
+    };
+    app.app.world.get_mut::<StartState>(app.ferris).unwrap().0 = carried.clone();
+    // start moved one cell to the right, the old exit turned into a checkpoint
+    let mut grid = app.grid().clone();
+    let mut level_start = LevelStart(grid.clone());
+    grid.set(UVec2::new(1, 1), None);
+    grid.set(UVec2::new(2, 1), Some(START_TILE));
+    edit_tile(&mut grid, &mut level_start, UVec2::new(6, 1));
+    grid.set(UVec2::new(4, 1), Some(END_TILE));
+    app.insert(grid);
+    app.send(MapEdited);
+    app.step(1);
+    assert_eq!(
+        app.get::<StartState>().unwrap().0,
+        Ferris {
+            pos: UVec2::new(2, 1),
+            ..carried
+        }
+    );
+    assert_eq!(app.get::<EndPos>().unwrap().0, vec![UVec2::new(4, 1)]);
+}
+
+#[test]
+fn zero_sfx_volume_mutes_effects() {
+    let mut app = TestApp::from_ascii(CORRIDOR);
+    app.app
+        .init_resource::<AudioMixer>()
+        .add_system(ferris_lab::audio::mixer_input.system());
+    // one more than needed, 0.1 steps don't add up to exactly 1.0
+    for _ in 0..11 {
+        app.tap(KeyCode::Key9);
+    }
+    let mixer = app.resource::<AudioMixer>();
+    assert_eq!(mixer.volume(SoundCategory::Sfx), 0.0);
+    assert!(mixer.volume(SoundCategory::Music) > 0.0);
+
+    let audio = Audio::default();
+    assert!(!mixer.play(&audio, Handle::default(), SoundCategory::Sfx));
+    assert!(mixer.play(&audio, Handle::default(), SoundCategory::Music));
+}
+
+#[test]
+fn step_mode_moves_once_per_step() {
+    let mut app = TestApp::from_ascii(CORRIDOR);
+    let start = app.ferris_pos();
+    app.tap(KeyCode::Comma);
+    assert!(app.resource::<StepMode>().active);
+
+    app.tap(KeyCode::Right);
+    app.step(5);
+    assert_eq!(app.ferris_pos(), start);
+
+    app.press(KeyCode::Right);
+    app.tap(KeyCode::Period);
+    assert_eq!(app.ferris_pos(), start + UVec2::new(1, 0));
+    // no step requested in these frames
+    app.step(5);
+    assert_eq!(app.ferris_pos(), start + UVec2::new(1, 0));
+
+    app.tap(KeyCode::Comma);
+    app.tap(KeyCode::Right);
+    assert_eq!(app.ferris_pos(), start + UVec2::new(2, 0));
+}
+
+#[test]
+fn exit_needs_the_keys_on_the_map() {
+    // b is the far key, a lies on the way to the exit
+    let mut app = TestApp::from_ascii("##########\n#b..S.aE.#\n##########\n");
+    app.app.add_system(apply_settings.system());
+    assert_eq!(app.resource::<Rules>().key_colors, vec![0, 1]);
+    set(&mut app, "exit", "allkeys");
+    let rules = app.resource::<Rules>().clone();
+    assert_eq!(rules.exit_condition, ExitCondition::AllKeys);
+
+    let grid = app.grid();
+    let start = Ferris::at(UVec2::new(4, 1));
+    let exit = [UVec2::new(7, 1)];
+    let (path, _) = solve_grid(grid, &rules, &start, &exit, &SolveOptions::default()).unwrap();
+    assert_eq!(path.len(), 10);
+    assert_eq!(path.last().unwrap().collected, [true, true, false]);
+
+    // only the colors on the map count
+    let grid = tilegrid_from_ascii("######\n#S.aE#\n######\n").unwrap();
+    let rules = Rules {
+        key_colors: key_colors(&grid),
+        ..rules
+    };
+    let start = Ferris::at(UVec2::new(1, 1));
+    let (path, _) = solve_grid(
+        &grid,
+        &rules,
+        &start,
+        &[UVec2::new(4, 1)],
+        &SolveOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(path.len(), 4);
+
+    // walking into the exit without b does nothing
+    app.tap(KeyCode::Right);
+    app.tap(KeyCode::Right);
+    app.tap(KeyCode::Right);
+    assert_eq!(app.ferris_pos(), UVec2::new(6, 1));
+    assert!(!app.won());
+}
+
+#[test]
+fn apply_move_in_every_direction() {
+    // key above the start, wall below, door to the left, floor to the right
+    let grid = tilegrid_from_ascii("#####\n##a##\n#AS.#\n#####\n").unwrap();
+    let rules = Rules::default();
+    let start = Ferris::at(UVec2::new(2, 1));
+    let moved = |direction| apply_move(&grid, &rules, &start, direction);
+
+    let (up, interaction) = moved(Direction::Up).unwrap();
+    assert_eq!(up.pos, UVec2::new(2, 2));
+    assert_eq!(up.keys, [1, 0, 0]);
+    assert_eq!(interaction, Interaction::PickUpKey(0));
+
+    assert!(moved(Direction::Down).is_none());
+    // locked without the key
+    assert!(moved(Direction::Left).is_none());
+    let (left, interaction) = apply_move(
+        &grid,
+        &rules,
+        &Ferris {
+            pos: start.pos,
+            ..up
+        },
+        Direction::Left,
+    )
+    .unwrap();
+    assert_eq!(left.pos, UVec2::new(1, 1));
+    assert_eq!(interaction, Interaction::OpenDoor(0));
+
+    let (right, interaction) = moved(Direction::Right).unwrap();
+    assert_eq!(right.pos, UVec2::new(3, 1));
+    assert_eq!(interaction, Interaction::None);
+
+    // never off the map
+    let corner = Ferris::at(UVec2::ZERO);
+    assert!(apply_move(&grid, &rules, &corner, Direction::Down).is_none());
+    assert!(apply_move(&grid, &rules, &corner, Direction::Left).is_none());
+
+    for direction in [
+        Direction::Up,
+        Direction::Down,
+        Direction::Left,
+        Direction::Right,
+    ] {
+        assert_eq!(Direction::from_offset(direction.delta()), Some(direction));
+    }
+    assert_eq!(Direction::from_offset(IVec2::new(1, 1)), None);
+}
+
+fn results_text(app: &mut TestApp) -> String {
+    let mut query = app.app.world.query_filtered::<&Text, With<ResultsPanel>>();
+    query
+        .iter(&app.app.world)
+        .map(|text| text.sections[0].value.clone())
+        .collect()
+}
+
+fn walk_corridor(app: &mut TestApp) {
+    for _ in 0..5 {
+        app.tap(KeyCode::Right);
+    }
+}
+
+#[test]
+fn faster_second_clear_is_a_time_record() {
+    let path = std::env::temp_dir().join(format!("ferris_bests_{}.json", std::process::id()));
+    let mut app = TestApp::from_ascii(CORRIDOR);
+    app.with_assets();
+    app.app
+        .add_system(restart_failed_level.system())
+        .add_system(
+            track_run_stats
+                .system()
+                .label("track_run_stats")
+                .after("character_input"),
+        )
+        .add_system(
+            show_results
+                .system()
+                .after("track_run_stats")
+                .before("hide_results"),
+        )
+        .add_system(hide_results.system().label("hide_results"));
+    app.insert(PersonalBests::default())
+        .insert(BestsPath(path.clone()));
+
+    app.step(30);
+    walk_corridor(&mut app);
+    assert!(app.won());
+    assert!(results_text(&mut app).contains("first clear!"));
+    let first = app.resource::<PersonalBests>().0[""];
+    assert_eq!(first.moves, 5);
+
+    app.send(LevelFailed);
+    app.step(1);
+    assert!(results_text(&mut app).is_empty());
+    walk_corridor(&mut app);
+    let second = app.resource::<PersonalBests>().0[""];
+    assert_eq!(second.moves, 5);
+    assert!(second.seconds < first.seconds);
+    let text = results_text(&mut app);
+    assert!(text.contains("new record!"));
+    assert!(!text.contains("first clear!"));
+    assert_eq!(PersonalBests::load(&path).unwrap().0[""], second);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn solved_runs_set_no_record() {
+    let path =
+        std::env::temp_dir().join(format!("ferris_bests_solved_{}.json", std::process::id()));
+    let mut app = playback_app(CORRIDOR);
+    app.with_assets();
+    app.app
+        .add_system(track_run_stats.system().label("track_run_stats"))
+        .add_system(show_results.system().after("track_run_stats"));
+    app.insert(PersonalBests::default())
+        .insert(BestsPath(path.clone()));
+    app.tap(KeyCode::R);
+    play_out(&mut app, 10);
+    assert!(app.won());
+    assert_eq!(app.resource::<RunStats>().solves, 1);
+    assert!(app.resource::<PersonalBests>().0.is_empty());
+    assert!(results_text(&mut app).contains("no record"));
+    assert!(!path.exists());
+}
+
+#[test]
+fn batch_solves_the_bundled_maps() {
+    use ferris_lab::solver::{load_ascii_maps, solve_batch};
+
+    let maps_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/maps");
+    let maps = load_ascii_maps(&maps_dir).unwrap();
+    let names: Vec<&str> = maps.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(
+        names,
+        ["labyrinth_level_0", "labyrinth_level_1", "maze_large"]
+    );
+
+    let eight_way = SolveOptions {
+        eight_way: true,
+        ..Default::default()
+    };
+    for options in [SolveOptions::default(), eight_way].iter() {
+        let results = solve_batch(&maps, &Rules::default(), options);
+        assert_eq!(results.len(), maps.len());
+        for result in results {
+            assert!(result.cost.is_some(), "{} has no solution", result.name);
+        }
+    }
+}
+
+// LDTK project from the assets as the asset loader reads it, without the tileset textures
+fn load_ldtk(name: &str) -> LdtkMap {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("assets")
+        .join(name);
+    let json = std::fs::read_to_string(path).unwrap();
+    LdtkMap {
+        project: serde_json::from_str(&json).unwrap(),
+        tilesets: HashMap::new(),
+    }
+}
+
+#[test]
+fn ldtk_maps_are_shifted_by_their_height() {
+    for name in LEVELS {
+        assert_eq!(ldtk_rows(&load_ldtk(name)), Some(16));
+    }
+    let origin = MapOrigin(Vec2::new(-40.0, 24.0));
+    assert_eq!(
+        origin.top_down_translation(16),
+        Vec2::new(-40.0, 24.0 + 16.0 * TILE_SIZE)
+    );
+    assert_eq!(origin.top_down_translation(3).y, 24.0 + 3.0 * TILE_SIZE);
+}
+
+#[test]
+fn letterbox_keeps_the_ratio_and_the_view_height() {
+    use ferris_lab::camera::letterbox_viewport;
+
+    let ratio = 16.0 / 9.0;
+    let mode = AspectMode::Letterbox(ratio);
+    // too wide: bars left and right, too tall: bars on top and bottom
+    for window in [Vec2::new(1600.0, 600.0), Vec2::new(800.0, 900.0)].iter() {
+        let (offset, size) = letterbox_viewport(*window, mode);
+        assert!((size.x / size.y - ratio).abs() < 1e-4);
+        assert!((offset * 2.0 + size - *window).length() < 1e-3);
+        assert!(size.x <= window.x && size.y <= window.y);
+
+        // the view is as high as the whole window is at the plain scale
+        let scale = framing_scale(0.5, *window, mode);
+        assert!((size.y * scale - window.y * 0.5).abs() < 1e-3);
+    }
+    let window = Vec2::new(1270.0, 720.0);
+    assert_eq!(
+        letterbox_viewport(window, AspectMode::Fill),
+        (Vec2::ZERO, window)
+    );
+    assert_eq!(framing_scale(0.5, window, AspectMode::Fill), 0.5);
+}
+
+#[test]
+fn tile_wobble_stays_within_the_amplitude() {
+    let mut neighbors_differ = false;
+    for step in 0..200 {
+        let t = step as f32 * 0.05;
+        for y in -3..3 {
+            for x in -3..3 {
+                let cell = IVec2::new(x, y);
+                let offset = wobble_offset(cell, t, 0.5);
+                assert!(offset.x.abs() <= 0.5 && offset.y.abs() <= 0.5);
+                // way too much would move tiles into the next cell
+                let offset = wobble_offset(cell, t, 100.0);
+                assert!(offset.x.abs() <= MAX_WOBBLE && offset.y.abs() <= MAX_WOBBLE);
+                neighbors_differ |=
+                    wobble_offset(cell, t, 0.5) != wobble_offset(cell + IVec2::X, t, 0.5);
+            }
+        }
+    }
+    assert!(MAX_WOBBLE < TILE_SIZE * 0.5);
+    assert!(neighbors_differ);
+    assert_eq!(wobble_offset(IVec2::new(4, 2), 1.0, 0.0), Vec2::ZERO);
+}
+
+#[test]
+fn keys_carry_over_into_the_next_level_only() {
+    let ferris = Ferris {
+        pos: UVec2::new(3, 4),
+        keys: [1, 0, 2],
+        collected: [true, true, true],
+    };
+    let carried = carry_over(&ferris, true, false);
+    assert_eq!(carried.keys, [1, 0, 2]);
+    assert_eq!(carried.collected, [true, true, true]);
+    // placed by init_ferris
+    assert_eq!(carried.pos, UVec2::ZERO);
+
+    assert_eq!(carry_over(&ferris, false, false), Ferris::default());
+    // the same map again is a restart
+    assert_eq!(carry_over(&ferris, true, true), Ferris::default());
+}
+
+#[test]
+fn last_pressed_arrow_key_wins() {
+    let mut app = TestApp::from_ascii("#####\n#...#\n#.S.#\n#...E\n#####\n");
+    let start = app.ferris_pos();
+    // pressed within the same frame
+    app.press(KeyCode::Up);
+    app.tap(KeyCode::Right);
+    assert_eq!(app.ferris_pos(), start + UVec2::new(1, 0));
+
+    app.press(KeyCode::Left);
+    app.tap(KeyCode::Down);
+    assert_eq!(
+        app.ferris_pos(),
+        start + UVec2::new(1, 0) - UVec2::new(0, 1)
+    );
+    assert_eq!(*app.get::<Facing>().unwrap(), Facing::Right);
+
+    assert_eq!(
+        resolve_direction([KeyCode::Left, KeyCode::Space, KeyCode::Right].iter()),
+        Some(Direction::Right)
+    );
+    assert_eq!(resolve_direction([KeyCode::Space].iter()), None);
+}
+
+#[test]
+fn one_shot_animations_stop_on_their_last_frame() {
+    let mut app = TestApp::from_ascii(CORRIDOR);
+    app.with_assets();
+    app.app.add_system(animate_character_system.system());
+    app.count::<AnimationFinished>();
+    let sheet = spritesheet::Spritesheet {
+        image: String::new(),
+        ranges: [("wave".to_string(), 2..5), ("dance".to_string(), 5..7)]
+            .iter()
+            .cloned()
+            .collect(),
+        durations: Vec::new(),
+        one_shot: std::iter::once("wave".to_string()).collect(),
+        slices: HashMap::new(),
+    };
+    let handle = app
+        .resource_mut::<Assets<spritesheet::Spritesheet>>()
+        .add(sheet);
+    let ferris = app.ferris;
+    app.app
+        .world
+        .entity_mut(ferris)
+        .insert(handle)
+        .insert(PlayAnimation::new("wave"));
+    // 0.1s per frame by default, plenty of time to go through the range a few times
+    app.step(100);
+    assert_eq!(app.get::<TextureAtlasSprite>().unwrap().index, 4);
+    assert!(app.get::<PlayAnimation>().unwrap().finished);
+    assert_eq!(app.events::<AnimationFinished>(), 1);
+
+    // not one-shot, loops
+    app.app
+        .world
+        .entity_mut(ferris)
+        .insert(PlayAnimation::new("dance"));
+    let mut seen = HashSet::new();
+    for _ in 0..30 {
+        app.step(7);
+        seen.insert(app.get::<TextureAtlasSprite>().unwrap().index);
+    }
+    assert_eq!(seen, [5, 6].iter().copied().collect());
+    assert_eq!(app.events::<AnimationFinished>(), 1);
+}
+
+#[test]
+fn key_inventory_follows_the_color_scheme() {
+    let mut app = TestApp::from_ascii(KEY_AND_DOOR);
+    app.with_assets();
+    app.app
+        .add_startup_system(spawn_key_inventory.system())
+        .add_system(update_key_inventory.system().after("character_input"));
+    let sections = |app: &mut TestApp| -> Vec<(String, Color)> {
+        let mut query = app
+            .app
+            .world
+            .query_filtered::<&Text, With<KeyInventoryText>>();
+        let text = query.iter(&app.app.world).next().unwrap();
+        text.sections
+            .iter()
+            .map(|section| (section.value.clone(), section.style.color))
+            .collect()
+    };
+    app.step(1);
+    assert!(sections(&mut app).is_empty());
+
+    app.tap(KeyCode::Right);
+    app.tap(KeyCode::Right);
+    assert_eq!(
+        sections(&mut app),
+        vec![("● 1 ".to_string(), key_color(0, ColorScheme::Default))]
+    );
+    app.insert(ColorScheme::HighContrast);
+    app.step(1);
+    assert_eq!(
+        sections(&mut app),
+        vec![("● 1 ".to_string(), key_color(0, ColorScheme::HighContrast))]
+    );
+    // a symbol of its own for each color
+    let symbols: Vec<String> = key_inventory_sections(&[1, 2, 1], ColorScheme::HighContrast)
+        .into_iter()
+        .map(|(value, _)| value)
+        .collect();
+    assert_eq!(symbols, ["● 1 ", "▲ 2 ", "■ 1 "]);
+}
+
+#[test]
+fn same_seed_same_maze() {
+    let size = UVec2::new(12, 8);
+    let maze = generate_maze(size, &mut GameRng::from_seed(7).0);
+    assert_eq!(maze, generate_maze(size, &mut GameRng::from_seed(7).0));
+    assert_ne!(maze, generate_maze(size, &mut GameRng::from_seed(8).0));
+    assert_eq!(maze.size(), UVec2::new(25, 17));
+
+    // every cell is reachable, so the exit is as well
+    let start = Ferris::at(maze.find(START_TILE).unwrap());
+    let exits = maze.find_all(END_TILE);
+    assert_eq!(exits, vec![UVec2::new(23, 15)]);
+    let solved = solve_grid(
+        &maze,
+        &Rules::default(),
+        &start,
+        &exits,
+        &SolveOptions::default(),
+    );
+    assert!(solved.is_some());
+
+    assert_eq!(parse_maze_size("20x12"), Some(UVec2::new(20, 12)));
+    assert_eq!(parse_maze_size("20"), None);
+    assert_eq!(parse_maze_size("0x3"), None);
+}
+
+// the spritesheet of ferris from the assets, changed by edit before it is parsed
+fn parse_spritesheet(
+    edit: impl FnOnce(&mut serde_json::Value),
+) -> Result<spritesheet::Spritesheet, spritesheet::SpritesheetError> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/ferris2.0.json");
+    let mut json: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    edit(&mut json);
+    spritesheet::Spritesheet::try_from_bytes(&path, json.to_string().into_bytes())
+}
+
+#[test]
+fn spritesheet_errors() {
+    use spritesheet::SpritesheetError;
+
+    let sheet = parse_spritesheet(|_| ()).unwrap();
+    assert_eq!(sheet.ranges["walk left"], 0..3);
+    assert_eq!(sheet.durations.len(), 10);
+
+    assert!(matches!(
+        parse_spritesheet(|json| json["frames"] = serde_json::json!("none")),
+        Err(SpritesheetError::Parse(_))
+    ));
+    assert!(matches!(
+        parse_spritesheet(|json| json["meta"]["image"] = serde_json::json!("")),
+        Err(SpritesheetError::MissingImage)
+    ));
+    assert!(matches!(
+        parse_spritesheet(|json| json["meta"]["frameTags"] = serde_json::json!([])),
+        Err(SpritesheetError::EmptyFrameTags)
+    ));
+}
+
+#[test]
+fn spritesheet_tags_are_validated() {
+    use spritesheet::SpritesheetError;
+
+    let inverted =
+        parse_spritesheet(|json| json["meta"]["frameTags"][0]["from"] = serde_json::json!(5));
+    assert!(matches!(
+        inverted,
+        Err(SpritesheetError::InvertedTag { from: 5, to: 3, .. })
+    ));
+    let out_of_range =
+        parse_spritesheet(|json| json["meta"]["frameTags"][1]["to"] = serde_json::json!(12));
+    assert_eq!(
+        out_of_range.unwrap_err().to_string(),
+        "tag 'walk right' references frame 12 but only 10 frames exist"
+    );
+    // the last frame is still fine
+    assert!(
+        parse_spritesheet(|json| json["meta"]["frameTags"][1]["to"] = serde_json::json!(9)).is_ok()
+    );
+}
+
+#[test]
+fn ascii_round_trip() {
+    let ascii = "#########\n\
+                 #S.aAbB+#\n\
+                 #_#cC#..E\n\
+                 #########\n";
+    let grid = tilegrid_from_ascii(ascii).unwrap();
+    assert_eq!(tilegrid_to_ascii(&grid, None), ascii);
+    assert_eq!(
+        tilegrid_from_ascii(&tilegrid_to_ascii(&grid, None)).unwrap(),
+        grid
+    );
+
+    // a dump of a running game starts where ferris was
+    let dump = tilegrid_to_ascii(&grid, Some(UVec2::new(3, 1)));
+    assert_eq!(dump.lines().nth(2), Some("#_#@C#..E"));
+    let from_dump = tilegrid_from_ascii(&dump).unwrap();
+    assert_eq!(from_dump.find_all(START_TILE), vec![UVec2::new(3, 1)]);
+    assert_eq!(from_dump.get(UVec2::new(1, 2)), None);
+}
+
+#[test]
+fn door_needing_two_keys() {
+    // the second key is behind ferris
+    let mut app = consuming_app("#########\n#aS.a.AE#\n#########\n");
+    let door = UVec2::new(6, 1);
+    app.resource_mut::<Rules>().door_keys.insert(door, 2);
+    let rules = app.resource::<Rules>().clone();
+    assert_eq!(rules.keys_required(door), 2);
+
+    let start = Ferris::at(app.ferris_pos());
+    let (path, _) = solve_grid(
+        app.grid(),
+        &rules,
+        &start,
+        &[UVec2::new(7, 1)],
+        &SolveOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(path.len(), 8);
+
+    for _ in 0..4 {
+        app.tap(KeyCode::Right);
+    }
+    assert_eq!(app.ferris().keys, [1, 0, 0]);
+    assert_eq!(app.ferris_pos(), UVec2::new(5, 1));
+    for _ in 0..4 {
+        app.tap(KeyCode::Left);
+    }
+    assert_eq!(app.ferris().keys, [2, 0, 0]);
+    for _ in 0..6 {
+        app.tap(KeyCode::Right);
+    }
+    assert!(app.won());
+}
+
+#[test]
+fn solver_takes_the_closer_exit() {
+    let grid = tilegrid_from_ascii("###########\n#E..S.....E\n###########\n").unwrap();
+    let exits = grid.find_all(END_TILE);
+    assert_eq!(exits.len(), 2);
+    let start = Ferris::at(grid.find(START_TILE).unwrap());
+    let (path, cost) = solve_grid(
+        &grid,
+        &Rules::default(),
+        &start,
+        &exits,
+        &SolveOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(path.last().unwrap().pos, UVec2::new(1, 1));
+    assert_eq!(cost, 3 * ORTHOGONAL_COST);
+
+    // walled off, the far one is the closest reachable exit
+    let mut grid = grid;
+    grid.set(UVec2::new(2, 1), Some(WALL_TILE));
+    let (path, _) = solve_grid(
+        &grid,
+        &Rules::default(),
+        &start,
+        &exits,
+        &SolveOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(path.last().unwrap().pos, UVec2::new(10, 1));
+}
+
+#[test]
+fn diagonal_cost_shapes_the_path() {
+    let grid = tilegrid_from_ascii(
+        "#######\n\
+         #....E#\n\
+         #.....#\n\
+         #.....#\n\
+         #S....#\n\
+         #######\n",
+    )
+    .unwrap();
+    let start = Ferris::at(UVec2::new(1, 1));
+    let exits = [UVec2::new(5, 4)];
+    let diagonal_steps = |diagonal_cost: u32| {
+        let options = SolveOptions {
+            eight_way: true,
+            diagonal_cost,
+            ..Default::default()
+        };
+        let (path, _) = solve_grid(&grid, &Rules::default(), &start, &exits, &options).unwrap();
+        path.windows(2)
+            .filter(|step| step[0].pos.x != step[1].pos.x && step[0].pos.y != step[1].pos.y)
+            .count()
+    };
+    // cheap diagonals cut across the room, expensive ones are never worth it
+    assert_eq!(diagonal_steps(11), 3);
+    assert_eq!(diagonal_steps(2 * ORTHOGONAL_COST as u32 + 5), 0);
+}
+
+#[test]
+fn spawn_next_to_a_walled_start() {
+    let grid = tilegrid_from_ascii(
+        "#######\n\
+         ###.###\n\
+         #S.#..E\n\
+         #######\n",
+    )
+    .unwrap();
+    // e.g. a start entity put onto a wall, the closest floor is right above it
+    assert_eq!(
+        nearest_walkable(&grid, UVec2::new(3, 1)),
+        Some(UVec2::new(3, 2))
+    );
+    assert_eq!(
+        nearest_walkable(&grid, UVec2::new(1, 1)),
+        Some(UVec2::new(1, 1))
+    );
+    assert_eq!(nearest_walkable(&grid, UVec2::new(9, 9)), None);
+
+    let mut app = TestApp::from_ascii(CORRIDOR);
+    app.with_assets();
+    app.app
+        .add_system(init_ferris.system())
+        .add_system(check_start_tiles.system());
+    app.count::<LevelValidationFailed>();
+    let placed = app.app.world.spawn().insert(Ferris::default()).id();
+    app.step(1);
+    assert_eq!(
+        app.app.world.get::<Ferris>(placed).unwrap().pos,
+        UVec2::new(1, 1)
+    );
+
+    // no start at all: ferris is not put anywhere, the map is reported instead
+    app.resource_mut::<TileGrid>().set(UVec2::new(1, 1), None);
+    app.send(TileGridReady);
+    let unplaced = app.app.world.spawn().insert(Ferris::default()).id();
+    app.step(1);
+    assert!(app.app.world.get::<EndPos>(unplaced).is_none());
+    assert_eq!(app.events::<LevelValidationFailed>(), 1);
+}
+
+#[test]
+fn state_cycles_stop_playback_and_search() {
+    let mut app = playback_app(CORRIDOR);
+    app.insert(PlaybackLoops(Some(1)));
+    let start = app.ferris();
+    // a recorded solution going back and forth forever, like a zero cost cycle would
+    let steps: VecDeque<Ferris> = (0..20)
+        .map(|i| Ferris::at(UVec2::new(2 - i % 2, 1)))
+        .collect();
+    let solution = Solution {
+        path: solution_path(&start, &steps),
+        steps: steps.clone(),
+        expected: start.clone(),
+        start: start.clone(),
+        start_grid: app.grid().clone(),
+        recorded: steps,
+        plays: 1,
+        visits: HashMap::new(),
+    };
+    let ferris = app.ferris;
+    app.app.world.entity_mut(ferris).insert(solution);
+    play_out(&mut app, 20);
+
+    let solution = app.get::<Solution>().unwrap();
+    assert!(solution.steps.is_empty());
+    assert_eq!(
+        solution.visits.values().max(),
+        Some(&(MAX_STATE_VISITS + 1))
+    );
+    assert_eq!(app.ferris_pos(), UVec2::new(2, 1));
+    assert!(!app.won());
+
+    // the search gives up once it expanded too many states
+    let grid = tilegrid_from_ascii(CORRIDOR).unwrap();
+    let exits = grid.find_all(END_TILE);
+    let options = SolveOptions {
+        max_expanded: 2,
+        ..Default::default()
+    };
+    assert!(solve_grid(&grid, &Rules::default(), &start, &exits, &options).is_none());
+    assert!(solve_grid(
+        &grid,
+        &Rules::default(),
+        &start,
+        &exits,
+        &SolveOptions::default()
+    )
+    .is_some());
+}
+
+#[test]
+fn keyed_maze_has_each_key_before_its_door() {
+    let mut rng = GameRng::from_seed(3);
+    let mut maze = generate_maze(UVec2::new(10, 6), &mut rng.0);
+    let rules = Rules::default();
+    place_locks(&mut maze, &rules, &mut rng.0, NUM_COLORS as usize).unwrap();
+
+    let start = Ferris::at(maze.find(START_TILE).unwrap());
+    let exits = maze.find_all(END_TILE);
+    let (path, _) = solve_grid(&maze, &rules, &start, &exits, &SolveOptions::default()).unwrap();
+    let step_onto = |pos: UVec2| path.iter().position(|state| state.pos == pos).unwrap();
+    for color_id in 0..NUM_COLORS {
+        // one pair per color
+        let keys = maze.find_all(key_tile(color_id));
+        let doors = maze.find_all(door_tile(color_id));
+        assert_eq!((keys.len(), doors.len()), (1, 1));
+        assert!(step_onto(keys[0]) < step_onto(doors[0]));
+    }
+
+    assert_eq!(
+        place_locks(&mut maze, &rules, &mut rng.0, NUM_COLORS as usize + 1),
+        Err(LockError::TooManyPairs(NUM_COLORS as usize + 1))
+    );
+}
+
+#[test]
+fn cell_borders_round_towards_the_origin() {
+    let grid = tilegrid_from_ascii(CORRIDOR).unwrap();
+    let origin = MapOrigin(Vec2::new(-40.0, 24.0));
+    let at = |x: f32, y: f32| translation_to_pos(&origin, &grid, origin.0 + Vec2::new(x, y));
+
+    assert_eq!(at(0.0, 0.0), Some(UVec2::new(0, 0)));
+    // a border belongs to the cell above / right of it
+    assert_eq!(at(TILE_SIZE, 0.0), Some(UVec2::new(1, 0)));
+    assert_eq!(at(TILE_SIZE - 0.01, TILE_SIZE), Some(UVec2::new(0, 1)));
+    assert_eq!(
+        at(7.0 * TILE_SIZE - 0.01, 3.0 * TILE_SIZE - 0.01),
+        Some(UVec2::new(6, 2))
+    );
+
+    // off the map, nothing is clamped onto the border cells
+    assert_eq!(at(-0.01, 0.0), None);
+    assert_eq!(at(0.0, -0.01), None);
+    assert_eq!(at(7.0 * TILE_SIZE, TILE_SIZE), None);
+    assert_eq!(at(TILE_SIZE, 3.0 * TILE_SIZE), None);
+}
+
+#[test]
+fn metrics_record_one_line_per_attempt() {
+    let path = std::env::temp_dir().join(format!("ferris_metrics_{}.jsonl", std::process::id()));
+    let mut app = TestApp::from_ascii(CORRIDOR);
+    app.app.add_system(record_metrics.system());
+    app.insert(MetricsLogger::new(Some(path.clone())))
+        .insert(LevelMeta {
+            name: "corridor".into(),
+            ..Default::default()
+        })
+        .insert(RunStats {
+            moves: 7,
+            started: Some(0.0),
+            solves: 1,
+        });
+
+    app.send(Died);
+    app.send(Died);
+    app.step(1);
+    app.send(GameWon);
+    app.step(1);
+    app.send(LevelFailed);
+    app.step(1);
+
+    let lines: Vec<serde_json::Value> = std::fs::read_to_string(&path)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(lines.len(), 2);
+    let won = &lines[0];
+    assert_eq!(won["level"], "corridor");
+    assert_eq!(won["moves"], 7);
+    assert_eq!(won["solves"], 1);
+    assert_eq!(won["deaths"], 2);
+    assert_eq!(won["won"], true);
+    assert!(won["seconds"].as_f64().unwrap() > 0.0);
+    // deaths start over with the next attempt
+    assert_eq!(lines[1]["deaths"], 0);
+    assert_eq!(lines[1]["won"], false);
+}
+
+#[test]
+fn per_door_key_opens_one_of_two_doors() {
+    // one key at (2, 1), same colored doors at (3, 1) and (4, 1)
+    const TWO_DOORS: &str = "#######\n#SaAAE#\n#######\n";
+    let grid = tilegrid_from_ascii(TWO_DOORS).unwrap();
+    let start = Ferris::at(grid.find(START_TILE).unwrap());
+    let exits = grid.find_all(END_TILE);
+    let per_door = Rules {
+        key_consumption: KeyConsumption::PerDoor,
+        ..Default::default()
+    };
+    let options = SolveOptions::default();
+    assert!(solve_grid(&grid, &Rules::default(), &start, &exits, &options).is_some());
+    assert!(solve_grid(&grid, &per_door, &start, &exits, &options).is_none());
+
+    let mut app = consuming_app(TWO_DOORS);
+    app.resource_mut::<Rules>().key_consumption = KeyConsumption::PerDoor;
+    for _ in 0..4 {
+        app.tap(KeyCode::Right);
+    }
+    // the key was used up by the first door
+    assert_eq!(app.ferris_pos(), UVec2::new(3, 1));
+    assert_eq!(app.ferris().keys, [0, 0, 0]);
+    assert!(!app.won());
+}
+
+#[test]
+fn neighbors_stop_at_the_map_border() {
+    let size = UVec2::new(4, 3);
+    let n = |x, y| Some(UVec2::new(x, y));
+    // up, down, left, right
+    assert_eq!(
+        get_neighboring_pos(UVec2::new(0, 0), size),
+        [n(0, 1), None, None, n(1, 0)]
+    );
+    assert_eq!(
+        get_neighboring_pos(UVec2::new(3, 2), size),
+        [None, n(3, 1), n(2, 2), None]
+    );
+    assert_eq!(
+        get_neighboring_pos(UVec2::new(2, 0), size),
+        [n(2, 1), None, n(1, 0), n(3, 0)]
+    );
+    assert_eq!(
+        get_neighboring_pos(UVec2::new(1, 1), size),
+        [n(1, 2), n(1, 0), n(0, 1), n(2, 1)]
+    );
+    // a single cell map has no neighbors at all
+    assert_eq!(get_neighboring_pos(UVec2::ZERO, UVec2::ONE), [None; 4]);
+}
+
+#[test]
+fn wall_variant_from_the_neighbor_mask() {
+    use ferris_lab::autotile::{E, N, NE, S, SW, W, WALL_AUTOTILE_START};
+    // isolated wall and wall surrounded by walls are the first and last variant
+    assert_eq!(wall_autotile(0), WALL_AUTOTILE_START);
+    assert_eq!(wall_autotile(255), WALL_AUTOTILE_START + 46);
+    assert_eq!(wall_autotile(N), WALL_AUTOTILE_START + 1);
+    assert_eq!(wall_autotile(N | E), WALL_AUTOTILE_START + 3);
+    assert_eq!(wall_autotile(N | NE | E), WALL_AUTOTILE_START + 4);
+    // diagonals without both walls next to them don't change the look
+    assert_eq!(wall_autotile(N | NE), wall_autotile(N));
+    assert_eq!(wall_autotile(S | SW | E), wall_autotile(S | E));
+    assert_ne!(wall_autotile(S | SW | W), wall_autotile(S | W));
+
+    // the map border counts as wall, the start cell next to the corner does not
+    let grid = tilegrid_from_ascii(CORRIDOR).unwrap();
+    assert_eq!(wall_mask(&grid, UVec2::new(0, 0)), 255 & !NE);
+}
+
+#[test]
+fn color_ids_of_key_and_door_tiles_only() {
+    for index in [0, 1, 8, 18, 19, 20, u16::MAX] {
+        assert_eq!(color_id(index), None, "tile {}", index);
+    }
+    for color in 0..NUM_COLORS {
+        assert_eq!(door_color_id(door_tile(color)), Some(color));
+        assert_eq!(key_color_id(key_tile(color)), Some(color));
+        assert_eq!(color_id(door_tile(color)), Some(color));
+        assert_eq!(color_id(key_tile(color)), Some(color));
+        // keys are no doors and the other way around
+        assert_eq!(door_color_id(key_tile(color)), None);
+        assert_eq!(key_color_id(door_tile(color)), None);
+    }
+}
+
+#[test]
+fn playback_picks_up_the_key_on_its_way() {
+    let mut app = playback_app(KEY_AND_DOOR);
+    app.insert(PlaybackLoops(Some(1))).count::<KeyPickedUp>();
+    app.tap(KeyCode::R);
+    // walking, one step per arrival: onto the floor and then onto the key
+    play_out(&mut app, 2);
+
+    assert_eq!(app.ferris_pos(), UVec2::new(3, 1));
+    assert_eq!(app.ferris().keys, [1, 0, 0]);
+    assert_eq!(app.events::<KeyPickedUp>(), 1);
+    // the key is gone like after a manual pickup, the door is still there
+    assert_eq!(app.grid().get(UVec2::new(3, 1)), None);
+    assert_eq!(app.grid().get(UVec2::new(5, 1)), Some(door_tile(0)));
+    assert!(!app.won());
+}
+
+#[test]
+fn ferris_at_is_empty_handed() {
+    let ferris = Ferris::at(UVec2::new(4, 2));
+    assert_eq!(ferris.pos, UVec2::new(4, 2));
+    assert_eq!(ferris.keys, [0; 3]);
+    assert_eq!(ferris.collected, [false; 3]);
+    assert_eq!(ferris.held_key(), None);
+    assert_eq!(Ferris::at(UVec2::ZERO), Ferris::default());
+}
+
+#[test]
+fn loading_screen_goes_once_all_assets_are_done() {
+    let mut app = TestApp::from_ascii(CORRIDOR);
+    app.insert(bevy::tasks::IoTaskPool(bevy::tasks::TaskPool::new()));
+    app.app
+        .add_plugin(bevy::asset::AssetPlugin)
+        .add_plugin(spritesheet::SpritesheetPlugin)
+        .add_system(update_loading_screen.system());
+    let asset_server = app.resource::<AssetServer>().clone();
+    // one asset that loads, one that fails: neither keeps the game waiting
+    let tracked = vec![
+        asset_server.load_untyped("ferris2.0.json"),
+        asset_server.load_untyped("missing.json"),
+    ];
+    app.insert(LoadingState {
+        tracked: tracked.clone(),
+        loaded: false,
+    });
+    let screen = app.app.world.spawn().insert(LoadingScreen).id();
+
+    // loading happens on the io task pool, give it some time
+    for _ in 0..500 {
+        if app.resource::<LoadingState>().loaded {
+            break;
+        }
+        app.step(1);
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    assert!(app.resource::<LoadingState>().loaded);
+    assert_eq!(
+        asset_server.get_load_state(tracked[0].id),
+        LoadState::Loaded
+    );
+    assert_eq!(
+        asset_server.get_load_state(tracked[1].id),
+        LoadState::Failed
+    );
+    assert!(app.app.world.get_entity(screen).is_none());
+
+    // and input is taken from now on
+    app.tap(KeyCode::Right);
+    assert_eq!(app.ferris_pos(), UVec2::new(2, 1));
+}
+
+#[test]
+fn pulse_shows_keys_with_their_doors() {
+    let mut app = TestApp::from_ascii(CORRIDOR);
+    app.app
+        .add_system(pair_pulse_input.system().label("pair_pulse_input"))
+        .add_system(update_tile_colors.system().after("pair_pulse_input"));
+    let tile = |app: &mut TestApp, x, texture_index| {
+        spawn_tile(app, UVec2::new(x, 0), texture_index, LAYER_ID)
+    };
+    let red_key = tile(&mut app, 0, key_tile(0));
+    let red_door = tile(&mut app, 1, door_tile(0));
+    let other_red_door = tile(&mut app, 6, door_tile(0));
+    let blue_key = tile(&mut app, 2, key_tile(2));
+    let blue_door = tile(&mut app, 3, door_tile(2));
+    let wall = tile(&mut app, 4, WALL_TILE);
+    app.step(1);
+    let scheme = *app.resource::<ColorScheme>();
+    let wall_tint = tile_tint(WALL_TILE, scheme);
+    assert_eq!(tile_color(&app, wall), wall_tint);
+
+    app.tap(KeyCode::K);
+    app.step(10);
+    let red = tile_color(&app, red_key);
+    let blue = tile_color(&app, blue_key);
+    assert!(red.a() < 1.0);
+    assert_eq!(tile_color(&app, red_door), red);
+    assert_eq!(tile_color(&app, other_red_door), red);
+    assert_eq!(tile_color(&app, blue_door), blue);
+    let mut red_opaque = red;
+    red_opaque.set_a(1.0);
+    assert_eq!(red_opaque, key_color(0, scheme));
+    // all colors pulse in sync, each in its own color
+    assert_eq!(blue.a(), red.a());
+    assert_ne!(blue, red);
+    assert_eq!(tile_color(&app, wall), wall_tint);
+
+    // back to normal once the pulse is over
+    app.step((PAIR_PULSE_DURATION / FRAME.as_secs_f32()) as usize);
+    assert!(app.resource::<PairPulse>().0.is_none());
+    assert_eq!(tile_color(&app, red_key), tile_tint(key_tile(0), scheme));
+    assert_eq!(tile_color(&app, blue_door), tile_tint(door_tile(2), scheme));
+}
+
+#[test]
+fn door_glows_next_to_ferris_with_its_key() {
+    let mut app = TestApp::from_ascii(CORRIDOR);
+    app.insert(DoorGlow(true));
+    app.app.add_system(update_tile_colors.system());
+    let start = app.ferris_pos();
+    let next = spawn_tile(&mut app, start + UVec2::new(0, 1), door_tile(0), LAYER_ID);
+    let far = spawn_tile(&mut app, start + UVec2::new(2, 1), door_tile(0), LAYER_ID);
+    let other_color = spawn_tile(&mut app, start - UVec2::new(0, 1), door_tile(1), LAYER_ID);
+    let scheme = *app.resource::<ColorScheme>();
+    let tint = tile_tint(door_tile(0), scheme);
+    let glow = Color::rgba(
+        tint.r() * DOOR_GLOW,
+        tint.g() * DOOR_GLOW,
+        tint.b() * DOOR_GLOW,
+        tint.a(),
+    );
+    app.step(1);
+    assert_eq!(tile_color(&app, next), tint);
+
+    app.set_ferris(Ferris {
+        keys: [1, 0, 0],
+        ..Ferris::at(start)
+    });
+    app.step(1);
+    assert_eq!(tile_color(&app, next), glow);
+    assert_eq!(tile_color(&app, far), tint);
+    assert_eq!(
+        tile_color(&app, other_color),
+        tile_tint(door_tile(1), scheme)
+    );
+
+    // gone again with the key
+    app.set_ferris(Ferris::at(start));
+    app.step(1);
+    assert_eq!(tile_color(&app, next), tint);
+}
+
+#[test]
+fn two_starts_are_reported_and_the_lowest_wins() {
+    let mut app = TestApp::from_ascii(
+        "#######\n\
+         #..S..E\n\
+         #S.....\n\
+         #######\n",
+    );
+    app.with_assets();
+    app.app
+        .add_system(init_ferris.system())
+        .add_system(check_start_tiles.system());
+    app.count::<LevelValidationFailed>();
+    app.send(TileGridReady);
+    let ferris = app.app.world.spawn().insert(Ferris::default()).id();
+    app.step(1);
+
+    assert_eq!(app.events::<LevelValidationFailed>(), 1);
+    // lowest row first, then the lowest column
+    assert_eq!(
+        app.app.world.get::<Ferris>(ferris).unwrap().pos,
+        UVec2::new(1, 1)
+    );
+    assert_eq!(app.ferris_pos(), UVec2::new(1, 1));
+}
+
+// frames from solving until ferris walked the corridor
+fn playback_frames(speed_keys: &[KeyCode]) -> usize {
+    let mut app = playback_app(CORRIDOR);
+    app.with_assets();
+    app.insert(PlaybackLoops(Some(1)));
+    app.app
+        .add_system(playback_speed_input.system())
+        .add_system(move_ferris.system().after("solve_input"));
+    for key in speed_keys {
+        app.tap(*key);
+    }
+    app.tap(KeyCode::R);
+    (1..1000)
+        .find(|_| {
+            app.step(1);
+            app.won()
+        })
+        .unwrap()
+}
+
+#[test]
+fn playback_speed_changes_the_cadence() {
+    let normal = playback_frames(&[]);
+    let fast = playback_frames(&[KeyCode::PageUp]);
+    let slow = playback_frames(&[KeyCode::PageDown, KeyCode::PageDown]);
+    // five cells of 16 pixels at step_size pixels per frame, plus a frame or so per cell
+    let walk = 5.0 * 16.0 / Tunables::default().step_size;
+    let near = |frames: usize, expected: f32| (frames as f32 - expected).abs() <= 8.0;
+    assert!(near(normal, walk), "{}", normal);
+    assert!(near(fast, walk / 2.0), "{}", fast);
+    assert!(near(slow, walk / MIN_PLAYBACK_SPEED), "{}", slow);
+
+    // bounded both ways
+    let slowest = playback_frames(&[KeyCode::PageDown; 4]);
+    assert_eq!(slowest, playback_frames(&[KeyCode::PageDown; 3]));
+}
+
+// sprite indices seen while walking one cell to the right
+fn walk_cycle_frames(app: &mut TestApp) -> Vec<u32> {
+    app.tap(KeyCode::Right);
+    (0..40)
+        .map(|_| {
+            app.step(1);
+            app.get::<TextureAtlasSprite>().unwrap().index
+        })
+        .collect()
+}
+
+#[test]
+fn per_step_sync_advances_one_frame_per_move() {
+    let mut app = TestApp::from_ascii(CORRIDOR);
+    app.with_assets();
+    app.insert(AnimationSync::PerStep);
+    app.app
+        .add_system(
+            move_ferris
+                .system()
+                .label("move_ferris")
+                .after("character_input"),
+        )
+        .add_system(animate_character_system.system().after("move_ferris"));
+    let handle = app
+        .resource_mut::<Assets<spritesheet::Spritesheet>>()
+        .add(parse_spritesheet(|_| ()).unwrap());
+    let ferris = app.ferris;
+    app.app.world.entity_mut(ferris).insert(handle);
+    app.step(1);
+    // idle frame of the walk cycle to the right
+    assert_eq!(app.get::<TextureAtlasSprite>().unwrap().index, 4);
+
+    let frames = walk_cycle_frames(&mut app);
+    assert_eq!(*frames.last().unwrap(), 5);
+    assert!(frames.iter().all(|index| *index == 4 || *index == 5));
+    assert_eq!(walk_cycle_frames(&mut app).last(), Some(&6));
+
+    // timed, the cycle goes on during the move
+    app.insert(AnimationSync::Timed);
+    let frames = walk_cycle_frames(&mut app);
+    let changes = frames.windows(2).filter(|w| w[0] != w[1]).count();
+    assert!(changes > 1, "{:?}", frames);
+}
+
+fn focus(app: &mut TestApp, focused: bool) {
+    app.send(bevy::window::WindowFocused {
+        id: bevy::window::WindowId::primary(),
+        focused,
+    });
+    app.step(1);
+}
+
+#[test]
+fn focus_loss_pauses_until_focus_is_back() {
+    let mut app = consuming_app(CORRIDOR);
+    app.app.add_system(pause_on_focus_loss.system());
+    let start = app.ferris_pos();
+
+    focus(&mut app, false);
+    assert!(app.resource::<StepMode>().active);
+    app.tap(KeyCode::Right);
+    assert_eq!(app.ferris_pos(), start);
+    focus(&mut app, true);
+    assert!(!app.resource::<StepMode>().active);
+    app.tap(KeyCode::Right);
+    assert_eq!(app.ferris_pos(), start + UVec2::new(1, 0));
+
+    // a manual pause stays on when the focus comes back
+    app.tap(KeyCode::Comma);
+    focus(&mut app, false);
+    focus(&mut app, true);
+    assert!(app.resource::<StepMode>().active);
+    app.tap(KeyCode::Comma);
+
+    set(&mut app, "focuspause", "off");
+    assert!(!app.resource::<FocusPause>().enabled);
+    focus(&mut app, false);
+    assert!(!app.resource::<StepMode>().active);
+}
+
+const WINDOW_SIZE: Vec2 = Vec2::new(800.0, 600.0);
+
+// a primary window and a camera looking at the world origin, for systems using the cursor
+fn add_window(app: &mut TestApp) {
+    let window = Window::new(
+        bevy::window::WindowId::primary(),
+        &WindowDescriptor::default(),
+        WINDOW_SIZE.x as u32,
+        WINDOW_SIZE.y as u32,
+        1.0,
+        None,
+    );
+    app.resource_mut::<Windows>().add(window);
+    app.app
+        .world
+        .spawn()
+        .insert(Transform::default())
+        .insert(ChaseCamera::default());
+}
+
+// puts the cursor over a world position (the camera is not moved or zoomed)
+fn hover(app: &mut TestApp, world: Vec2) {
+    let cursor = world + WINDOW_SIZE * 0.5;
+    app.resource_mut::<Windows>()
+        .get_primary_mut()
+        .unwrap()
+        .update_cursor_position_from_backend(Some(cursor));
+    app.step(1);
+}
+
+fn preview_cells(app: &mut TestApp) -> HashSet<UVec2> {
+    let origin = MapOrigin::default();
+    app.app
+        .world
+        .query_filtered::<&Transform, With<MovePreview>>()
+        .iter(&app.app.world)
+        .map(|transform| translation_to_pos(&origin, app.grid(), transform.translation.truncate()))
+        .map(Option::unwrap)
+        .collect()
+}
+
+#[test]
+fn move_preview_follows_the_snapped_cursor() {
+    let mut app = TestApp::from_ascii(CORRIDOR);
+    app.with_assets();
+    add_window(&mut app);
+    app.insert(ClickToMove(true)).insert(EditMode(false));
+    app.app.add_system(click_to_move.system());
+
+    // anywhere inside the cell, the preview covers the whole cell and the path to it
+    hover(&mut app, Vec2::new(3.2, 1.9) * TILE_SIZE);
+    let to_third: HashSet<UVec2> = (1..=3).map(|x| UVec2::new(x, 1)).collect();
+    assert_eq!(preview_cells(&mut app), to_third);
+    hover(&mut app, Vec2::new(3.9, 1.1) * TILE_SIZE);
+    assert_eq!(preview_cells(&mut app), to_third);
+    // on the border it is the next cell
+    hover(&mut app, Vec2::new(4.0, 1.5) * TILE_SIZE);
+    assert!(preview_cells(&mut app).contains(&UVec2::new(4, 1)));
+
+    // off the map there is nothing to preview and clicks do nothing
+    hover(&mut app, Vec2::new(-0.5, 1.5) * TILE_SIZE);
+    assert!(preview_cells(&mut app).is_empty());
+    app.click(MouseButton::Left);
+    app.step(1);
+    assert!(app.get::<Solution>().is_none());
+
+    hover(&mut app, Vec2::new(3.5, 1.5) * TILE_SIZE);
+    app.click(MouseButton::Left);
+    app.step(1);
+    let solution = app.get::<Solution>().unwrap();
+    assert_eq!(solution.steps.back().unwrap().pos, UVec2::new(3, 1));
+}
+
+#[test]
+fn ferris_is_gone_before_the_level_is_left() {
+    let mut app = TestApp::from_ascii(CORRIDOR);
+    app.app
+        .add_system(start_exit_animation.system().label("start_exit_animation"))
+        .add_system(animate_exit.system().after("start_exit_animation"));
+    app.count::<LevelExited>();
+    let ferris = app.ferris;
+    app.app.world.entity_mut(ferris).insert(Celebrating);
+
+    let mut last_scale = 1.0;
+    for _ in 0..100 {
+        app.step(1);
+        let scale = app.transform().scale.x;
+        assert!(scale <= last_scale);
+        last_scale = scale;
+        if app.events::<LevelExited>() > 0 {
+            break;
+        }
+        assert!(scale > 0.0);
+    }
+    assert_eq!(app.events::<LevelExited>(), 1);
+    assert!(last_scale.abs() < 1e-3, "{}", last_scale);
+
+    // stays gone, and is left only once
+    app.step(10);
+    assert_eq!(app.transform().scale.x, 0.0);
+    assert_eq!(app.events::<LevelExited>(), 1);
+}
+
+#[test]
+fn confirmed_quit_saves_and_exits() {
+    let path = std::env::temp_dir().join(format!("ferris_bests_quit_{}.json", std::process::id()));
+    let mut app = TestApp::from_ascii(CORRIDOR);
+    app.with_assets();
+    app.app
+        .add_event::<bevy::app::AppExit>()
+        .add_system_to_stage(CoreStage::PreUpdate, quit_dialog.system());
+    app.count::<bevy::app::AppExit>();
+    let mut bests = PersonalBests::default();
+    bests.0.insert(
+        "corridor".to_string(),
+        Best {
+            moves: 5,
+            seconds: 2.5,
+        },
+    );
+    app.insert(bests.clone()).insert(BestsPath(path.clone()));
+    let dialog_texts = |app: &mut TestApp| {
+        app.app
+            .world
+            .query_filtered::<Entity, With<QuitDialogText>>()
+            .iter(&app.app.world)
+            .count()
+    };
+
+    // asked first, and N keeps playing
+    app.tap(KeyCode::Escape);
+    assert!(app.resource::<QuitDialog>().open);
+    assert_eq!(dialog_texts(&mut app), 1);
+    // the game gets no keys meanwhile
+    let start = app.ferris_pos();
+    app.tap(KeyCode::Right);
+    assert_eq!(app.ferris_pos(), start);
+    app.tap(KeyCode::N);
+    assert!(!app.resource::<QuitDialog>().open);
+    assert_eq!(dialog_texts(&mut app), 0);
+    assert_eq!(app.events::<bevy::app::AppExit>(), 0);
+    app.tap(KeyCode::Right);
+    assert_eq!(app.ferris_pos(), start + UVec2::new(1, 0));
+
+    app.tap(KeyCode::Escape);
+    app.tap(KeyCode::Y);
+    assert_eq!(app.events::<bevy::app::AppExit>(), 1);
+    assert_eq!(PersonalBests::load(&path).unwrap(), bests);
+    std::fs::remove_file(&path).unwrap();
+}
+
+// colors of the live dust / burst particles
+fn particle_colors(app: &mut TestApp) -> Vec<Color> {
+    let handles: Vec<Handle<ColorMaterial>> = app
+        .app
+        .world
+        .query_filtered::<&Handle<ColorMaterial>, With<DustParticle>>()
+        .iter(&app.app.world)
+        .cloned()
+        .collect();
+    let materials = app.resource::<Assets<ColorMaterial>>();
+    handles
+        .iter()
+        .map(|handle| materials.get(handle).unwrap().color)
+        .collect()
+}
+
+#[test]
+fn key_pickup_bursts_in_the_key_color() {
+    let mut app = TestApp::from_ascii(CORRIDOR);
+    app.with_assets();
+    app.app.add_system(spawn_bursts.system());
+    app.send(KeyPickedUp {
+        pos: UVec2::new(3, 1),
+        color_id: 1,
+    });
+    app.step(1);
+
+    let colors = particle_colors(&mut app);
+    assert_eq!(colors.len(), Tunables::default().burst_size);
+    let mut expected = key_color(1, *app.resource::<ColorScheme>());
+    expected.set_a(0.8);
+    assert!(colors.iter().all(|color| *color == expected));
+    let center = pos_to_translation(
+        &MapOrigin::default(),
+        &UVec2::new(3, 1),
+        zorder::Layer::Breadcrumbs,
+    );
+    let translations: Vec<Vec3> = app
+        .app
+        .world
+        .query_filtered::<&Transform, With<DustParticle>>()
+        .iter(&app.app.world)
+        .map(|transform| transform.translation)
+        .collect();
+    assert!(translations
+        .iter()
+        .all(|translation| *translation == center));
+
+    // a lot at once is capped
+    for _ in 0..20 {
+        app.send(DoorOpened {
+            pos: UVec2::new(5, 1),
+            color_id: 0,
+        });
+    }
+    app.step(1);
+    assert_eq!(particle_colors(&mut app).len(), MAX_BURST_PARTICLES);
+}
+
+#[test]
+fn weighted_heuristic_expands_fewer_states() {
+    // a long wall between start and exit, plain A* looks at a lot of the field before
+    // going around it
+    let mut grid = TileGrid::new(UVec2::new(40, 40));
+    for y in 2..39 {
+        grid.set(UVec2::new(20, y), Some(WALL_TILE));
+    }
+    grid.set(UVec2::new(0, 20), Some(START_TILE));
+    grid.set(UVec2::new(39, 20), Some(END_TILE));
+    let start = Ferris::at(UVec2::new(0, 20));
+    let exits = grid.find_all(END_TILE);
+    let solve = |heuristic_weight| {
+        let options = SolveOptions {
+            heuristic_weight,
+            ..Default::default()
+        };
+        let (solved, expanded) =
+            solve_grid_expanded(&grid, &Rules::default(), &start, &exits, &options);
+        (solved.unwrap().1, expanded)
+    };
+    let (cost, expanded) = solve(1.0);
+    let (weighted_cost, weighted_expanded) = solve(3.0);
+    assert_eq!(cost, 77 * ORTHOGONAL_COST);
+    // no longer guaranteed to be the shortest path, but at most 3 times as long
+    assert!(weighted_cost >= cost && weighted_cost <= 3 * cost);
+    assert!(
+        weighted_expanded < expanded,
+        "{} vs {}",
+        weighted_expanded,
+        expanded
+    );
+}
+
+// custom field of an LDTK level or entity
+fn ldtk_field(name: &str, value: serde_json::Value) -> serde_json::Value {
+    let field_type = match &value {
+        serde_json::Value::String(_) => "String",
+        serde_json::Value::Array(_) => "Array<Int>",
+        value if value.is_f64() => "Float",
+        _ => "Int",
+    };
+    serde_json::json!({
+        "__identifier": name,
+        "__type": field_type,
+        "__value": value,
+        "defUid": 1,
+        "realEditorValues": [],
+    })
+}
+
+// an LDTK entity with a `Type` field (and more fields) at an LDTK grid position
+fn ldtk_entity(grid: [i64; 2], fields: &[(&str, serde_json::Value)]) -> serde_json::Value {
+    let field_instances: Vec<serde_json::Value> = fields
+        .iter()
+        .map(|(name, value)| ldtk_field(name, value.clone()))
+        .collect();
+    serde_json::json!({
+        "__identifier": "Marker",
+        "__grid": grid,
+        "__pivot": [0.0, 0.0],
+        "__tile": null,
+        "defUid": 1,
+        "fieldInstances": field_instances,
+        "height": 16,
+        "px": [grid[0] * 16, grid[1] * 16],
+        "width": 16,
+    })
+}
+
+#[test]
+fn ldtk_entities_place_start_exit_and_doors() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/labyrinth.ldtk");
+    let mut json: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+    let layers = json["levels"][0]["layerInstances"].as_array_mut().unwrap();
+    // same size as the other layers of the level
+    let mut entities = layers[0].clone();
+    entities["__type"] = "Entities".into();
+    entities["__identifier"] = "Entities".into();
+    entities["intGridCsv"] = serde_json::json!([]);
+    entities["entityInstances"] = serde_json::json!([
+        ldtk_entity([1, 14], &[("Type", "start".into())]),
+        ldtk_entity([14, 1], &[("Type", "end".into())]),
+        ldtk_entity([5, 5], &[("Type", "red_door".into()), ("Keys", 2.into())]),
+        ldtk_entity([7, 7], &[("Type", "lava".into())]),
+    ]);
+    layers.push(entities);
+    let ldtk_map = LdtkMap {
+        project: serde_json::from_value(json).unwrap(),
+        tilesets: HashMap::new(),
+    };
+
+    // bottom-up like the tile positions, the unknown type is left out
+    let placed = ldtk_entity_tiles(&ldtk_map);
+    assert_eq!(
+        placed,
+        vec![
+            (UVec2::new(1, 1), START_TILE),
+            (UVec2::new(14, 14), END_TILE),
+            (UVec2::new(5, 10), door_tile(0)),
+        ]
+    );
+    assert_eq!(
+        ldtk_door_keys(&ldtk_map),
+        std::iter::once((UVec2::new(5, 10), 2)).collect()
+    );
+
+    // written over the collision grid like process_loaded_tile_maps does, ferris starts there
+    let mut grid = ldtk_intgrid_collision(&ldtk_map).unwrap();
+    for (pos, texture_index) in placed {
+        grid.set(pos, Some(texture_index));
+    }
+    assert!(grid.find_all(START_TILE).contains(&UVec2::new(1, 1)));
+    assert!(grid.find_all(END_TILE).contains(&UVec2::new(14, 14)));
+}
+
+#[test]
+fn decoration_over_intgrid_floor_is_passable() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/labyrinth.ldtk");
+    let mut json: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+    // a wall tile drawn onto the floor at ldtk (1, 1) of the visual layer
+    let visual = &mut json["levels"][0]["layerInstances"][0];
+    assert_eq!(visual["__type"], "Tiles");
+    visual["gridTiles"].as_array_mut().unwrap().push(
+        serde_json::json!({"px": [16, 16], "src": [0, 0], "f": 0, "t": WALL_TILE, "d": [17]}),
+    );
+    let ldtk_map = LdtkMap {
+        project: serde_json::from_value(json).unwrap(),
+        tilesets: HashMap::new(),
+    };
+
+    let grid = ldtk_intgrid_collision(&ldtk_map).unwrap();
+    assert_eq!(grid.size(), UVec2::new(16, 16));
+    let decorated = UVec2::new(1, 14);
+    assert_eq!(grid.get(decorated), None);
+    assert!(is_walkable(grid.get(decorated)));
+    // walls of the IntGrid are still walls
+    assert_eq!(grid.get(UVec2::new(0, 15)), Some(WALL_TILE));
+
+    // ferris walks over it, coming from the cell to its right
+    let mut rules = Rules::default();
+    rules.exit_condition = ExitCondition::Always;
+    let from = Ferris::at(UVec2::new(2, 14));
+    let (path, _) = solve_grid(
+        &grid,
+        &rules,
+        &from,
+        &[UVec2::new(1, 13)],
+        &SolveOptions::default(),
+    )
+    .unwrap();
+    assert!(path.iter().any(|state| state.pos == decorated));
+}
+
+#[test]
+fn level_meta_from_the_ldtk_level_fields() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/labyrinth.ldtk");
+    let mut json: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+    let identifier = json["levels"][0]["identifier"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let uid = json["levels"][0]["uid"].as_u64().unwrap() as u32;
+    let load = |json: &serde_json::Value| {
+        ldtk_level_meta(&LdtkMap {
+            project: serde_json::from_value(json.clone()).unwrap(),
+            tilesets: HashMap::new(),
+        })
+    };
+
+    // nothing authored: named after the level
+    json["levels"][0]["fieldInstances"] = serde_json::json!([]);
+    assert_eq!(
+        load(&json),
+        LevelMeta {
+            uid,
+            name: identifier,
+            ..Default::default()
+        }
+    );
+
+    json["levels"][0]["fieldInstances"] = serde_json::json!([
+        ldtk_field("name", "The Long Way".into()),
+        ldtk_field("author", "sim".into()),
+        ldtk_field("par", 42.into()),
+        ldtk_field("zoom", 1.5.into()),
+        ldtk_field("key_order", serde_json::json!([2, 0])),
+    ]);
+    assert_eq!(
+        load(&json),
+        LevelMeta {
+            uid,
+            name: "The Long Way".to_string(),
+            author: Some("sim".to_string()),
+            par: Some(42),
+            zoom: Some(1.5),
+            key_order: vec![2, 0],
+        }
+    );
+}
+
+// key at (2, 1), checkpoint at (3, 1) and the door of the key at (4, 1)
+const KEY_CHECKPOINT_DOOR: &str = "#########\n\
+                                   #Sa+A..E#\n\
+                                   #########\n";
+
+fn rewind(app: &mut TestApp) {
+    app.app.add_system(rewind_input.system());
+    app.tap(KeyCode::Back);
+}
+
+#[test]
+fn rewind_to_the_checkpoint_walked_over() {
+    let mut app = consuming_app(KEY_CHECKPOINT_DOOR);
+    for _ in 0..4 {
+        app.tap(KeyCode::Right);
+    }
+    assert_eq!(app.ferris_pos(), UVec2::new(5, 1));
+    assert_eq!(app.grid().get(UVec2::new(4, 1)), None);
+
+    // back onto the checkpoint with the key, the door is closed again
+    rewind(&mut app);
+    assert_eq!(
+        app.ferris(),
+        Ferris {
+            keys: [1, 0, 0],
+            collected: [true, false, false],
+            ..Ferris::at(UVec2::new(3, 1))
+        }
+    );
+    assert_eq!(app.grid().get(UVec2::new(2, 1)), None);
+    assert_eq!(app.grid().get(UVec2::new(4, 1)), Some(door_tile(0)));
+}
+
+#[test]
+fn playback_records_checkpoints_too() {
+    let mut app = playback_app(KEY_CHECKPOINT_DOOR);
+    app.insert(PlaybackLoops(Some(1)));
+    app.tap(KeyCode::R);
+    play_out(&mut app, 4);
+    assert_eq!(app.ferris_pos(), UVec2::new(5, 1));
+    let checkpoint = app.resource::<LastCheckpoint>().0.as_ref().unwrap();
+    assert_eq!(checkpoint.ferris.pos, UVec2::new(3, 1));
+    // taken with the key used up and the door still closed
+    assert_eq!(checkpoint.grid.get(UVec2::new(2, 1)), None);
+    assert_eq!(checkpoint.grid.get(UVec2::new(4, 1)), Some(door_tile(0)));
+
+    rewind(&mut app);
+    assert_eq!(app.ferris_pos(), UVec2::new(3, 1));
+    assert!(app.get::<Solution>().is_none());
+}
+
+#[test]
+fn exit_arrow_points_to_the_offscreen_exit() {
+    let mut app = TestApp::from_ascii(CORRIDOR);
+    add_window(&mut app);
+    app.app.add_system(update_exit_arrow.system());
+    let arrow = app
+        .app
+        .world
+        .spawn()
+        .insert(Transform::default())
+        .insert(Visible {
+            is_visible: false,
+            is_transparent: true,
+        })
+        .insert(ExitArrow)
+        .id();
+    let arrow_state = |app: &TestApp| {
+        let transform = *app.app.world.get::<Transform>(arrow).unwrap();
+        let visible = app.app.world.get::<Visible>(arrow).unwrap().is_visible;
+        (transform, visible)
+    };
+    let half_extent = WINDOW_SIZE * 0.5 - Vec2::splat(TILE_SIZE);
+    let origin = MapOrigin::default();
+
+    // the corridor's own exit is in view
+    app.step(1);
+    assert!(!arrow_state(&app).1);
+
+    for exit in [UVec2::new(100, 1), UVec2::new(3, 80), UVec2::new(60, 60)] {
+        app.app.world.get_mut::<EndPos>(app.ferris).unwrap().0 = vec![exit];
+        app.step(1);
+        let (transform, visible) = arrow_state(&app);
+        assert!(visible);
+        // the arrow's x axis is turned towards the exit, seen from the view center
+        let dir = pos_to_translation(&origin, &exit, zorder::Layer::Overlay)
+            .truncate()
+            .normalize();
+        let pointing = (transform.rotation * Vec3::X).truncate();
+        assert!((pointing - dir).length() < 1e-4, "{:?} {:?}", pointing, dir);
+        // on the border of the view, not beyond
+        let pos = transform.translation.truncate();
+        assert!(pos.x <= half_extent.x + 1e-3 && pos.y <= half_extent.y + 1e-3);
+        assert!(
+            (pos.x - half_extent.x).abs() < 1e-3 || (pos.y - half_extent.y).abs() < 1e-3,
+            "{:?}",
+            pos
+        );
+    }
+}
+
+#[test]
+fn camera_leads_in_the_walking_direction() {
+    let mut app = TestApp::from_ascii(CORRIDOR);
+    app.app.init_resource::<AspectMode>();
+    let ferris = app.ferris;
+    app.app.world.entity_mut(ferris).insert(ChaseCameraTarget);
+    let camera = app
+        .app
+        .world
+        .spawn()
+        .insert(Transform::default())
+        .insert(bevy::render::camera::Camera::default())
+        .insert(ChaseCamera::default())
+        .id();
+    app.app
+        .add_system(
+            move_ferris
+                .system()
+                .label("move_ferris")
+                .after("character_input"),
+        )
+        .add_system(update_camera.system().after("move_ferris"));
+    let lead = |app: &TestApp| app.app.world.get::<ChaseCamera>(camera).unwrap().lead;
+
+    app.step(1);
+    assert_eq!(lead(&app), Vec2::ZERO);
+    app.tap(KeyCode::Right);
+    app.step(20);
+    assert!(lead(&app).x > 0.0);
+    assert_eq!(lead(&app).y, 0.0);
+    assert!(lead(&app).x <= Tunables::default().camera_lead);
+
+    // eases back to the center once ferris stands still
+    app.step(200);
+    assert!(lead(&app).length() < 0.5, "{:?}", lead(&app));
+}
+
+#[test]
+fn optimal_hint_follows_ferris_throttled() {
+    let mut app = TestApp::from_ascii(CORRIDOR);
+    app.app
+        .init_resource::<OptimalHint>()
+        .add_system(update_optimal_hint.system().after("character_input"));
+    let hint = |app: &TestApp| app.resource::<OptimalHint>().0;
+    // the 0.25s throttle runs out after 16 frames
+    let throttle_frames = 20;
+
+    app.step(throttle_frames);
+    assert_eq!(hint(&app), Some(5));
+
+    // not solved again right after the move
+    app.tap(KeyCode::Right);
+    assert_eq!(hint(&app), Some(5));
+    app.step(throttle_frames);
+    assert_eq!(hint(&app), Some(4));
+    assert!(app.get::<Solution>().is_none());
+}
+
+// frames a manual move one cell to the right takes on screen
+fn move_frames(time_scale_keys: &[KeyCode]) -> usize {
+    let mut app = TestApp::from_ascii(CORRIDOR);
+    app.app
+        .add_system(time_scale_input.system())
+        .add_system(move_ferris.system().after("character_input"));
+    for key in time_scale_keys {
+        app.tap(*key);
+    }
+    app.tap(KeyCode::Right);
+    let target = pos_to_translation(
+        &MapOrigin::default(),
+        &app.ferris_pos(),
+        zorder::Layer::Dynamic,
+    );
+    (1..1000)
+        .find(|_| {
+            app.step(1);
+            app.transform().translation == target
+        })
+        .unwrap()
+}
+
+#[test]
+fn time_scale_slows_down_and_speeds_up_moves() {
+    let normal = move_frames(&[]);
+    let walk = 16.0 / Tunables::default().step_size;
+    let near = |frames: usize, expected: f32| (frames as f32 - expected).abs() <= 2.0;
+    assert!(near(normal, walk), "{}", normal);
+    assert!(near(move_frames(&[KeyCode::LBracket]), walk * 4.0));
+    assert!(near(move_frames(&[KeyCode::RBracket]), walk / 4.0));
+    // the same key again goes back to normal speed
+    assert_eq!(move_frames(&[KeyCode::LBracket, KeyCode::LBracket]), normal);
+}
+
+#[test]
+fn unsolvable_level_shows_one_no_path_toast() {
+    let mut app = TestApp::from_ascii("#####\n#S#E#\n#####\n");
+    app.with_assets().count::<NoPath>();
+    app.app
+        .add_system(show_no_path.system().after("solve_input"));
+    let toasts = |app: &mut TestApp| {
+        app.app
+            .world
+            .query_filtered::<Entity, With<ferris_lab::toast::Toast>>()
+            .iter(&app.app.world)
+            .count()
+    };
+
+    app.tap(KeyCode::R);
+    assert_eq!(app.events::<NoPath>(), 1);
+    assert!(app.get::<Solution>().is_none());
+    assert_eq!(toasts(&mut app), 1);
+
+    // solving again replaces the toast instead of stacking another one
+    app.tap(KeyCode::R);
+    app.step(1);
+    assert_eq!(app.events::<NoPath>(), 2);
+    assert_eq!(toasts(&mut app), 1);
+}
+
+#[test]
+fn unbalanced_keys_and_doors_are_warned_about() {
+    let mut app = TestApp::from_ascii(
+        "########\n\
+         #S.a.BE#\n\
+         #..c.C.#\n\
+         ########\n",
+    );
+    app.app
+        .add_event::<MapEdited>()
+        .add_system(check_key_door_balance.system());
+    let warnings = |app: &TestApp| {
+        let events = app.resource::<Events<LevelBalanceWarning>>();
+        let mut warnings: Vec<(u8, u32, u32)> = events
+            .get_reader()
+            .iter(events)
+            .map(|warning| (warning.color_id, warning.keys, warning.doors))
+            .collect();
+        warnings.sort_unstable();
+        warnings
+    };
+
+    // only checked once the grid is ready or edited
+    app.step(1);
+    assert!(warnings(&app).is_empty());
+
+    app.send(TileGridReady);
+    app.step(1);
+    // the blue pair is fine
+    assert_eq!(warnings(&app), vec![(0, 1, 0), (1, 0, 1)]);
+}
+
+#[test]
+fn torch_light_dims_tiles_by_distance() {
+    let mut app = TestApp::from_ascii(CORRIDOR);
+    app.app
+        .add_system(light_radius_input.system().label("light_input"))
+        .add_system(
+            update_tile_colors
+                .system()
+                .after("light_input")
+                .after("character_input"),
+        );
+    let start = app.ferris_pos();
+    let near = spawn_tile(&mut app, start + UVec2::new(0, 1), WALL_TILE, LAYER_ID);
+    let far = spawn_tile(&mut app, start + UVec2::new(5, 1), WALL_TILE, LAYER_ID);
+    let tint = tile_tint(WALL_TILE, *app.resource::<ColorScheme>());
+    let dimmed = |brightness: f32| {
+        Color::rgba(
+            tint.r() * brightness,
+            tint.g() * brightness,
+            tint.b() * brightness,
+            tint.a(),
+        )
+    };
+    app.step(1);
+    assert_eq!(tile_color(&app, far), tint);
+
+    app.tap(KeyCode::L);
+    let radius = app.resource::<LightRadius>().0.unwrap();
+    assert_eq!(tile_color(&app, near), dimmed(1.0 - 1.0 / radius));
+    assert_eq!(tile_color(&app, far), dimmed(0.0));
+
+    // the light moves along with ferris
+    app.tap(KeyCode::Right);
+    assert_eq!(tile_color(&app, near), dimmed(1.0 - 2.0 / radius));
+    assert_eq!(tile_color(&app, far), dimmed(1.0 - 5.0 / radius));
+
+    app.tap(KeyCode::L);
+    assert_eq!(tile_color(&app, near), tint);
+}
+
+#[test]
+fn breadcrumbs_fade_out_over_their_lifetime() {
+    let mut app = TestApp::from_ascii(CORRIDOR);
+    app.with_assets()
+        .insert(BreadcrumbLifetime(0.5))
+        .insert(PathRender::Crumbs);
+    app.app
+        .add_system(
+            show_solution
+                .system()
+                .label("show_solution")
+                .after("solve_input"),
+        )
+        .add_system(fade_breadcrumbs.system().after("show_solution"));
+    let alphas = |app: &mut TestApp| {
+        let crumbs: Vec<Handle<ColorMaterial>> = app
+            .app
+            .world
+            .query_filtered::<&Handle<ColorMaterial>, With<Breadcrumb>>()
+            .iter(&app.app.world)
+            .cloned()
+            .collect();
+        let materials = app.resource::<Assets<ColorMaterial>>();
+        crumbs
+            .iter()
+            .map(|material| materials.get(material).unwrap().color.a())
+            .collect::<Vec<f32>>()
+    };
+
+    // one crumb per step to the exit, spawned once the solution is there
+    app.tap(KeyCode::R);
+    app.step(1);
+    assert_eq!(alphas(&mut app).len(), 5);
+
+    app.step(15);
+    let half = alphas(&mut app);
+    assert_eq!(half.len(), 5);
+    assert!(
+        half.iter().all(|alpha| (alpha - 0.5).abs() < 0.1),
+        "{:?}",
+        half
+    );
+
+    app.step(20);
+    assert!(alphas(&mut app).is_empty());
+}
+
+#[test]
+fn blocked_move_still_turns_ferris() {
+    let mut app = TestApp::from_ascii(CORRIDOR);
+    let start = app.ferris_pos();
+    app.tap(KeyCode::Left);
+    assert_eq!(app.ferris_pos(), start);
+    assert_eq!(*app.get::<Facing>().unwrap(), Facing::Left);
+
+    // vertical moves keep the facing, blocked or not
+    app.tap(KeyCode::Up);
+    assert_eq!(app.ferris_pos(), start);
+    assert_eq!(*app.get::<Facing>().unwrap(), Facing::Left);
+
+    app.tap(KeyCode::Right);
+    assert_eq!(app.ferris_pos(), start + UVec2::new(1, 0));
+    assert_eq!(*app.get::<Facing>().unwrap(), Facing::Right);
+}
+
+#[test]
+fn spritesheet_slices_are_named_regions() {
+    let sheet = parse_spritesheet(|json| {
+        json["meta"]["slices"] = serde_json::json!([
+            {
+                "name": "shadow",
+                "color": "#0000ffff",
+                "keys": [
+                    { "frame": 0, "bounds": { "x": 4, "y": 12, "w": 8, "h": 3 } },
+                    { "frame": 2, "bounds": { "x": 0, "y": 0, "w": 1, "h": 1 } }
+                ]
+            },
+            {
+                "name": "panel",
+                "color": "#0000ffff",
+                "keys": [{
+                    "frame": 0,
+                    "bounds": { "x": 16, "y": 0, "w": 16, "h": 16 },
+                    "center": { "x": 4, "y": 4, "w": 8, "h": 8 }
+                }]
+            },
+            { "name": "unused", "color": "#0000ffff", "keys": [] }
+        ])
+    })
+    .unwrap();
+    // the first key wins, slices without keys are left out
+    assert_eq!(sheet.slices.len(), 2);
+    let shadow = sheet.slices["shadow"];
+    assert_eq!(shadow.min, Vec2::new(4.0, 12.0));
+    assert_eq!(shadow.max, Vec2::new(12.0, 15.0));
+    let panel = sheet.slices["panel"];
+    assert_eq!(panel.min, Vec2::new(16.0, 0.0));
+    assert_eq!(panel.max, Vec2::new(32.0, 16.0));
+
+    // sheets exported without slices still load
+    let sheet = parse_spritesheet(|json| {
+        json["meta"].as_object_mut().unwrap().remove("slices");
+    })
+    .unwrap();
+    assert!(sheet.slices.is_empty());
+}
+
+#[test]
+fn playback_solves_again_off_the_path() {
+    let mut app = playback_app(
+        "######\n\
+         #....#\n\
+         #S...E\n\
+         #....#\n\
+         ######\n",
+    );
+    app.insert(PlaybackLoops(Some(1))).count::<NoPath>();
+    app.tap(KeyCode::R);
+    play_out(&mut app, 1);
+    assert_eq!(app.ferris_pos(), UVec2::new(2, 2));
+
+    // pushed off the recorded path in the middle of playback
+    app.set_ferris(Ferris::at(UVec2::new(2, 1)));
+    app.arrive();
+    app.step(1);
+    let solution = app.get::<Solution>().unwrap();
+    assert_eq!(solution.expected, app.ferris());
+    assert_eq!(solution.steps.back().unwrap().pos, UVec2::new(5, 2));
+    // one step of the new path, four cells from the exit before
+    let d = (app.ferris_pos().as_i32() - IVec2::new(5, 2)).abs();
+    assert_eq!(d.x + d.y, 3);
+
+    play_out(&mut app, 10);
+    assert_eq!(app.ferris_pos(), UVec2::new(5, 2));
+    assert!(app.won());
+    assert_eq!(app.events::<NoPath>(), 0);
+}
+
+#[test]
+fn sprite_layers_are_drawn_in_order() {
+    use zorder::Layer;
+
+    let order = [
+        Layer::Map,
+        Layer::Grid,
+        Layer::PathHighlight,
+        Layer::StartMarker,
+        Layer::Breadcrumbs,
+        Layer::Dynamic,
+        Layer::Decoration,
+        Layer::Overlay,
+    ];
+    for pair in order.windows(2) {
+        assert!(pair[0].z() < pair[1].z(), "{:?} {:?}", pair[0], pair[1]);
+    }
+
+    // ferris is put on the dynamic layer
+    let app = TestApp::from_ascii(CORRIDOR);
+    assert_eq!(app.transform().translation.z, Layer::Dynamic.z());
+    let origin = MapOrigin::default();
+    let pos = app.ferris_pos();
+    let crumb = pos_to_translation(&origin, &pos, Layer::Breadcrumbs);
+    assert_eq!(crumb.truncate(), app.transform().translation.truncate());
+}
+
+// ferris' scale in each frame of a walk one cell to the right
+fn walk_scales(app: &mut TestApp) -> Vec<Vec3> {
+    app.tap(KeyCode::Right);
+    (0..40)
+        .map(|_| {
+            app.step(1);
+            app.transform().scale
+        })
+        .collect()
+}
+
+#[test]
+fn ferris_squashes_and_stretches_while_walking() {
+    let mut app = TestApp::from_ascii(CORRIDOR);
+    app.app
+        .add_system(move_ferris.system().after("character_input"));
+    let amplitude = SquashStretch::default().amplitude;
+    let scales = walk_scales(&mut app);
+
+    // squashed when setting off, stretched towards the end of the step
+    let first = scales[0];
+    assert!(first.x > 1.0 + amplitude * 0.9 && first.y < 1.0 - amplitude * 0.9);
+    // the tap itself was the first frame of the walk
+    let walk_frames = (16.0 / Tunables::default().step_size) as usize;
+    let last = scales[walk_frames - 3];
+    assert!(last.x < 1.0 - amplitude * 0.9 && last.y > 1.0 + amplitude * 0.9);
+    // back to normal on arrival
+    assert_eq!(*scales.last().unwrap(), Vec3::splat(1.0));
+
+    let mut app = TestApp::from_ascii(CORRIDOR);
+    app.insert(SquashStretch { amplitude: 0.0 });
+    app.app
+        .add_system(move_ferris.system().after("character_input"));
+    assert!(walk_scales(&mut app)
+        .iter()
+        .all(|scale| *scale == Vec3::splat(1.0)));
+}
+
+#[test]
+fn locked_door_and_win_shake_the_camera() {
+    let mut app = TestApp::from_ascii(
+        "######\n\
+         #SA.E#\n\
+         ######\n",
+    );
+    app.app
+        .add_system(
+            shake_on_bump
+                .system()
+                .label("shake")
+                .after("character_input"),
+        )
+        .add_system(
+            shake_on_win
+                .system()
+                .label("shake")
+                .after("character_input"),
+        )
+        .add_system(
+            ferris_lab::camera::remove_camera_shake
+                .system()
+                .label("remove_shake")
+                .after("shake"),
+        )
+        .add_system(
+            ferris_lab::camera::apply_camera_shake
+                .system()
+                .after("remove_shake"),
+        );
+    let camera = app
+        .app
+        .world
+        .spawn()
+        .insert(Transform::default())
+        .insert(CameraShake::default())
+        .id();
+    let shake = |app: &TestApp| {
+        let trauma = app.app.world.get::<CameraShake>(camera).unwrap().trauma;
+        let offset = app.app.world.get::<Transform>(camera).unwrap().translation;
+        (trauma, offset)
+    };
+    let dt = FRAME.as_secs_f32();
+    let decay = CameraShake::default().decay;
+    let max_offset = CameraShake::default().max_offset;
+
+    // plain walls don't shake
+    app.tap(KeyCode::Up);
+    assert_eq!(shake(&app), (0.0, Vec3::ZERO));
+
+    app.tap(KeyCode::Right);
+    let (trauma, offset) = shake(&app);
+    assert!((trauma - (0.3 - decay * dt)).abs() < 1e-5, "{}", trauma);
+    let strength = max_offset * trauma * trauma;
+    assert!(offset.x.abs() <= strength && offset.y.abs() <= strength);
+    assert_eq!(offset.z, 0.0);
+
+    // decays and the camera ends up where it was
+    app.step((0.3 / (decay * dt)) as usize + 1);
+    assert_eq!(shake(&app), (0.0, Vec3::ZERO));
+
+    app.set_ferris(Ferris::at(UVec2::new(3, 1)));
+    app.tap(KeyCode::Right);
+    assert!(app.won());
+    // GameWon may only be read in the next frame, detect_win isn't ordered before
+    app.step(1);
+    let (trauma, _) = shake(&app);
+    assert!(trauma > 0.6 - 3.0 * decay * dt, "{}", trauma);
+}
+
+#[test]
+fn solution_line_follows_the_remaining_steps() {
+    let mut app = playback_app(CORRIDOR);
+    app.with_assets().insert(PathRender::Line);
+    app.app
+        .add_system(path_render_input.system().label("path_render_input"))
+        .add_system(show_solution.system().after("solve_input"))
+        .add_system(
+            show_solution_line
+                .system()
+                .after("play_solution")
+                .after("path_render_input"),
+        );
+    let segments = |app: &mut TestApp| {
+        app.app
+            .world
+            .query_filtered::<(&Sprite, &Transform), With<PathLine>>()
+            .iter(&app.app.world)
+            .map(|(sprite, transform)| (sprite.size, transform.translation))
+            .collect::<Vec<_>>()
+    };
+
+    // the solution is there from the next frame on
+    app.tap(KeyCode::R);
+    app.step(1);
+    let line = segments(&mut app);
+    assert_eq!(line.len(), 5);
+    let size = Vec2::new(16.0 + PATH_LINE_WIDTH, PATH_LINE_WIDTH);
+    assert!(line.iter().all(|(segment, _)| *segment == size));
+    let crumbs = app
+        .app
+        .world
+        .query_filtered::<Entity, With<Breadcrumb>>()
+        .iter(&app.app.world)
+        .count();
+    assert_eq!(crumbs, 0);
+
+    // the segment ferris walked is gone
+    play_out(&mut app, 1);
+    let line = segments(&mut app);
+    assert_eq!(line.len(), 4);
+    let origin = MapOrigin::default();
+    let from = pos_to_translation(&origin, &app.ferris_pos(), zorder::Layer::Breadcrumbs);
+    let first = line
+        .iter()
+        .map(|(_, center)| center.x)
+        .fold(f32::INFINITY, f32::min);
+    assert_eq!(first, from.x + 8.0);
+
+    app.tap(KeyCode::P);
+    assert!(segments(&mut app).is_empty());
+}
+
+#[test]
+fn ferris_is_tinted_with_the_held_keys() {
+    let mut app = consuming_app(KEY_AND_DOOR);
+    app.resource_mut::<Rules>().key_consumption = KeyConsumption::PerDoor;
+    app.app
+        .add_system(color_scheme_input.system().label("color_scheme_input"))
+        .add_system(
+            tint_ferris_by_keys
+                .system()
+                .after("consume_tiles")
+                .after("color_scheme_input"),
+        );
+    let tint = |app: &TestApp| app.get::<TextureAtlasSprite>().unwrap().color;
+    app.step(1);
+    assert_eq!(tint(&app), Color::WHITE);
+
+    app.tap(KeyCode::Right);
+    app.tap(KeyCode::Right);
+    assert_eq!(app.ferris().keys, [1, 0, 0]);
+    let red = held_keys_tint(&[1, 0, 0], ColorScheme::Default);
+    assert_ne!(red, Color::WHITE);
+    assert_eq!(tint(&app), red);
+
+    // follows the color scheme without ferris changing
+    app.tap(KeyCode::H);
+    assert_eq!(
+        tint(&app),
+        held_keys_tint(&[1, 0, 0], ColorScheme::HighContrast)
+    );
+
+    // white again once the key is used up on the door
+    app.tap(KeyCode::Right);
+    app.tap(KeyCode::Right);
+    assert_eq!(app.ferris().keys, [0, 0, 0]);
+    assert_eq!(tint(&app), Color::WHITE);
+
+    // several keys blend into their average
+    let both = Vec4::from(held_keys_tint(&[1, 0, 1], ColorScheme::Default));
+    let average = (Vec4::from(key_color(0, ColorScheme::Default))
+        + Vec4::from(key_color(2, ColorScheme::Default)))
+        / 2.0;
+    assert!((both - Vec4::ONE.lerp(average, 0.6)).abs().max_element() < 1e-6);
+}
+
+#[test]
+fn chase_camera_snaps_to_a_spawned_target() {
+    let camera_after_spawn = |snap_on_spawn: bool| {
+        let mut app = TestApp::from_ascii(CORRIDOR);
+        app.app
+            .init_resource::<AspectMode>()
+            .add_system(update_camera.system());
+        let camera = app
+            .app
+            .world
+            .spawn()
+            .insert(Transform::default())
+            .insert(bevy::render::camera::Camera::default())
+            .insert(ChaseCamera {
+                snap_on_spawn,
+                ..Default::default()
+            })
+            .id();
+        app.step(1);
+        // spawned far off the camera
+        let target = app
+            .app
+            .world
+            .spawn()
+            .insert(Transform::from_xyz(500.0, -300.0, 0.0))
+            .insert(ChaseCameraTarget)
+            .id();
+        app.step(1);
+        let camera = *app.app.world.get::<Transform>(camera).unwrap();
+        let target = *app.app.world.get::<Transform>(target).unwrap();
+        (camera, target)
+    };
+
+    let (camera, target) = camera_after_spawn(true);
+    assert_eq!(camera.translation.truncate(), target.translation.truncate());
+    let scale = Tunables::default().camera_scale;
+    assert_eq!(camera.scale, Vec3::new(scale, scale, 1.0));
+
+    // otherwise the camera only starts to chase
+    let (camera, _) = camera_after_spawn(false);
+    assert_eq!(camera.translation.truncate(), Vec2::new(1.0, -1.0));
+}
+
+#[test]
+fn ferris_waits_for_the_tile_grid() {
+    let mut app = TestApp::from_ascii(CORRIDOR);
+    app.with_assets();
+    app.app.add_system(init_ferris.system());
+    let grid = app.grid().clone();
+    // the tiles arrive some frames after ferris
+    app.insert(TileGrid::default());
+    let ferris = app.app.world.spawn().insert(Ferris::default()).id();
+    app.step(3);
+    assert!(app.app.world.get::<EndPos>(ferris).is_none());
+    assert_eq!(
+        app.app.world.get::<Ferris>(ferris).unwrap().pos,
+        UVec2::ZERO
+    );
+
+    app.insert(grid);
+    app.step(1);
+    let start = UVec2::new(1, 1);
+    assert_eq!(app.app.world.get::<Ferris>(ferris).unwrap().pos, start);
+    assert_eq!(
+        app.app.world.get::<EndPos>(ferris).unwrap().0,
+        vec![UVec2::new(6, 1)]
+    );
+    assert_eq!(
+        app.app.world.get::<StartState>(ferris).unwrap().0.pos,
+        start
+    );
+    assert_eq!(
+        app.app.world.get::<Transform>(ferris).unwrap().translation,
+        pos_to_translation(&MapOrigin::default(), &start, zorder::Layer::Dynamic)
+    );
+}
+
+#[test]
+fn ascii_map_from_the_command_line() {
+    let path = std::env::temp_dir().join(format!("ferris_arg_{}.txt", std::process::id()));
+    std::fs::write(&path, CORRIDOR).unwrap();
+    let arg = Some(path.display().to_string());
+    let (source, grid) = map_source_from_arg(arg.clone());
+    assert_eq!(source, MapSource::Generated);
+    assert_eq!(grid, tilegrid_from_ascii(CORRIDOR).unwrap());
+
+    // broken maps fall back to the LDTK project
+    std::fs::write(&path, "").unwrap();
+    let (source, grid) = map_source_from_arg(arg);
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(source, MapSource::Ldtk);
+    assert!(grid.is_empty());
+
+    assert_eq!(
+        map_source_from_arg(Some("/nonexistent/map.txt".into())).0,
+        MapSource::Ldtk
+    );
+    assert_eq!(
+        map_source_from_arg(None),
+        (MapSource::Ldtk, TileGrid::default())
+    );
+}
+
+#[test]
+fn generated_map_is_spawned_at_the_origin() {
+    let mut app = TestApp::from_ascii(CORRIDOR);
+    app.with_assets();
+    let origin = Vec2::new(-120.0, -40.0);
+    app.insert(MapOrigin(origin)).insert(MapSource::Generated);
+    app.app
+        .add_asset::<Mesh>()
+        .add_startup_system(spawn_generated_map.system())
+        .add_system(map_position.system());
+    app.step(2);
+
+    let grid = app.grid().clone();
+    let tiles: HashMap<UVec2, u16> = app
+        .app
+        .world
+        .query::<(&Tile, &TilePos)>()
+        .iter(&app.app.world)
+        .map(|(tile, pos)| ((*pos).into(), tile.texture_index))
+        .collect();
+    assert_eq!(tiles.len(), grid.iter().count());
+    for (pos, texture_index) in grid.iter().filter(|(_, index)| *index != WALL_TILE) {
+        assert_eq!(tiles.get(&pos), Some(&texture_index));
+    }
+
+    // laid out bottom-up, so tile (0, 0) is at the origin where ferris is drawn too
+    let translations: Vec<Vec3> = app
+        .app
+        .world
+        .query_filtered::<&Transform, With<Map>>()
+        .iter(&app.app.world)
+        .map(|transform| transform.translation)
+        .collect();
+    assert_eq!(translations, vec![origin.extend(zorder::Layer::Map.z())]);
+}
+
+#[test]
+fn solving_again_cancels_playback() {
+    let mut app = playback_app(CORRIDOR);
+    app.tap(KeyCode::R);
+    play_out(&mut app, 1);
+    assert_eq!(app.ferris_pos(), UVec2::new(2, 1));
+
+    app.tap(KeyCode::R);
+    assert!(app.get::<Solution>().is_none());
+    play_out(&mut app, 5);
+    assert_eq!(app.ferris_pos(), UVec2::new(2, 1));
+    assert!(!app.won());
+
+    // without toggling R solves again and playback goes on
+    app.insert(SolveToggles(false));
+    app.tap(KeyCode::R);
+    play_out(&mut app, 1);
+    assert_eq!(app.ferris_pos(), UVec2::new(3, 1));
+    app.tap(KeyCode::R);
+    assert!(app.get::<Solution>().is_some());
+    play_out(&mut app, 5);
+    assert!(app.won());
+}
+
+#[test]
+fn changed_tiles_are_flushed_once_per_frame() {
+    let mut app = TestApp::from_ascii(CORRIDOR);
+    app.app
+        .add_system(light_radius_input.system().label("light_input"))
+        .add_system(
+            update_tile_colors
+                .system()
+                .label("update_tile_colors")
+                .after("light_input")
+                .after("character_input"),
+        );
+    let start = app.ferris_pos();
+    let cells: HashSet<(u16, UVec2)> = (1..4)
+        .map(|x| (LAYER_ID, start + UVec2::new(x, 1)))
+        .collect();
+    for (_, cell) in cells.iter() {
+        spawn_tile(&mut app, *cell, WALL_TILE, LAYER_ID);
+    }
+    app.step(1);
+    app.resource_mut::<PendingChunkUpdates>().0.clear();
+
+    // nothing changed, nothing to re-mesh
+    app.step(1);
+    assert!(app.resource::<PendingChunkUpdates>().0.is_empty());
+    app.tap(KeyCode::L);
+    assert_eq!(app.resource::<PendingChunkUpdates>().0, cells);
+
+    app.app
+        .add_system(flush_chunk_updates.system().after("update_tile_colors"));
+    app.tap(KeyCode::Right);
+    assert!(app.resource::<PendingChunkUpdates>().0.is_empty());
+}
+
+#[test]
+fn ferris_celebrates_at_the_exit_and_stops_moving() {
+    let mut app = TestApp::from_ascii(CORRIDOR);
+    app.app
+        .add_system(start_celebrating.system().after("character_input"));
+    app.set_ferris(Ferris::at(UVec2::new(5, 1)));
+    app.tap(KeyCode::Right);
+    assert!(app.won());
+    // GameWon may only be seen in the next frame
+    app.step(1);
+    assert!(app.get::<Celebrating>().is_some());
+    assert_eq!(app.get::<PlayAnimation>().unwrap().name, "win");
+
+    let exit = app.ferris_pos();
+    app.tap(KeyCode::Left);
+    assert_eq!(app.ferris_pos(), exit);
+    app.tap(KeyCode::R);
+    assert!(app.get::<Solution>().is_none());
+}
+
+#[test]
+fn debug_overlay_shows_the_cell_costs() {
+    assert_eq!(tile_cost(None), Some(1));
+    assert_eq!(tile_cost(Some(END_TILE)), Some(1));
+    assert_eq!(tile_cost(Some(door_tile(1))), Some(1));
+    assert_eq!(tile_cost(Some(WALL_TILE)), None);
+
+    let mut app = TestApp::from_ascii(KEY_AND_DOOR);
+    app.with_assets().insert(DebugOverlay::default());
+    app.app
+        .add_system(debug_overlay_input.system().label("debug_overlay_input"))
+        .add_system(show_debug_overlay.system().after("debug_overlay_input"));
+    let labels = |app: &mut TestApp| {
+        app.app
+            .world
+            .query_filtered::<&Text, With<DebugOverlayText>>()
+            .iter(&app.app.world)
+            .map(|text| text.sections[0].value.clone())
+            .collect::<Vec<String>>()
+    };
+    app.step(1);
+    assert!(labels(&mut app).is_empty());
+
+    app.tap(KeyCode::F3);
+    let labels_shown = labels(&mut app);
+    let size = app.grid().size();
+    assert_eq!(labels_shown.len(), (size.x * size.y) as usize);
+    let walls = app.grid().find_all(WALL_TILE).len();
+    assert_eq!(
+        labels_shown.iter().filter(|label| *label == "x").count(),
+        walls
+    );
+    assert!(labels_shown
+        .iter()
+        .all(|label| label == "x" || label == "1"));
+
+    app.tap(KeyCode::F3);
+    assert!(labels(&mut app).is_empty());
+}
+
+#[test]
+fn offscreen_breadcrumbs_are_culled() {
+    let mut app = TestApp::from_ascii(CORRIDOR);
+    add_window(&mut app);
+    app.app.add_system(cull_offscreen.system());
+    let spawn = |app: &mut TestApp, x: f32, y: f32, crumb: bool| {
+        let mut entity = app.app.world.spawn();
+        entity
+            .insert(Transform::from_xyz(x, y, 0.0))
+            .insert(Visible::default());
+        if crumb {
+            entity.insert(Breadcrumb {
+                lifetime: Timer::from_seconds(100.0, false),
+            });
+        }
+        entity.id()
+    };
+    let center = spawn(&mut app, 0.0, 0.0, true);
+    // within the tile of margin around the view
+    let margin = spawn(&mut app, WINDOW_SIZE.x * 0.5 + 10.0, 0.0, true);
+    let right = spawn(&mut app, WINDOW_SIZE.x * 0.5 + 30.0, 0.0, true);
+    let below = spawn(&mut app, 0.0, -WINDOW_SIZE.y * 0.5 - 20.0, true);
+    let other = spawn(&mut app, WINDOW_SIZE.x * 0.5 + 30.0, 0.0, false);
+    let visible =
+        |app: &TestApp, entity: Entity| app.app.world.get::<Visible>(entity).unwrap().is_visible;
+
+    app.step(1);
+    assert!(visible(&app, center));
+    assert!(visible(&app, margin));
+    assert!(!visible(&app, right));
+    assert!(!visible(&app, below));
+    // only breadcrumbs and overlays are culled
+    assert!(visible(&app, other));
+
+    // shown again once the camera gets there
+    let camera = app
+        .app
+        .world
+        .query_filtered::<Entity, With<ChaseCamera>>()
+        .iter(&app.app.world)
+        .next()
+        .unwrap();
+    app.app
+        .world
+        .get_mut::<Transform>(camera)
+        .unwrap()
+        .translation
+        .x = WINDOW_SIZE.x;
+    app.step(1);
+    assert!(!visible(&app, center));
+    assert!(visible(&app, right));
+}
+
+#[test]
+fn picked_up_key_flies_into_ferris() {
+    let mut app = TestApp::from_ascii(CORRIDOR);
+    app.with_assets().insert(KeyPopupDuration(0.4));
+    app.app
+        .add_system(spawn_key_popups.system().label("spawn_key_popups"))
+        .add_system(animate_key_popups.system().after("spawn_key_popups"));
+    let popup = |app: &mut TestApp| {
+        app.app
+            .world
+            .query_filtered::<(&Transform, &TextureAtlasSprite), With<KeyPopup>>()
+            .iter(&app.app.world)
+            .map(|(transform, sprite)| (*transform, sprite.index))
+            .next()
+    };
+    let origin = MapOrigin::default();
+    let from = pos_to_translation(&origin, &UVec2::new(5, 1), zorder::Layer::Overlay);
+    app.send(KeyPickedUp {
+        pos: UVec2::new(5, 1),
+        color_id: 2,
+    });
+    app.step(1);
+    let (transform, index) = popup(&mut app).unwrap();
+    assert_eq!(transform.translation, from);
+    assert_eq!(index, key_tile(2) as u32);
+
+    // pops up first
+    app.step(5);
+    let (transform, _) = popup(&mut app).unwrap();
+    assert!(transform.scale.x > 1.2, "{:?}", transform.scale);
+
+    // then shrinks on the way into ferris
+    app.step(16);
+    let (transform, _) = popup(&mut app).unwrap();
+    let ferris = app.transform().translation;
+    assert!(transform.scale.x < 1.0, "{:?}", transform.scale);
+    assert!((transform.translation.x - ferris.x).abs() < (from.x - ferris.x).abs() * 0.4);
+    assert_eq!(transform.translation.z, from.z);
+
+    app.step(5);
+    assert!(popup(&mut app).is_none());
+}
+
+#[test]
+fn instant_movement_snaps_to_the_cell() {
+    let mut app = playback_app(CORRIDOR);
+    app.app
+        .add_system(movement_style_input.system().label("movement_style_input"))
+        .add_system(
+            move_ferris
+                .system()
+                .after("movement_style_input")
+                .after("play_solution"),
+        );
+    app.tap(KeyCode::I);
+    assert_eq!(*app.resource::<MovementStyle>(), MovementStyle::Instant);
+
+    let origin = MapOrigin::default();
+    app.tap(KeyCode::Right);
+    let target = pos_to_translation(&origin, &app.ferris_pos(), zorder::Layer::Dynamic);
+    assert_eq!(app.transform().translation, target);
+    assert_eq!(app.transform().scale, Vec3::splat(1.0));
+    assert_eq!(*app.get::<Facing>().unwrap(), Facing::Right);
+
+    // playback is paced by time instead of the walk
+    app.tap(KeyCode::R);
+    let frames = (1..1000)
+        .find(|_| {
+            app.step(1);
+            app.won()
+        })
+        .unwrap();
+    let expected = 4.0 * 0.15 / FRAME.as_secs_f32();
+    assert!((frames as f32 - expected).abs() <= 12.0, "{}", frames);
+    let exit = pos_to_translation(&origin, &app.ferris_pos(), zorder::Layer::Dynamic);
+    assert_eq!(app.transform().translation, exit);
+}
+
+#[test]
+fn path_highlight_stays_until_the_exit() {
+    let mut app = playback_app(CORRIDOR);
+    app.with_assets().insert(PlaybackLoops(Some(1)));
+    app.app
+        .add_system(show_path_highlight.system().after("play_solution"));
+    let grid = app.grid().clone();
+    let highlighted = |app: &mut TestApp| {
+        let origin = MapOrigin::default();
+        app.app
+            .world
+            .query_filtered::<&Transform, With<PathHighlight>>()
+            .iter(&app.app.world)
+            .map(|transform| {
+                translation_to_pos(&origin, &grid, transform.translation.truncate()).unwrap()
+            })
+            .collect::<HashSet<UVec2>>()
+    };
+    let path: HashSet<UVec2> = (1..7).map(|x| UVec2::new(x, 1)).collect();
+
+    app.tap(KeyCode::R);
+    app.step(1);
+    assert_eq!(highlighted(&mut app), path);
+    // unlike the breadcrumbs it doesn't follow the playback
+    play_out(&mut app, 3);
+    assert_eq!(highlighted(&mut app), path);
+
+    play_out(&mut app, 3);
+    assert!(app.won());
+    app.step(1);
+    assert!(highlighted(&mut app).is_empty());
+}
+
+#[test]
+fn camera_centers_maps_smaller_than_the_view() {
+    let camera_for = |ascii: &str| {
+        let mut app = TestApp::from_ascii(ascii);
+        add_window(&mut app);
+        app.app
+            .init_resource::<AspectMode>()
+            .add_system(update_camera.system());
+        let ferris = app.ferris;
+        app.app.world.entity_mut(ferris).insert(ChaseCameraTarget);
+        let camera = app
+            .app
+            .world
+            .spawn()
+            .insert(Transform::default())
+            .insert(bevy::render::camera::Camera::default())
+            .insert(ChaseCamera::default())
+            .id();
+        app.step(1);
+        let camera = app.app.world.get::<Transform>(camera).unwrap().translation;
+        let map_center = app.grid().size().as_f32() * TILE_SIZE * 0.5;
+        (
+            camera.truncate(),
+            map_center,
+            app.transform().translation.truncate(),
+        )
+    };
+
+    let (camera, map_center, _) = camera_for(CORRIDOR);
+    assert_eq!(camera, map_center);
+
+    // a long corridor only fits vertically
+    let wide = format!(
+        "{}\n#S{}E\n{}\n",
+        "#".repeat(80),
+        ".".repeat(77),
+        "#".repeat(80)
+    );
+    let (camera, map_center, ferris) = camera_for(&wide);
+    assert_eq!(camera.y, map_center.y);
+    assert_eq!(camera.x, ferris.x);
+}
+
+#[test]
+fn animation_frames_last_their_spritesheet_duration() {
+    let mut app = TestApp::from_ascii(CORRIDOR);
+    app.with_assets();
+    app.app.add_system(animate_character_system.system());
+    let sheet = spritesheet::Spritesheet {
+        image: String::new(),
+        ranges: std::iter::once(("blink".to_string(), 0..2)).collect(),
+        // in ms, the second frame is held four times as long
+        durations: vec![48, 192],
+        one_shot: HashSet::new(),
+        slices: HashMap::new(),
+    };
+    let handle = app
+        .resource_mut::<Assets<spritesheet::Spritesheet>>()
+        .add(sheet);
+    let ferris = app.ferris;
+    app.app
+        .world
+        .entity_mut(ferris)
+        .insert(handle)
+        .insert(PlayAnimation::new("blink"));
+
+    let mut frames = [0usize; 2];
+    for _ in 0..300 {
+        app.step(1);
+        frames[app.get::<TextureAtlasSprite>().unwrap().index as usize] += 1;
+    }
+    let ratio = frames[1] as f32 / frames[0] as f32;
+    assert!((3.0..5.0).contains(&ratio), "{:?}", frames);
+    let duration = app.get::<Timer>().unwrap().duration();
+    assert!([48, 192].contains(&duration.as_millis()), "{:?}", duration);
+}
+
+#[test]
+fn outline_follows_ferris_and_goes_with_him() {
+    let mut app = TestApp::from_ascii(CORRIDOR);
+    app.app
+        .add_system(add_outlines.system())
+        .add_system(sync_outlines.system())
+        .add_system(remove_outlines.system());
+    let ferris = app.ferris;
+    app.app
+        .world
+        .entity_mut(ferris)
+        .insert(Handle::<TextureAtlas>::default())
+        .insert(Outlined);
+    let outlines = |app: &mut TestApp| {
+        app.app
+            .world
+            .query_filtered::<(&Parent, &TextureAtlasSprite, &Transform), With<Outline>>()
+            .iter(&app.app.world)
+            .map(|(parent, sprite, transform)| (parent.0, sprite.index, *transform))
+            .collect::<Vec<_>>()
+    };
+
+    app.step(1);
+    let outline = outlines(&mut app);
+    assert_eq!(outline.len(), 1);
+    let (parent, index, transform) = outline[0];
+    assert_eq!(parent, ferris);
+    assert_eq!(index, 0);
+    // drawn bigger and behind ferris
+    assert_eq!(transform.scale, Vec3::splat(OUTLINE_SCALE));
+    assert!(transform.translation.z < 0.0);
+
+    app.app
+        .world
+        .get_mut::<TextureAtlasSprite>(ferris)
+        .unwrap()
+        .index = 5;
+    app.step(1);
+    assert_eq!(outlines(&mut app)[0].1, 5);
+
+    app.app.world.entity_mut(ferris).remove::<Outlined>();
+    app.step(1);
+    assert!(outlines(&mut app).is_empty());
+}
+
+#[test]
+fn reveal_all_ignores_the_light_radius() {
+    let mut app = TestApp::from_ascii(CORRIDOR);
+    app.insert(LightRadius(Some(4.0)));
+    app.app
+        .add_system(reveal_all_input.system().label("reveal_all_input"))
+        .add_system(update_tile_colors.system().after("reveal_all_input"));
+    let start = app.ferris_pos();
+    let far = spawn_tile(&mut app, start + UVec2::new(4, 1), WALL_TILE, LAYER_ID);
+    let tint = tile_tint(WALL_TILE, *app.resource::<ColorScheme>());
+    app.step(1);
+    assert_ne!(tile_color(&app, far), tint);
+
+    app.tap(KeyCode::F4);
+    assert!(app.resource::<RevealAll>().0);
+    assert_eq!(tile_color(&app, far), tint);
+    // the radius is kept for when the cheat is turned off again
+    assert_eq!(app.resource::<LightRadius>().0, Some(4.0));
+
+    app.tap(KeyCode::F4);
+    assert_ne!(tile_color(&app, far), tint);
+}
+
+#[test]
+fn steps_kick_up_dust_behind_ferris() {
+    let mut app = TestApp::from_ascii(CORRIDOR);
+    app.with_assets();
+    app.app
+        .add_system(
+            spawn_dust
+                .system()
+                .label("spawn_dust")
+                .after("character_input"),
+        )
+        .add_system(update_dust.system().after("spawn_dust"));
+    let dust = |app: &mut TestApp| {
+        app.app
+            .world
+            .query::<(&Transform, &DustParticle)>()
+            .iter(&app.app.world)
+            .map(|(transform, particle)| (transform.translation, particle.velocity))
+            .collect::<Vec<_>>()
+    };
+    // the first frame only sets where ferris is
+    app.step(1);
+    let from = pos_to_translation(
+        &MapOrigin::default(),
+        &app.ferris_pos(),
+        zorder::Layer::Breadcrumbs,
+    );
+    app.tap(KeyCode::Right);
+    let particles = dust(&mut app);
+    assert_eq!(particles.len(), DUST_PER_STEP);
+    // at the back edge of the cell ferris left, drifting further back
+    assert!(particles
+        .iter()
+        .all(|(translation, velocity)| translation.x < from.x && velocity.x < 0.0));
+    assert_eq!(particle_colors(&mut app).len(), DUST_PER_STEP);
+
+    // gone after their lifetime
+    app.step((DUST_LIFETIME / FRAME.as_secs_f32()) as usize + 2);
+    assert!(dust(&mut app).is_empty());
+
+    // jumps are no steps
+    app.set_ferris(Ferris::at(UVec2::new(5, 1)));
+    app.step(1);
+    assert!(dust(&mut app).is_empty());
+}
+
+#[test]
+fn tooltip_describes_the_hovered_cell() {
+    let mut app = TestApp::from_ascii(KEY_AND_DOOR);
+    app.with_assets().insert(DebugOverlay(false));
+    add_window(&mut app);
+    app.app
+        .add_startup_system(spawn_tile_tooltip.system())
+        .add_system(update_tile_tooltip.system());
+    let tooltip = |app: &mut TestApp| {
+        app.app
+            .world
+            .query_filtered::<(&Text, &Visible), With<TileTooltip>>()
+            .iter(&app.app.world)
+            .map(|(text, visible)| (text.sections[0].value.clone(), visible.is_visible))
+            .next()
+            .unwrap()
+    };
+
+    // only with the debug overlay
+    hover(&mut app, Vec2::new(3.5, 1.5) * TILE_SIZE);
+    assert!(!tooltip(&mut app).1);
+
+    app.insert(DebugOverlay(true));
+    app.step(1);
+    let (text, visible) = tooltip(&mut app);
+    assert!(visible);
+    assert!(
+        text.ends_with(&format!("{} key (color 0)", key_tile(0))),
+        "{}",
+        text
+    );
+
+    hover(&mut app, Vec2::new(5.5, 1.5) * TILE_SIZE);
+    assert!(tooltip(&mut app).0.ends_with("door (color 0)"));
+    hover(&mut app, Vec2::new(2.5, 1.5) * TILE_SIZE);
+    assert!(tooltip(&mut app).0.ends_with("- floor"));
+
+    // nothing to describe off the map
+    hover(&mut app, Vec2::new(-3.0, 1.5) * TILE_SIZE);
+    assert!(!tooltip(&mut app).1);
+
+    assert_eq!(tile_behavior(Some(WALL_TILE)), "wall");
+    assert_eq!(tile_behavior(Some(END_TILE)), "exit");
+}
+
+#[test]
+fn camera_eases_into_the_level_zoom() {
+    let mut app = TestApp::from_ascii(CORRIDOR);
+    app.app
+        .init_resource::<AspectMode>()
+        .add_system(update_camera.system());
+    let ferris = app.ferris;
+    app.app.world.entity_mut(ferris).insert(ChaseCameraTarget);
+    let camera = app
+        .app
+        .world
+        .spawn()
+        .insert(Transform::default())
+        .insert(bevy::render::camera::Camera::default())
+        .insert(ChaseCamera::default())
+        .id();
+    let scale = |app: &TestApp| app.app.world.get::<Transform>(camera).unwrap().scale;
+    // snapped to the default zoom on spawn
+    app.step(1);
+    let default_scale = Tunables::default().camera_scale;
+    assert_eq!(scale(&app), Vec3::new(default_scale, default_scale, 1.0));
+
+    app.resource_mut::<LevelMeta>().zoom = Some(1.0);
+    app.step(1);
+    let eased = scale(&app);
+    assert!(eased.x > default_scale && eased.x < 1.0, "{:?}", eased);
+    assert_eq!(eased.x, eased.y);
+    assert_eq!(eased.z, 1.0);
+
+    app.step(120);
+    assert!((scale(&app).x - 1.0).abs() < 1e-3, "{:?}", scale(&app));
+
+    // levels without a zoom go back to the default
+    app.resource_mut::<LevelMeta>().zoom = None;
+    app.step(120);
+    assert!((scale(&app).x - default_scale).abs() < 1e-3);
+}
+
+#[test]
+fn frame_all_keeps_every_target_in_view() {
+    let view = WINDOW_SIZE;
+    assert_eq!(frame_targets(&[], view, 0.5), None);
+    // close together: no closer than the level zoom
+    assert_eq!(
+        frame_targets(&[Vec2::ZERO, Vec2::new(32.0, 0.0)], view, 0.5),
+        Some((Vec2::new(16.0, 0.0), 0.5))
+    );
+    // a tile of margin around both
+    let (center, scale) = frame_targets(&[Vec2::ZERO, Vec2::new(600.0, 0.0)], view, 0.5).unwrap();
+    assert_eq!(center, Vec2::new(300.0, 0.0));
+    assert!((scale - (600.0 + 2.0 * TILE_SIZE) / view.x).abs() < 1e-6);
+    // zooming out is capped
+    let (_, scale) = frame_targets(&[Vec2::ZERO, Vec2::new(0.0, 5000.0)], view, 0.5).unwrap();
+    assert_eq!(scale, MAX_FRAME_ALL_SCALE);
+
+    let mut app = TestApp::from_ascii(CORRIDOR);
+    add_window(&mut app);
+    app.insert(CameraMode::FrameAll);
+    app.app
+        .init_resource::<AspectMode>()
+        .add_system(update_camera.system());
+    let ferris = app.ferris;
+    app.app.world.entity_mut(ferris).insert(ChaseCameraTarget);
+    let other = Vec3::new(640.0, 200.0, 0.0);
+    app.app
+        .world
+        .spawn()
+        .insert(Transform::from_translation(other))
+        .insert(ChaseCameraTarget);
+    let camera = app
+        .app
+        .world
+        .spawn()
+        .insert(Transform::default())
+        .insert(bevy::render::camera::Camera::default())
+        .insert(ChaseCamera::default())
+        .id();
+    app.step(200);
+
+    let camera = *app.app.world.get::<Transform>(camera).unwrap();
+    let targets = [app.transform().translation.truncate(), other.truncate()];
+    let level_scale = Tunables::default().camera_scale;
+    let (center, scale) = frame_targets(&targets, view, level_scale).unwrap();
+    assert!((camera.translation.truncate() - center).length() < 0.1);
+    assert!((camera.scale.x - scale).abs() < 1e-3);
+    // both are in view
+    let half_view = view * 0.5 * camera.scale.x;
+    for target in targets.iter() {
+        let offset = (*target - camera.translation.truncate()).abs();
+        assert!(offset.x < half_view.x && offset.y < half_view.y);
+    }
+}
+
+#[test]
+fn fast_moves_leave_a_fading_trail() {
+    let mut app = TestApp::from_ascii(CORRIDOR);
+    app.app
+        .add_system(motion_trail_input.system().label("motion_trail_input"))
+        .add_system(spawn_trail_ghosts.system().after("motion_trail_input"))
+        .add_system(fade_trail_ghosts.system());
+    let ferris = app.ferris;
+    app.app
+        .world
+        .entity_mut(ferris)
+        .insert(Handle::<TextureAtlas>::default());
+    let ghosts = |app: &mut TestApp| {
+        app.app
+            .world
+            .query_filtered::<(&Transform, &TextureAtlasSprite), With<TrailGhost>>()
+            .iter(&app.app.world)
+            .map(|(transform, sprite)| (transform.translation, sprite.color.a()))
+            .collect::<Vec<_>>()
+    };
+    // moves ferris by this many pixels per frame
+    let slide = |app: &mut TestApp, pixels: f32, frames: usize| {
+        for _ in 0..frames {
+            app.app
+                .world
+                .get_mut::<Transform>(ferris)
+                .unwrap()
+                .translation
+                .x += pixels;
+            app.step(1);
+        }
+    };
+    let fast = TRAIL_MIN_SPEED * FRAME.as_secs_f32() * 2.0;
+
+    // off by default
+    slide(&mut app, fast, 10);
+    assert!(ghosts(&mut app).is_empty());
+
+    app.tap(KeyCode::F10);
+    slide(&mut app, fast * 0.25, 10);
+    assert!(ghosts(&mut app).is_empty());
+    slide(&mut app, fast, 10);
+    let trail = ghosts(&mut app);
+    assert!(!trail.is_empty() && trail.len() <= MAX_TRAIL_GHOSTS);
+    let x = app.transform().translation.x;
+    assert!(trail.iter().all(|(translation, alpha)| {
+        translation.x <= x && translation.z == zorder::Layer::Breadcrumbs.z() && *alpha <= 0.5
+    }));
+
+    // fades out once ferris stands still
+    slide(
+        &mut app,
+        0.0,
+        (TRAIL_LIFETIME / FRAME.as_secs_f32()) as usize + 2,
+    );
+    assert!(ghosts(&mut app).is_empty());
+}
+
+#[test]
+fn deadzone_camera_moves_just_enough() {
+    let deadzone = Deadzone::square(64.0);
+    assert_eq!(deadzone.follow(Vec2::new(30.0, -50.0), 1.0), Vec2::ZERO);
+    assert_eq!(
+        deadzone.follow(Vec2::new(100.0, -10.0), 1.0),
+        Vec2::new(36.0, 0.0)
+    );
+    // the box is in screen pixels
+    assert_eq!(
+        deadzone.follow(Vec2::new(100.0, -40.0), 0.5),
+        Vec2::new(68.0, -8.0)
+    );
+
+    let mut app = TestApp::from_ascii(CORRIDOR);
+    app.resource_mut::<Tunables>().camera_lead = 0.0;
+    app.app
+        .init_resource::<AspectMode>()
+        .add_system(deadzone_input.system())
+        .add_system(update_camera.system());
+    let ferris = app.ferris;
+    app.app.world.entity_mut(ferris).insert(ChaseCameraTarget);
+    let camera = app
+        .app
+        .world
+        .spawn()
+        .insert(Transform::default())
+        .insert(bevy::render::camera::Camera::default())
+        .insert(ChaseCamera::default())
+        .id();
+    let camera_x = |app: &TestApp| {
+        app.app
+            .world
+            .get::<Transform>(camera)
+            .unwrap()
+            .translation
+            .x
+    };
+    app.tap(KeyCode::B);
+    assert!(app.app.world.get::<Deadzone>(camera).is_some());
+    let start = camera_x(&app);
+
+    let move_ferris_by = |app: &mut TestApp, x: f32| {
+        app.app
+            .world
+            .get_mut::<Transform>(ferris)
+            .unwrap()
+            .translation
+            .x += x;
+        app.step(1);
+    };
+    // inside the box the camera stays put
+    let half_box = Tunables::default().chase_max_distance * Tunables::default().camera_scale;
+    move_ferris_by(&mut app, half_box - 1.0);
+    assert_eq!(camera_x(&app), start);
+    // then it keeps ferris on the border of the box
+    move_ferris_by(&mut app, 10.0);
+    assert_eq!(camera_x(&app), start + 9.0);
+
+    app.tap(KeyCode::B);
+    assert!(app.app.world.get::<Deadzone>(camera).is_none());
+}
+
+#[test]
+fn start_cell_is_marked_below_ferris() {
+    let mut app = TestApp::from_ascii(CORRIDOR);
+    app.with_assets();
+    app.app.add_system(init_ferris.system());
+    let markers = |app: &mut TestApp| {
+        app.app
+            .world
+            .query_filtered::<&Transform, With<StartMarker>>()
+            .iter(&app.app.world)
+            .map(|transform| transform.translation)
+            .collect::<Vec<Vec3>>()
+    };
+    app.app.world.spawn().insert(Ferris::default());
+    app.step(1);
+    let expected = pos_to_translation(
+        &MapOrigin::default(),
+        &UVec2::new(1, 1),
+        zorder::Layer::StartMarker,
+    );
+    assert_eq!(markers(&mut app), vec![expected]);
+    assert!(expected.z < zorder::Layer::Dynamic.z());
+
+    // the next level replaces the marker
+    let mut grid = app.grid().clone();
+    grid.set(UVec2::new(1, 1), None);
+    grid.set(UVec2::new(3, 1), Some(START_TILE));
+    app.insert(grid);
+    app.app.world.spawn().insert(Ferris::default());
+    app.step(1);
+    let expected = pos_to_translation(
+        &MapOrigin::default(),
+        &UVec2::new(3, 1),
+        zorder::Layer::StartMarker,
+    );
+    assert_eq!(markers(&mut app), vec![expected]);
+}
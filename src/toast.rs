@@ -0,0 +1,47 @@
+use bevy::prelude::*;
+
+// short lived on-screen message, despawned by expire_toasts
+pub struct Toast {
+    pub timer: Timer,
+}
+
+pub fn spawn_toast(commands: &mut Commands, font: Handle<Font>, message: &str, color: Color) {
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    bottom: Val::Px(20.0),
+                    left: Val::Px(20.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text::with_section(
+                message,
+                TextStyle {
+                    font,
+                    font_size: 24.0,
+                    color,
+                },
+                Default::default(),
+            ),
+            ..Default::default()
+        })
+        .insert(Toast {
+            timer: Timer::from_seconds(2.0, false),
+        });
+}
+
+pub fn expire_toasts(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut Toast)>,
+) {
+    for (entity, mut toast) in query.iter_mut() {
+        toast.timer.tick(time.delta());
+        if toast.timer.finished() {
+            commands.entity(entity).despawn();
+        }
+    }
+}
@@ -0,0 +1,33 @@
+// Draw order of the different kinds of sprites. The tile map layers are stacked above
+// Map, so keep some space between the values.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Layer {
+    Map,
+    // debug grid overlay
+    Grid,
+    // cells of the last solution
+    PathHighlight,
+    // where ferris started the level
+    StartMarker,
+    Breadcrumbs,
+    // ferris and everything else moving around
+    Dynamic,
+    // cosmetic tiles that cover ferris, faded out around him
+    Decoration,
+    Overlay,
+}
+
+impl Layer {
+    pub fn z(self) -> f32 {
+        match self {
+            Layer::Map => 0.0,
+            Layer::Grid => 5.0,
+            Layer::PathHighlight => 7.0,
+            Layer::StartMarker => 8.0,
+            Layer::Breadcrumbs => 10.0,
+            Layer::Dynamic => 20.0,
+            Layer::Decoration => 50.0,
+            Layer::Overlay => 100.0,
+        }
+    }
+}